@@ -0,0 +1,399 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::crash::*;
+use super::faction::*;
+use super::guidance::*;
+use super::gun::*;
+use super::loadout::*;
+use super::player::*;
+use super::sensor::*;
+use super::weapons::*;
+use super::wingman::Wingman;
+use super::Drone;
+
+pub const DRONE_SPEED: f32 = 190.;
+pub const DRONE_PATROL_TURN_RATE: f32 = 0.25 * YAW_SPEED;
+pub const GUN_FIRE_ANGLE: f32 = 0.05;
+pub const DRONE_MIN_ALTITUDE: f32 = 150.;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Difficulty {
+    /// Seconds between first detecting the player and engaging.
+    pub reaction_time: f32,
+    /// Seconds the player must stay inside the seeker before a missile is released.
+    pub lock_time: f32,
+    /// Maximum aiming error in radians applied to pursuit and gun fire.
+    pub aim_error: f32,
+    /// Maximum turn rate in radians per second.
+    pub turn_rate: f32,
+    pub detection_range: f32,
+    pub missile_range: f32,
+}
+
+impl Difficulty {
+    pub fn easy() -> Self {
+        Difficulty {
+            reaction_time: 3.,
+            lock_time: 4.,
+            aim_error: 0.08,
+            turn_rate: 0.6,
+            detection_range: 800.,
+            missile_range: 600.,
+        }
+    }
+
+    pub fn normal() -> Self {
+        Difficulty {
+            reaction_time: 1.5,
+            lock_time: 2.5,
+            aim_error: 0.04,
+            turn_rate: 0.9,
+            detection_range: 1200.,
+            missile_range: 900.,
+        }
+    }
+
+    pub fn hard() -> Self {
+        Difficulty {
+            reaction_time: 0.5,
+            lock_time: 1.5,
+            aim_error: 0.015,
+            turn_rate: 1.2,
+            detection_range: 1600.,
+            missile_range: 1200.,
+        }
+    }
+}
+
+impl Default for Difficulty {
+    fn default() -> Self {
+        Difficulty::normal()
+    }
+}
+
+/// Difficulty preset chosen in the menu and stored in the save file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DifficultyLevel {
+    Easy,
+    Normal,
+    Hard,
+}
+
+impl DifficultyLevel {
+    pub fn difficulty(&self) -> Difficulty {
+        match self {
+            DifficultyLevel::Easy => Difficulty::easy(),
+            DifficultyLevel::Normal => Difficulty::normal(),
+            DifficultyLevel::Hard => Difficulty::hard(),
+        }
+    }
+
+    pub fn next(&self) -> DifficultyLevel {
+        match self {
+            DifficultyLevel::Easy => DifficultyLevel::Normal,
+            DifficultyLevel::Normal => DifficultyLevel::Hard,
+            DifficultyLevel::Hard => DifficultyLevel::Easy,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DifficultyLevel::Easy => "EASY",
+            DifficultyLevel::Normal => "NORMAL",
+            DifficultyLevel::Hard => "HARD",
+        }
+    }
+}
+
+impl Default for DifficultyLevel {
+    fn default() -> Self {
+        DifficultyLevel::Normal
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DroneState {
+    Patrol,
+    Reacting,
+    Engage,
+}
+
+#[derive(Component)]
+pub struct DroneAi {
+    pub state: DroneState,
+    pub target: Option<Entity>,
+    pub reaction_timer: f32,
+    pub lock_progress: f32,
+    aim_offset: Vec3,
+}
+
+impl Default for DroneAi {
+    fn default() -> Self {
+        DroneAi {
+            state: DroneState::Patrol,
+            target: None,
+            reaction_timer: 0.,
+            lock_progress: 0.,
+            aim_offset: Vec3::ZERO,
+        }
+    }
+}
+
+impl DroneAi {
+    /// Steers onto `target`, building up a missile lock, and returns the direction to fly
+    /// and the weapons to fire this frame.
+    pub fn engage(
+        &mut self,
+        transform: &Transform,
+        loadout: &Loadout,
+        target: &Contact,
+        difficulty: &Difficulty,
+        delta_seconds: f32,
+    ) -> (Vec3, Vec<WeaponType>) {
+        let seeker = MissileParams::default().seeker;
+        let position = transform.translation;
+        let forward = transform.rotation * Vec3::X;
+        let line_of_sight = target.position - position;
+        let distance = line_of_sight.length();
+        let mut weapons = Vec::new();
+
+        let aim = lead_point(position, DRONE_SPEED, target.position, target.velocity) - position;
+        let mut desired_direction = (aim.normalize_or_zero() + self.aim_offset).normalize();
+        if position.y < DRONE_MIN_ALTITUDE {
+            desired_direction.y = desired_direction.y.max(0.3);
+        }
+
+        if seeker_can_acquire(&seeker, forward, line_of_sight)
+            && distance < difficulty.missile_range
+        {
+            self.lock_progress += delta_seconds;
+        } else {
+            self.lock_progress = 0.;
+        }
+
+        if self.lock_progress >= difficulty.lock_time && loadout.count(WeaponType::Missile) > 0 {
+            weapons.push(WeaponType::Missile);
+            self.lock_progress = 0.;
+            self.aim_offset = random_aim_offset(difficulty.aim_error);
+        }
+
+        let gun_aim = gunsight_aim_point(
+            position,
+            forward * DRONE_SPEED,
+            target.position,
+            target.velocity,
+            MUZZLE_SPEED,
+        );
+        if let Some(gun_aim) = gun_aim {
+            let off_boresight = forward.angle_between(gun_aim - position);
+            if distance < GUN_RANGE && off_boresight < GUN_FIRE_ANGLE + difficulty.aim_error {
+                weapons.push(WeaponType::Gun);
+            }
+        }
+
+        (desired_direction.normalize(), weapons)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Contact {
+    pub entity: Entity,
+    pub position: Vec3,
+    pub velocity: Vec3,
+}
+
+pub fn contact_velocity(
+    rb_vel: Option<&RigidBodyVelocityComponent>,
+    target_velocity: Option<&TargetVelocity>,
+) -> Vec3 {
+    rb_vel
+        .map(|rb_vel| Vec3::from(rb_vel.linvel))
+        .or_else(|| target_velocity.map(|target_velocity| target_velocity.linvel))
+        .unwrap_or(Vec3::ZERO)
+}
+
+/// Steering request written by the AI and flown by `drone_movement`.
+#[derive(Default, Component)]
+pub struct DroneControl {
+    pub desired_direction: Option<Vec3>,
+    pub desired_speed: Option<f32>,
+}
+
+/// Point to fly at so that a pursuer at `speed` meets a target moving at `target_velocity`.
+pub fn lead_point(
+    position: Vec3,
+    speed: f32,
+    target_position: Vec3,
+    target_velocity: Vec3,
+) -> Vec3 {
+    let time_to_target = (target_position - position).length() / speed.max(1.);
+    target_position + target_velocity * time_to_target
+}
+
+/// Rotates `rotation` towards `direction`, turning at most `max_angle` radians.
+pub fn turn_towards(rotation: Quat, direction: Vec3, max_angle: f32) -> Quat {
+    let forward = rotation * Vec3::X;
+    let direction = direction.normalize_or_zero();
+    if direction == Vec3::ZERO {
+        return rotation;
+    }
+
+    let angle = forward.angle_between(direction);
+    if angle <= f32::EPSILON {
+        return rotation;
+    }
+    let full_turn = Quat::from_rotation_arc(forward, direction);
+    let turn = Quat::IDENTITY.slerp(full_turn, (max_angle / angle).min(1.));
+    (turn * rotation).normalize()
+}
+
+fn random_aim_offset(aim_error: f32) -> Vec3 {
+    Vec3::new(
+        rand::random::<f32>() - 0.5,
+        rand::random::<f32>() - 0.5,
+        rand::random::<f32>() - 0.5,
+    ) * 2.
+        * aim_error
+}
+
+pub fn drone_ai(
+    difficulty: Res<Difficulty>,
+    mut fire_events: EventWriter<FireWeapon>,
+    mut drone_query: Query<
+        (
+            Entity,
+            &Transform,
+            &Faction,
+            &Loadout,
+            &SensorContacts,
+            &mut DroneAi,
+            &mut DroneControl,
+        ),
+        (With<Drone>, Without<Wingman>),
+    >,
+    contact_query: Query<&Faction, (Without<Crashed>, Without<Invulnerable>)>,
+    time: Res<Time>,
+) {
+    for (drone_entity, transform, faction, loadout, contacts, mut ai, mut control) in
+        drone_query.iter_mut()
+    {
+        let position = transform.translation;
+
+        let hostile = |entity: Entity| {
+            entity != drone_entity
+                && contact_query.get(entity).map_or(false, |contact_faction| {
+                    is_hostile(*faction, *contact_faction)
+                })
+        };
+        let distance_to = |track: &SensorTrack| (track.position - position).length();
+
+        let target = ai
+            .target
+            .and_then(|target| contacts.track(target).map(|track| (target, track)))
+            .filter(|(entity, _)| hostile(*entity))
+            .or_else(|| {
+                contacts
+                    .detected()
+                    .filter(|(entity, _)| hostile(*entity))
+                    .min_by(|(_, a), (_, b)| {
+                        distance_to(a)
+                            .partial_cmp(&distance_to(b))
+                            .unwrap_or(std::cmp::Ordering::Equal)
+                    })
+            })
+            .filter(|(_, track)| distance_to(track) < difficulty.detection_range);
+
+        let (target_entity, track) = match target {
+            Some(target) => target,
+            None => {
+                *ai = DroneAi::default();
+                control.desired_direction = None;
+                continue;
+            }
+        };
+        ai.target = Some(target_entity);
+
+        match ai.state {
+            DroneState::Patrol => {
+                ai.state = DroneState::Reacting;
+                ai.reaction_timer = difficulty.reaction_time;
+            }
+            DroneState::Reacting => {
+                ai.reaction_timer -= time.delta_seconds();
+                if ai.reaction_timer <= 0. {
+                    ai.state = DroneState::Engage;
+                    ai.aim_offset = random_aim_offset(difficulty.aim_error);
+                }
+            }
+            DroneState::Engage => {}
+        }
+        if ai.state != DroneState::Engage {
+            continue;
+        }
+
+        let contact = Contact {
+            entity: target_entity,
+            position: track.position,
+            velocity: track.velocity,
+        };
+        let (desired_direction, weapons) = ai.engage(
+            transform,
+            loadout,
+            &contact,
+            &difficulty,
+            time.delta_seconds(),
+        );
+        control.desired_direction = Some(desired_direction);
+
+        for weapon in weapons {
+            fire_events.send(FireWeapon {
+                shooter: drone_entity,
+                weapon,
+                target: Some(target_entity),
+            });
+        }
+    }
+}
+
+pub fn drone_movement(
+    difficulty: Res<Difficulty>,
+    mut drone_query: Query<
+        (
+            &mut Transform,
+            &mut ColliderPositionComponent,
+            Option<&DroneControl>,
+        ),
+        With<Drone>,
+    >,
+    timer: Res<Time>,
+) {
+    for (mut drone_transform, mut collider_position, control) in drone_query.iter_mut() {
+        let desired_direction = control.and_then(|control| control.desired_direction);
+        let speed = control
+            .and_then(|control| control.desired_speed)
+            .unwrap_or(DRONE_SPEED);
+
+        drone_transform.rotation = match desired_direction {
+            Some(direction) => turn_towards(
+                drone_transform.rotation,
+                direction,
+                difficulty.turn_rate * timer.delta_seconds(),
+            ),
+            None => {
+                drone_transform.rotation
+                    * Quat::from_rotation_y(DRONE_PATROL_TURN_RATE * timer.delta_seconds())
+            }
+        };
+
+        drone_transform.translation = drone_transform.translation
+            + (drone_transform.rotation * Vec3::X * speed * timer.delta_seconds());
+
+        collider_position.0 = Isometry::from_parts(
+            drone_transform.translation.into(),
+            drone_transform.rotation.into(),
+        )
+        .into();
+    }
+}
@@ -0,0 +1,972 @@
+use bevy::{input::mouse::MouseMotion, prelude::*};
+use bevy_rapier3d::prelude::*;
+
+use super::guidance::*;
+use super::gun::GRAVITY;
+use super::input::*;
+use super::menu::AppState;
+use super::player::*;
+use super::weapons::*;
+
+/// Free camera movement keys, kept off the rebindable flight controls.
+const FREE_FORWARD: KeyCode = KeyCode::I;
+const FREE_BACK: KeyCode = KeyCode::K;
+const FREE_LEFT: KeyCode = KeyCode::J;
+const FREE_RIGHT: KeyCode = KeyCode::L;
+const FREE_UP: KeyCode = KeyCode::O;
+const FREE_DOWN: KeyCode = KeyCode::U;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CameraMode {
+    Cockpit,
+    Chase,
+    Padlock,
+    Missile,
+    FlyBy,
+    Free,
+}
+
+impl CameraMode {
+    pub fn next(&self) -> CameraMode {
+        match self {
+            CameraMode::Cockpit => CameraMode::Chase,
+            CameraMode::Chase => CameraMode::Padlock,
+            CameraMode::Padlock => CameraMode::Missile,
+            CameraMode::Missile => CameraMode::FlyBy,
+            CameraMode::FlyBy => CameraMode::Free,
+            CameraMode::Free => CameraMode::Cockpit,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CameraMode::Cockpit => "COCKPIT",
+            CameraMode::Chase => "CHASE",
+            CameraMode::Padlock => "PADLOCK",
+            CameraMode::Missile => "MISSILE",
+            CameraMode::FlyBy => "FLY-BY",
+            CameraMode::Free => "FREE",
+        }
+    }
+}
+
+/// A damped spring pulling the camera towards where its mode wants it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Spring {
+    pub stiffness: f32,
+    /// `2 * stiffness.sqrt()` is critically damped: the fastest settle without overshoot.
+    pub damping: f32,
+}
+
+pub struct CameraShake {
+    /// Shake in radians at full speed.
+    pub speed: f32,
+    /// Shake in radians per G above `g_threshold`.
+    pub g: f32,
+    pub g_threshold: f32,
+    /// Shakes per second.
+    pub frequency: f32,
+}
+
+pub struct CockpitCamera {
+    /// Pilot's eye in the aircraft's frame.
+    pub eye: Vec3,
+    pub fov: f32,
+    /// How far the right stick turns the pilot's head, in radians.
+    pub look_angle: f32,
+}
+
+pub struct ChaseCamera {
+    /// Camera position in the aircraft's frame.
+    pub offset: Vec3,
+    /// Height above the aircraft the camera looks at.
+    pub look_height: f32,
+    pub fov: f32,
+    /// Extra field of view at full speed.
+    pub speed_fov: f32,
+    /// Extra distance behind the aircraft at full speed.
+    pub speed_pull_back: f32,
+    /// Distance the camera sags below the aircraft per G pulled above 1.
+    pub g_drop: f32,
+    pub position_spring: Spring,
+    pub rotation_spring: Spring,
+}
+
+pub struct PadlockCamera {
+    pub eye: Vec3,
+    pub fov: f32,
+    /// Furthest the pilot can look from the nose, in radians.
+    pub max_angle: f32,
+}
+
+pub struct MissileCamera {
+    /// Distance behind and above the missile.
+    pub offset: Vec2,
+    pub fov: f32,
+    /// Seconds to hold on the impact point before returning to the chase view.
+    pub linger: f32,
+}
+
+pub struct FlyByCamera {
+    /// Distance ahead of the aircraft the camera is placed.
+    pub lead: f32,
+    pub side: f32,
+    pub height: f32,
+    pub fov: f32,
+}
+
+pub struct FreeCamera {
+    pub speed: f32,
+    /// Radians turned per pixel of mouse movement.
+    pub sensitivity: f32,
+    pub fov: f32,
+}
+
+pub struct CameraSettings {
+    /// Seconds taken to blend between modes.
+    pub transition_time: f32,
+    /// Applied to the views attached to the aircraft.
+    pub shake: CameraShake,
+    pub cockpit: CockpitCamera,
+    pub chase: ChaseCamera,
+    pub padlock: PadlockCamera,
+    pub missile: MissileCamera,
+    pub fly_by: FlyByCamera,
+    pub free: FreeCamera,
+}
+
+impl Default for CameraSettings {
+    fn default() -> Self {
+        CameraSettings {
+            transition_time: 0.6,
+            shake: CameraShake {
+                speed: 0.15_f32.to_radians(),
+                g: 0.1_f32.to_radians(),
+                g_threshold: 4.,
+                frequency: 12.,
+            },
+            cockpit: CockpitCamera {
+                eye: Vec3::new(1.2, 0.45, 0.),
+                fov: 75_f32.to_radians(),
+                look_angle: 135_f32.to_radians(),
+            },
+            chase: ChaseCamera {
+                offset: Vec3::new(-6.0, 0.8, 0.0),
+                look_height: 0.8,
+                fov: 60_f32.to_radians(),
+                speed_fov: 45_f32.to_radians(),
+                speed_pull_back: 2.,
+                g_drop: 0.15,
+                position_spring: Spring {
+                    stiffness: 120.,
+                    damping: 22.,
+                },
+                rotation_spring: Spring {
+                    stiffness: 200.,
+                    damping: 28.,
+                },
+            },
+            padlock: PadlockCamera {
+                eye: Vec3::new(1.2, 0.45, 0.),
+                fov: 75_f32.to_radians(),
+                max_angle: 150_f32.to_radians(),
+            },
+            missile: MissileCamera {
+                offset: Vec2::new(4., 0.6),
+                fov: 60_f32.to_radians(),
+                linger: 2.,
+            },
+            fly_by: FlyByCamera {
+                lead: 250.,
+                side: 20.,
+                height: 6.,
+                fov: 45_f32.to_radians(),
+            },
+            free: FreeCamera {
+                speed: 150.,
+                sensitivity: 0.003,
+                fov: 60_f32.to_radians(),
+            },
+        }
+    }
+}
+
+pub struct CameraState {
+    pub mode: CameraMode,
+    /// Camera pose and field of view when the current transition started.
+    from: Option<(Transform, f32)>,
+    /// Progress through the current transition, from 0 to 1.
+    blend: f32,
+    last_missile: Option<Entity>,
+    since_missile_lost: f32,
+    fly_by_anchor: Option<Vec3>,
+    free: Option<Transform>,
+    rig: CameraRig,
+    last_velocity: Option<Vec3>,
+}
+
+impl Default for CameraState {
+    fn default() -> Self {
+        CameraState {
+            mode: CameraMode::Chase,
+            from: None,
+            blend: 1.,
+            last_missile: None,
+            since_missile_lost: 0.,
+            fly_by_anchor: None,
+            free: None,
+            rig: CameraRig::default(),
+            last_velocity: None,
+        }
+    }
+}
+
+impl CameraState {
+    pub fn set_mode(&mut self, mode: CameraMode) {
+        if mode != self.mode {
+            self.mode = mode;
+            self.free = None;
+            self.fly_by_anchor = None;
+            self.start_transition();
+        }
+    }
+
+    /// Blends from wherever the camera is now to the current mode's view.
+    fn start_transition(&mut self) {
+        self.from = None;
+        self.blend = 0.;
+    }
+}
+
+/// Advances a damped spring with `offset` from its rest point and `velocity` by `delta_seconds`,
+/// returning the new offset and velocity. The spring is solved exactly rather than integrated, so
+/// one long step lands in the same place as many short ones.
+pub fn spring_step(
+    offset: Vec3,
+    velocity: Vec3,
+    spring: &Spring,
+    delta_seconds: f32,
+) -> (Vec3, Vec3) {
+    let t = delta_seconds.max(0.);
+    let omega = spring.stiffness.max(0.).sqrt();
+    if omega <= f32::EPSILON {
+        return (offset + velocity * t, velocity);
+    }
+    let zeta = spring.damping.max(0.) / (2. * omega);
+
+    if (zeta - 1.).abs() < 1e-4 {
+        let decay = (-omega * t).exp();
+        let b = velocity + offset * omega;
+        ((offset + b * t) * decay, (velocity - b * omega * t) * decay)
+    } else if zeta < 1. {
+        let omega_d = omega * (1. - zeta * zeta).sqrt();
+        let decay = (-zeta * omega * t).exp();
+        let (sin, cos) = (omega_d * t).sin_cos();
+        let b = (velocity + offset * zeta * omega) / omega_d;
+        (
+            (offset * cos + b * sin) * decay,
+            (velocity * cos - (b * zeta * omega + offset * omega_d) * sin) * decay,
+        )
+    } else {
+        let root = (zeta * zeta - 1.).sqrt();
+        let r1 = -omega * (zeta - root);
+        let r2 = -omega * (zeta + root);
+        let c1 = (velocity - offset * r2) / (r1 - r2);
+        let c2 = offset - c1;
+        let (e1, e2) = ((r1 * t).exp(), (r2 * t).exp());
+        (c1 * e1 + c2 * e2, c1 * r1 * e1 + c2 * r2 * e2)
+    }
+}
+
+/// Camera pose that follows a target pose through a pair of springs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CameraRig {
+    pub transform: Transform,
+    pub velocity: Vec3,
+    /// Rate the rotation error relative to the target is changing, as a scaled axis in the
+    /// target's frame.
+    pub angular_velocity: Vec3,
+}
+
+impl Default for CameraRig {
+    fn default() -> Self {
+        CameraRig::at(Transform::identity())
+    }
+}
+
+impl CameraRig {
+    pub fn at(transform: Transform) -> Self {
+        CameraRig {
+            transform,
+            velocity: Vec3::ZERO,
+            angular_velocity: Vec3::ZERO,
+        }
+    }
+
+    /// Rig already moving with a target at `velocity`.
+    pub fn moving(transform: Transform, velocity: Vec3) -> Self {
+        CameraRig {
+            velocity,
+            ..CameraRig::at(transform)
+        }
+    }
+
+    /// Moves the rig towards `target`, which has been moving at `target_velocity` and turning
+    /// at `target_angular_velocity`, a world scaled axis per second, over the step. The springs
+    /// act on the offset and rotation relative to the target, so a target at constant velocity
+    /// and turn rate is followed without lag at any frame rate.
+    pub fn step(
+        &mut self,
+        target: &Transform,
+        target_velocity: Vec3,
+        target_angular_velocity: Vec3,
+        position_spring: &Spring,
+        rotation_spring: &Spring,
+        delta_seconds: f32,
+    ) {
+        let elapsed = delta_seconds.max(0.);
+        let target_start = target.translation - target_velocity * elapsed;
+        let (offset, relative_velocity) = spring_step(
+            self.transform.translation - target_start,
+            self.velocity - target_velocity,
+            position_spring,
+            delta_seconds,
+        );
+        self.transform.translation = target.translation + offset;
+        self.velocity = target_velocity + relative_velocity;
+
+        let target_start_rotation =
+            Quat::from_scaled_axis(-target_angular_velocity * elapsed) * target.rotation;
+        let mut error = target_start_rotation.inverse() * self.transform.rotation;
+        if error.w < 0. {
+            error = -error;
+        }
+        let (axis, angle) = error.to_axis_angle();
+        let (error, angular_velocity) = spring_step(
+            axis * angle,
+            self.angular_velocity,
+            rotation_spring,
+            delta_seconds,
+        );
+        self.transform.rotation = (target.rotation * Quat::from_scaled_axis(error)).normalize();
+        self.angular_velocity = angular_velocity;
+    }
+}
+
+/// Shake in radians for the aircraft's speed, as a fraction of full, and G load.
+pub fn shake_amplitude(shake: &CameraShake, speed_ratio: f32, g_load: f32) -> f32 {
+    shake.speed * speed_ratio.clamp(0., 1.).powi(2) + shake.g * (g_load - shake.g_threshold).max(0.)
+}
+
+/// Small rotation jittering the view, from a few out-of-step sine waves.
+pub fn shake_rotation(seconds: f64, amplitude: f32, frequency: f32) -> Quat {
+    let wave = |rate: f64, phase: f64| {
+        ((seconds * frequency as f64 * rate + phase) * std::f64::consts::TAU).sin() as f32
+    };
+    let yaw = (wave(1., 0.) + 0.5 * wave(2.31, 0.37)) / 1.5;
+    let pitch = (wave(1.17, 0.61) + 0.5 * wave(2.73, 0.13)) / 1.5;
+    Quat::from_rotation_y(yaw * amplitude) * Quat::from_rotation_x(pitch * amplitude)
+}
+
+/// Eases `from` into `to` as `t` goes from 0 to 1.
+pub fn blend_pose(from: &Transform, to: &Transform, t: f32) -> Transform {
+    let t = t.clamp(0., 1.);
+    let eased = t * t * (3. - 2. * t);
+    Transform {
+        translation: from.translation.lerp(to.translation, eased),
+        rotation: from.rotation.slerp(to.rotation, eased),
+        ..*to
+    }
+}
+
+/// Limits `direction` to within `max_angle` radians of `forward`.
+pub fn clamp_look(forward: Vec3, direction: Vec3, max_angle: f32) -> Vec3 {
+    let direction = direction.normalize_or_zero();
+    let angle = forward.angle_between(direction);
+    if direction == Vec3::ZERO || angle <= max_angle {
+        return direction;
+    }
+    let turn = Quat::IDENTITY.slerp(
+        Quat::from_rotation_arc(forward, direction),
+        max_angle / angle,
+    );
+    turn * forward
+}
+
+/// Looks from the pilot's eye along the nose, turned by the head look `axis`.
+pub fn cockpit_pose(settings: &CockpitCamera, player: &Transform, axis: Vec2) -> Transform {
+    let eye = player.translation + player.rotation * settings.eye;
+    let head = Quat::from_rotation_y(-axis.x * settings.look_angle)
+        * Quat::from_rotation_z(axis.y * settings.look_angle / 2.);
+    let up = player.rotation * Vec3::Y;
+    Transform::from_translation(eye).looking_at(eye + player.rotation * head * Vec3::X, up)
+}
+
+/// Sits behind the aircraft, orbited around it by `axis`. It drops further back with speed and
+/// sags under G.
+pub fn chase_pose(
+    settings: &ChaseCamera,
+    player: &Transform,
+    axis: Vec2,
+    speed_ratio: f32,
+    g_load: f32,
+) -> Transform {
+    let orbit = Quat::from_rotation_y(-axis.x * std::f32::consts::PI)
+        * Quat::from_rotation_z(axis.y * std::f32::consts::FRAC_2_PI);
+    let up = (player.rotation * Vec3::Y).normalize();
+    let offset = settings.offset
+        + Vec3::new(
+            -settings.speed_pull_back * speed_ratio.clamp(0., 1.),
+            -settings.g_drop * (g_load - 1.).max(0.),
+            0.,
+        );
+    Transform::from_translation(player.translation + player.rotation * orbit * offset)
+        .looking_at(player.translation + up * settings.look_height, up)
+}
+
+/// Turns the pilot's head towards `target`, or along the nose when there is none.
+pub fn padlock_pose(
+    settings: &PadlockCamera,
+    player: &Transform,
+    target: Option<Vec3>,
+) -> Transform {
+    let eye = player.translation + player.rotation * settings.eye;
+    let forward = player.rotation * Vec3::X;
+    let up = player.rotation * Vec3::Y;
+    let look = target
+        .map(|target| clamp_look(forward, target - eye, settings.max_angle))
+        .filter(|look| *look != Vec3::ZERO && look.cross(up) != Vec3::ZERO)
+        .unwrap_or(forward);
+    Transform::from_translation(eye).looking_at(eye + look, up)
+}
+
+/// Follows behind a missile, looking along its flight path.
+pub fn missile_pose(settings: &MissileCamera, position: Vec3, velocity: Vec3) -> Transform {
+    let direction = if velocity == Vec3::ZERO {
+        Vec3::X
+    } else {
+        velocity.normalize()
+    };
+    let eye = position - direction * settings.offset.x + Vec3::Y * settings.offset.y;
+    Transform::from_translation(eye).looking_at(position + direction * settings.offset.x, Vec3::Y)
+}
+
+/// Point ahead of and beside the aircraft's flight path for it to fly past.
+pub fn fly_by_anchor(settings: &FlyByCamera, player: &Transform, velocity: Vec3) -> Vec3 {
+    let direction = if velocity == Vec3::ZERO {
+        player.rotation * Vec3::X
+    } else {
+        velocity.normalize()
+    };
+    let side = direction.cross(Vec3::Y);
+    let side = if side.length() < 0.1 {
+        player.rotation * Vec3::Z
+    } else {
+        side.normalize()
+    };
+    player.translation
+        + direction * settings.lead
+        + side * settings.side
+        + Vec3::Y * settings.height
+}
+
+/// Whether the aircraft has flown far enough past or away from the anchor to need a new one.
+pub fn fly_by_expired(
+    settings: &FlyByCamera,
+    anchor: Vec3,
+    position: Vec3,
+    velocity: Vec3,
+) -> bool {
+    let offset = position - anchor;
+    offset.dot(velocity.normalize_or_zero()) > settings.lead || offset.length() > settings.lead * 2.
+}
+
+pub fn camera_controls(
+    keyboard_input: Res<Input<KeyCode>>,
+    key_bindings: Res<KeyBindings>,
+    gamepads: Res<Gamepads>,
+    button_inputs: Res<Input<GamepadButton>>,
+    mut fired_events: EventReader<WeaponFired>,
+    player_query: Query<Entity, With<Player>>,
+    mut state: ResMut<CameraState>,
+) {
+    if keyboard_input.just_pressed(key_bindings.camera_mode)
+        || gamepads.iter().any(|gamepad| {
+            button_inputs.just_pressed(GamepadButton(*gamepad, GamepadButtonType::Mode))
+        })
+    {
+        let mode = state.mode.next();
+        state.set_mode(mode);
+    }
+
+    for event in fired_events.iter() {
+        if event.weapon == WeaponType::Missile && player_query.get(event.shooter).is_ok() {
+            state.last_missile = Some(event.projectile);
+            state.since_missile_lost = 0.;
+        }
+    }
+}
+
+pub fn update_camera(
+    settings: Res<CameraSettings>,
+    mut state: ResMut<CameraState>,
+    mut camera_query: Query<
+        (&mut Transform, &mut PerspectiveProjection, &mut Camera),
+        With<MainCamera>,
+    >,
+    transform_query: Query<&Transform, Without<MainCamera>>,
+    player_query: Query<(Entity, &RigidBodyVelocityComponent, &Player, &PlayerInput)>,
+    missile_query: Query<&Missile>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mouse_input: Res<Input<MouseButton>>,
+    mut mouse_motion: EventReader<MouseMotion>,
+    windows: Res<Windows>,
+    time: Res<Time>,
+    app_state: Res<State<AppState>>,
+) {
+    // The replay viewer directs the camera itself.
+    if *app_state.current() == AppState::Replay {
+        return;
+    }
+    let (mut camera_transform, mut perspective_projection, mut camera) =
+        match camera_query.get_single_mut() {
+            Ok(camera) => camera,
+            Err(_) => return,
+        };
+    let delta_seconds = time.delta_seconds();
+
+    let (player, player_velocity, player_angular_velocity, target, camera_axis) = player_query
+        .get_single()
+        .ok()
+        .and_then(|(entity, rb_vel, player, player_input)| {
+            transform_query.get(entity).ok().map(|transform| {
+                (
+                    *transform,
+                    Vec3::from(rb_vel.linvel),
+                    Vec3::from(rb_vel.angvel),
+                    player.target,
+                    player_input.camera_axis,
+                )
+            })
+        })
+        .unwrap_or((
+            Transform::identity(),
+            Vec3::ZERO,
+            Vec3::ZERO,
+            None,
+            Vec2::ZERO,
+        ));
+    let speed_ratio = (player_velocity.length() - MIN_SPEED) / (MAX_SPEED - MIN_SPEED);
+    let acceleration = match state.last_velocity {
+        Some(last_velocity) if delta_seconds > 0. => {
+            (player_velocity - last_velocity) / delta_seconds
+        }
+        _ => Vec3::ZERO,
+    };
+    state.last_velocity = Some(player_velocity);
+    let g_load = (acceleration + Vec3::Y * GRAVITY).dot(player.rotation * Vec3::Y) / GRAVITY;
+    let chase = || {
+        (
+            chase_pose(&settings.chase, &player, camera_axis, speed_ratio, g_load),
+            settings.chase.fov + speed_ratio * settings.chase.speed_fov,
+        )
+    };
+
+    let (pose, fov) = match state.mode {
+        CameraMode::Cockpit => (
+            cockpit_pose(&settings.cockpit, &player, camera_axis),
+            settings.cockpit.fov,
+        ),
+        CameraMode::Chase => chase(),
+        CameraMode::Padlock => (
+            padlock_pose(
+                &settings.padlock,
+                &player,
+                target
+                    .and_then(|target| transform_query.get(target).ok())
+                    .map(|transform| transform.translation),
+            ),
+            settings.padlock.fov,
+        ),
+        CameraMode::Missile => {
+            let missile = state.last_missile.and_then(|missile| {
+                transform_query
+                    .get(missile)
+                    .ok()
+                    .zip(missile_query.get(missile).ok())
+            });
+            match missile {
+                Some((transform, missile)) => (
+                    missile_pose(&settings.missile, transform.translation, missile.velocity),
+                    settings.missile.fov,
+                ),
+                None if state.last_missile.is_some() => {
+                    // Hold on the impact point, then ease back to the aircraft.
+                    state.since_missile_lost += delta_seconds;
+                    if state.since_missile_lost >= settings.missile.linger {
+                        state.last_missile = None;
+                        state.start_transition();
+                    }
+                    (*camera_transform, perspective_projection.fov)
+                }
+                None => chase(),
+            }
+        }
+        CameraMode::FlyBy => {
+            let anchor = match state.fly_by_anchor {
+                Some(anchor)
+                    if !fly_by_expired(
+                        &settings.fly_by,
+                        anchor,
+                        player.translation,
+                        player_velocity,
+                    ) =>
+                {
+                    anchor
+                }
+                _ => {
+                    let anchor = fly_by_anchor(&settings.fly_by, &player, player_velocity);
+                    state.fly_by_anchor = Some(anchor);
+                    // A new pass is a cut, not a blend.
+                    state.blend = 1.;
+                    anchor
+                }
+            };
+            (
+                Transform::from_translation(anchor).looking_at(player.translation, Vec3::Y),
+                settings.fly_by.fov,
+            )
+        }
+        CameraMode::Free => {
+            let (look, movement) =
+                free_camera_input(&keyboard_input, &mouse_input, &mut mouse_motion);
+            let transform = fly_free_camera(
+                &state.free.unwrap_or(*camera_transform),
+                &settings.free,
+                look,
+                movement,
+                delta_seconds,
+            );
+            state.free = Some(transform);
+            (transform, settings.free.fov)
+        }
+    };
+
+    let (from, from_fov) = *state
+        .from
+        .get_or_insert((*camera_transform, perspective_projection.fov));
+    let view = if state.blend < 1. {
+        state.blend = (state.blend + delta_seconds / settings.transition_time.max(0.01)).min(1.);
+        perspective_projection.fov = from_fov + (fov - from_fov) * state.blend;
+        let view = blend_pose(&from, &pose, state.blend);
+        state.rig = CameraRig::moving(view, player_velocity);
+        view
+    } else if state.mode == CameraMode::Chase {
+        let chase = &settings.chase;
+        state.rig.step(
+            &pose,
+            player_velocity,
+            player_angular_velocity,
+            &chase.position_spring,
+            &chase.rotation_spring,
+            delta_seconds,
+        );
+        perspective_projection.fov = fov;
+        state.rig.transform
+    } else {
+        perspective_projection.fov = fov;
+        pose
+    };
+
+    *camera_transform = match state.mode {
+        CameraMode::Cockpit | CameraMode::Chase | CameraMode::Padlock => {
+            let amplitude = shake_amplitude(&settings.shake, speed_ratio, g_load);
+            Transform {
+                rotation: view.rotation
+                    * shake_rotation(
+                        time.seconds_since_startup(),
+                        amplitude,
+                        settings.shake.frequency,
+                    ),
+                ..view
+            }
+        }
+        _ => view,
+    };
+
+    apply_projection(&mut perspective_projection, &mut camera, &windows);
+}
+
+/// Pushes a changed field of view through to the camera's projection matrix.
+pub fn apply_projection(
+    perspective_projection: &mut PerspectiveProjection,
+    camera: &mut Camera,
+    windows: &Windows,
+) {
+    if let Some(window) = windows.get_primary() {
+        perspective_projection.update(window.width(), window.height());
+        camera.projection_matrix = perspective_projection.get_projection_matrix();
+        camera.depth_calculation = perspective_projection.depth_calculation();
+    }
+}
+
+/// Mouse look, while the right button is held, and key movement for the free camera.
+pub fn free_camera_input(
+    keyboard_input: &Input<KeyCode>,
+    mouse_input: &Input<MouseButton>,
+    mouse_motion: &mut EventReader<MouseMotion>,
+) -> (Vec2, Vec3) {
+    let dragged = mouse_motion
+        .iter()
+        .fold(Vec2::ZERO, |dragged, motion| dragged + motion.delta);
+    let look = if mouse_input.pressed(MouseButton::Right) {
+        dragged
+    } else {
+        Vec2::ZERO
+    };
+
+    let mut movement = Vec3::ZERO;
+    for (key, direction) in [
+        (FREE_FORWARD, -Vec3::Z),
+        (FREE_BACK, Vec3::Z),
+        (FREE_LEFT, -Vec3::X),
+        (FREE_RIGHT, Vec3::X),
+        (FREE_UP, Vec3::Y),
+        (FREE_DOWN, -Vec3::Y),
+    ] {
+        if keyboard_input.pressed(key) {
+            movement += direction;
+        }
+    }
+    (look, movement)
+}
+
+/// Turns the free camera by `look` pixels of mouse movement and moves it along `movement` in its
+/// own frame.
+pub fn fly_free_camera(
+    transform: &Transform,
+    settings: &FreeCamera,
+    look: Vec2,
+    movement: Vec3,
+    delta_seconds: f32,
+) -> Transform {
+    let yaw = Quat::from_rotation_y(-look.x * settings.sensitivity);
+    let pitch = Quat::from_rotation_x(-look.y * settings.sensitivity);
+    let rotation = (yaw * transform.rotation * pitch).normalize();
+    Transform {
+        translation: transform.translation
+            + rotation * movement.normalize_or_zero() * settings.speed * delta_seconds,
+        rotation,
+        ..*transform
+    }
+}
+
+pub fn reset_camera(mut state: ResMut<CameraState>) {
+    state.last_missile = None;
+    state.fly_by_anchor = None;
+    state.free = None;
+    state.last_velocity = None;
+    state.start_transition();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STIFFNESS: f32 = 100.;
+
+    fn springs() -> [Spring; 3] {
+        [
+            // Critically damped.
+            Spring {
+                stiffness: STIFFNESS,
+                damping: 2. * STIFFNESS.sqrt(),
+            },
+            // Under-damped.
+            Spring {
+                stiffness: STIFFNESS,
+                damping: 5.,
+            },
+            // Over-damped.
+            Spring {
+                stiffness: STIFFNESS,
+                damping: 60.,
+            },
+        ]
+    }
+
+    fn assert_near(actual: Vec3, expected: Vec3, tolerance: f32) {
+        assert!(
+            (actual - expected).length() < tolerance,
+            "expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn spring_step_is_timestep_independent() {
+        let offset = Vec3::new(3., -1., 2.);
+        let velocity = Vec3::new(-4., 2., 0.5);
+        for spring in springs() {
+            let (long_offset, long_velocity) = spring_step(offset, velocity, &spring, 1. / 30.);
+            let (short_offset, short_velocity) = (0..4)
+                .fold((offset, velocity), |(offset, velocity), _| {
+                    spring_step(offset, velocity, &spring, 1. / 120.)
+                });
+            assert_near(short_offset, long_offset, 1e-4);
+            assert_near(short_velocity, long_velocity, 1e-3);
+        }
+    }
+
+    #[test]
+    fn spring_settles_at_rest() {
+        for spring in springs() {
+            let (offset, velocity) = (0..600).fold(
+                (Vec3::new(5., 0., -5.), Vec3::ZERO),
+                |(offset, velocity), _| spring_step(offset, velocity, &spring, 1. / 60.),
+            );
+            assert_near(offset, Vec3::ZERO, 1e-3);
+            assert_near(velocity, Vec3::ZERO, 1e-3);
+        }
+    }
+
+    #[test]
+    fn rig_is_timestep_independent_for_moving_target() {
+        let target_velocity = Vec3::new(200., 10., -30.);
+        let target_at = |seconds: f32| Transform::from_translation(target_velocity * seconds);
+        let rotation_spring = springs()[0];
+
+        for spring in springs() {
+            let start = CameraRig::moving(
+                Transform::from_translation(Vec3::new(-6., 2., 1.)),
+                Vec3::new(150., 0., 0.),
+            );
+
+            let mut long = start;
+            long.step(
+                &target_at(1. / 30.),
+                target_velocity,
+                Vec3::ZERO,
+                &spring,
+                &rotation_spring,
+                1. / 30.,
+            );
+
+            let mut short = start;
+            for step in 1..=4 {
+                short.step(
+                    &target_at(step as f32 / 120.),
+                    target_velocity,
+                    Vec3::ZERO,
+                    &spring,
+                    &rotation_spring,
+                    1. / 120.,
+                );
+            }
+
+            assert_near(
+                short.transform.translation,
+                long.transform.translation,
+                1e-3,
+            );
+            assert_near(short.velocity, long.velocity, 1e-2);
+        }
+    }
+
+    #[test]
+    fn rig_follows_constant_velocity_target_without_lag() {
+        let target_velocity = Vec3::X * 200.;
+        let spring = springs()[0];
+        let mut rig = CameraRig::default();
+        let mut time = 0.;
+        for _ in 0..300 {
+            time += 1. / 60.;
+            let target = Transform::from_translation(target_velocity * time);
+            rig.step(
+                &target,
+                target_velocity,
+                Vec3::ZERO,
+                &spring,
+                &spring,
+                1. / 60.,
+            );
+        }
+        assert_near(rig.transform.translation, target_velocity * time, 1e-2);
+        assert_near(rig.velocity, target_velocity, 1e-2);
+    }
+
+    #[test]
+    fn rig_is_timestep_independent_for_turning_target() {
+        let turn_rate = Vec3::new(0.2, 1.1, -0.4);
+        let target_at = |seconds: f32| {
+            Transform::from_rotation(
+                Quat::from_scaled_axis(turn_rate * seconds) * Quat::from_rotation_z(0.3),
+            )
+        };
+        let start = CameraRig::at(Transform::from_rotation(Quat::from_rotation_y(-0.5)));
+        let position_spring = springs()[0];
+
+        for spring in springs() {
+            let mut long = start;
+            long.step(
+                &target_at(1. / 30.),
+                Vec3::ZERO,
+                turn_rate,
+                &position_spring,
+                &spring,
+                1. / 30.,
+            );
+
+            let mut short = start;
+            for step in 1..=4 {
+                short.step(
+                    &target_at(step as f32 / 120.),
+                    Vec3::ZERO,
+                    turn_rate,
+                    &position_spring,
+                    &spring,
+                    1. / 120.,
+                );
+            }
+
+            assert!(
+                short.transform.rotation.dot(long.transform.rotation).abs() > 0.99999,
+                "{:?} vs {:?}",
+                short.transform.rotation,
+                long.transform.rotation
+            );
+            assert_near(short.angular_velocity, long.angular_velocity, 1e-3);
+        }
+    }
+
+    #[test]
+    fn rig_follows_turning_target_without_lag() {
+        let turn_rate = Vec3::Y * 0.8;
+        let spring = springs()[0];
+        let mut rig = CameraRig::default();
+        let mut time = 0.;
+        for _ in 0..300 {
+            time += 1. / 60.;
+            let target = Transform::from_rotation(Quat::from_scaled_axis(turn_rate * time));
+            rig.step(&target, Vec3::ZERO, turn_rate, &spring, &spring, 1. / 60.);
+        }
+        let target = Quat::from_scaled_axis(turn_rate * time);
+        assert!(rig.transform.rotation.dot(target).abs() > 0.99999);
+    }
+
+    #[test]
+    fn rotation_spring_converges() {
+        let target = Transform::from_rotation(
+            Quat::from_rotation_y(std::f32::consts::FRAC_PI_2) * Quat::from_rotation_z(0.3),
+        );
+        for spring in springs() {
+            let mut rig = CameraRig::default();
+            for _ in 0..600 {
+                rig.step(&target, Vec3::ZERO, Vec3::ZERO, &spring, &spring, 1. / 60.);
+            }
+            assert!(rig.transform.rotation.dot(target.rotation).abs() > 0.9999);
+            assert_near(rig.angular_velocity, Vec3::ZERO, 1e-2);
+        }
+    }
+}
@@ -0,0 +1,395 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use serde::Deserialize;
+
+use super::crash::*;
+use super::guidance::*;
+use super::gun::GRAVITY;
+use super::input::*;
+use super::player::*;
+use super::Drone;
+
+pub const DISPENSE_INTERVAL: f32 = 0.3;
+pub const AI_THREAT_RANGE: f32 = 400.;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecoyKind {
+    Flare,
+    Chaff,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum SeekerKind {
+    Infrared,
+    Radar,
+}
+
+impl Default for SeekerKind {
+    fn default() -> Self {
+        SeekerKind::Infrared
+    }
+}
+
+#[derive(Component)]
+pub struct Decoy {
+    pub kind: DecoyKind,
+    pub source: Entity,
+    pub signature: f32,
+    pub decay_rate: f32,
+    pub velocity: Vec3,
+    pub drag: f32,
+}
+
+#[derive(Component)]
+pub struct Countermeasures {
+    pub flares: u32,
+    pub chaff: u32,
+    pub max_flares: u32,
+    pub max_chaff: u32,
+    pub cooldown: f32,
+}
+
+impl Countermeasures {
+    pub fn new(flares: u32, chaff: u32) -> Self {
+        Countermeasures {
+            flares,
+            chaff,
+            max_flares: flares,
+            max_chaff: chaff,
+            cooldown: 0.,
+        }
+    }
+
+    pub fn take(&mut self, kind: DecoyKind) -> bool {
+        if self.cooldown > 0. {
+            return false;
+        }
+        let count = match kind {
+            DecoyKind::Flare => &mut self.flares,
+            DecoyKind::Chaff => &mut self.chaff,
+        };
+        if *count == 0 {
+            return false;
+        }
+        *count -= 1;
+        self.cooldown = DISPENSE_INTERVAL;
+        true
+    }
+
+    pub fn refill(&mut self) {
+        self.flares = self.max_flares;
+        self.chaff = self.max_chaff;
+        self.cooldown = 0.;
+    }
+}
+
+#[derive(Default)]
+pub struct DecoyAssets {
+    pub flare_mesh: Handle<Mesh>,
+    pub flare_material: Handle<StandardMaterial>,
+    pub chaff_mesh: Handle<Mesh>,
+    pub chaff_material: Handle<StandardMaterial>,
+}
+
+/// Rate per second at which a seeker of `seeker_kind` switches to a decoy seen `off_boresight`
+/// radians away from its current line of sight, given the decoy's current `signature`.
+pub fn decoy_seduction_rate(
+    seeker_kind: SeekerKind,
+    seeker: &Seeker,
+    decoy_kind: DecoyKind,
+    signature: f32,
+    off_boresight: f32,
+) -> f32 {
+    let base_rate = match (seeker_kind, decoy_kind) {
+        (SeekerKind::Infrared, DecoyKind::Flare) => 0.8,
+        (SeekerKind::Radar, DecoyKind::Chaff) => 0.6,
+        _ => 0.,
+    };
+    if off_boresight > seeker.field_of_view / 2. {
+        return 0.;
+    }
+    let geometry = 1. - off_boresight / (seeker.field_of_view / 2.);
+    (base_rate * signature.clamp(0., 1.) * geometry).max(0.)
+}
+
+/// Chance that a seduction happening at `rate` per second happens within `delta_seconds`.
+pub fn seduction_chance(rate: f32, delta_seconds: f32) -> f32 {
+    1. - (-rate.max(0.) * delta_seconds.max(0.)).exp()
+}
+
+pub fn setup_countermeasures(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    commands.insert_resource(DecoyAssets {
+        flare_mesh: meshes.add(Mesh::from(shape::Icosphere {
+            radius: 0.15,
+            subdivisions: 1,
+        })),
+        flare_material: materials.add(StandardMaterial {
+            base_color: Color::rgb(1., 0.85, 0.6),
+            emissive: Color::rgb(1., 0.7, 0.3),
+            unlit: true,
+            ..Default::default()
+        }),
+        chaff_mesh: meshes.add(Mesh::from(shape::Cube { size: 0.3 })),
+        chaff_material: materials.add(StandardMaterial {
+            base_color: Color::SILVER,
+            metallic: 1.,
+            ..Default::default()
+        }),
+    });
+}
+
+pub fn spawn_decoy(
+    commands: &mut Commands,
+    decoy_assets: &DecoyAssets,
+    kind: DecoyKind,
+    source: Entity,
+    position: Vec3,
+    velocity: Vec3,
+) {
+    let (mesh, material, drag, decay_rate) = match kind {
+        DecoyKind::Flare => (
+            decoy_assets.flare_mesh.clone(),
+            decoy_assets.flare_material.clone(),
+            0.02,
+            0.25,
+        ),
+        DecoyKind::Chaff => (
+            decoy_assets.chaff_mesh.clone(),
+            decoy_assets.chaff_material.clone(),
+            0.2,
+            0.2,
+        ),
+    };
+
+    commands
+        .spawn_bundle(PbrBundle {
+            mesh,
+            material,
+            transform: Transform::from_translation(position),
+            ..Default::default()
+        })
+        .insert(Decoy {
+            kind,
+            source,
+            signature: 1.,
+            decay_rate,
+            velocity,
+            drag,
+        })
+        .insert(TargetVelocity::default());
+}
+
+fn dispense(
+    commands: &mut Commands,
+    decoy_assets: &DecoyAssets,
+    countermeasures: &mut Countermeasures,
+    kind: DecoyKind,
+    source: Entity,
+    transform: &Transform,
+    velocity: Vec3,
+) {
+    if countermeasures.take(kind) {
+        let ejection = transform.rotation * Vec3::new(-10., -10., 0.);
+        spawn_decoy(
+            commands,
+            decoy_assets,
+            kind,
+            source,
+            transform.translation - transform.rotation * Vec3::X * 2.,
+            velocity + ejection,
+        );
+    }
+}
+
+pub fn player_countermeasures(
+    mut commands: Commands,
+    keyboard_input: Res<Input<KeyCode>>,
+    key_bindings: Res<KeyBindings>,
+    button_inputs: Res<Input<GamepadButton>>,
+    decoy_assets: Res<DecoyAssets>,
+    mut player_query: Query<
+        (
+            Entity,
+            &Transform,
+            &RigidBodyVelocityComponent,
+            &InputDevice,
+            &mut Countermeasures,
+        ),
+        (With<Player>, Without<Crashed>),
+    >,
+) {
+    for (entity, transform, rb_vel, device, mut countermeasures) in player_query.iter_mut() {
+        let pressed =
+            |key, button| device.just_pressed(&keyboard_input, key, &button_inputs, button);
+        let flare = pressed(key_bindings.flare, GamepadButtonType::North);
+        let chaff = pressed(key_bindings.chaff, GamepadButtonType::DPadDown);
+
+        if flare {
+            dispense(
+                &mut commands,
+                &decoy_assets,
+                &mut countermeasures,
+                DecoyKind::Flare,
+                entity,
+                transform,
+                rb_vel.linvel.into(),
+            );
+        }
+        if chaff {
+            dispense(
+                &mut commands,
+                &decoy_assets,
+                &mut countermeasures,
+                DecoyKind::Chaff,
+                entity,
+                transform,
+                rb_vel.linvel.into(),
+            );
+        }
+    }
+}
+
+pub fn ai_countermeasures(
+    mut commands: Commands,
+    decoy_assets: Res<DecoyAssets>,
+    missile_query: Query<(&Missile, &Transform)>,
+    mut drone_query: Query<
+        (Entity, &Transform, &TargetVelocity, &mut Countermeasures),
+        (With<Drone>, Without<Missile>),
+    >,
+) {
+    for (entity, transform, target_velocity, mut countermeasures) in drone_query.iter_mut() {
+        let threatened = missile_query.iter().any(|(missile, missile_transform)| {
+            missile.target == Some(entity)
+                && (missile_transform.translation - transform.translation).length()
+                    < AI_THREAT_RANGE
+        });
+        if !threatened {
+            continue;
+        }
+
+        let kind = if countermeasures.flares >= countermeasures.chaff {
+            DecoyKind::Flare
+        } else {
+            DecoyKind::Chaff
+        };
+        dispense(
+            &mut commands,
+            &decoy_assets,
+            &mut countermeasures,
+            kind,
+            entity,
+            transform,
+            target_velocity.linvel,
+        );
+    }
+}
+
+pub fn countermeasures_cooldown(mut query: Query<&mut Countermeasures>, time: Res<Time>) {
+    for mut countermeasures in query.iter_mut() {
+        countermeasures.cooldown = (countermeasures.cooldown - time.delta_seconds()).max(0.);
+    }
+}
+
+pub fn decoy_run(
+    mut commands: Commands,
+    mut decoy_query: Query<(Entity, &mut Decoy, &mut Transform)>,
+    time: Res<Time>,
+) {
+    for (entity, mut decoy, mut transform) in decoy_query.iter_mut() {
+        let acceleration =
+            Vec3::Y * -GRAVITY - decoy.velocity * decoy.velocity.length() * decoy.drag;
+        decoy.velocity = decoy.velocity + acceleration * time.delta_seconds();
+        transform.translation = transform.translation + decoy.velocity * time.delta_seconds();
+
+        decoy.signature -= decoy.decay_rate * time.delta_seconds();
+        if decoy.signature <= 0. {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+pub fn decoy_seduction(
+    decoy_query: Query<(Entity, &Decoy, &Transform)>,
+    mut missile_query: Query<(&mut Missile, &Transform), Without<Decoy>>,
+    time: Res<Time>,
+) {
+    for (decoy_entity, decoy, decoy_transform) in decoy_query.iter() {
+        for (mut missile, missile_transform) in missile_query.iter_mut() {
+            if missile.target != Some(decoy.source) {
+                continue;
+            }
+
+            let off_boresight = missile
+                .velocity
+                .angle_between(decoy_transform.translation - missile_transform.translation);
+            let rate = decoy_seduction_rate(
+                missile.params.seeker_kind,
+                &missile.params.seeker,
+                decoy.kind,
+                decoy.signature,
+                off_boresight,
+            );
+
+            if rand::random::<f32>() < seduction_chance(rate, time.delta_seconds()) {
+                missile.target = Some(decoy_entity);
+            }
+        }
+    }
+}
+
+pub fn refill_on_respawn(
+    mut respawn_events: EventReader<PlayerRespawned>,
+    mut query: Query<&mut Countermeasures>,
+) {
+    for event in respawn_events.iter() {
+        if let Ok(mut countermeasures) = query.get_mut(event.entity) {
+            countermeasures.refill();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn faded_decoy_seduces_less() {
+        let seeker = MissileParams::default().seeker;
+        let fresh = decoy_seduction_rate(SeekerKind::Infrared, &seeker, DecoyKind::Flare, 1., 0.);
+        let faded = decoy_seduction_rate(SeekerKind::Infrared, &seeker, DecoyKind::Flare, 0.25, 0.);
+        assert!(fresh > 0.);
+        assert!((faded - fresh * 0.25).abs() < 1e-6);
+    }
+
+    #[test]
+    fn decoy_outside_seeker_or_wrong_kind_is_ignored() {
+        let seeker = MissileParams::default().seeker;
+        let outside = seeker.field_of_view / 2. + 0.01;
+        assert_eq!(
+            decoy_seduction_rate(SeekerKind::Infrared, &seeker, DecoyKind::Flare, 1., outside),
+            0.
+        );
+        assert_eq!(
+            decoy_seduction_rate(SeekerKind::Infrared, &seeker, DecoyKind::Chaff, 1., 0.),
+            0.
+        );
+        assert_eq!(
+            decoy_seduction_rate(SeekerKind::Radar, &seeker, DecoyKind::Flare, 1., 0.),
+            0.
+        );
+    }
+
+    #[test]
+    fn seduction_chance_is_frame_rate_independent() {
+        let rate = 0.8;
+        let one_step = seduction_chance(rate, 1. / 30.);
+        let survive_two_steps = (1. - seduction_chance(rate, 1. / 60.)).powi(2);
+        assert!((one_step - (1. - survive_two_steps)).abs() < 1e-6);
+        assert_eq!(seduction_chance(rate, 0.), 0.);
+    }
+}
@@ -0,0 +1,269 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use super::damage::AircraftDestroyed;
+use super::player::*;
+use super::terrain::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CrashCause {
+    Impact,
+    Water,
+    OutOfBounds,
+    Destroyed,
+}
+
+pub struct CrashSettings {
+    pub max_impact_speed: f32,
+    pub max_impact_angle: f32,
+    /// Sink rate below which a contact is never a crash, however steep.
+    pub max_sink_rate: f32,
+    pub ceiling: f32,
+    pub crash_duration: f32,
+    pub invulnerability_duration: f32,
+}
+
+impl Default for CrashSettings {
+    fn default() -> Self {
+        CrashSettings {
+            max_impact_speed: 40.,
+            max_impact_angle: 15_f32.to_radians(),
+            max_sink_rate: 5.,
+            ceiling: 1500.,
+            crash_duration: 3.,
+            invulnerability_duration: 3.,
+        }
+    }
+}
+
+pub struct SpawnPoint {
+    pub position: Vec3,
+    pub look_at: Vec3,
+    pub speed: f32,
+}
+
+impl Default for SpawnPoint {
+    fn default() -> Self {
+        SpawnPoint {
+            position: Vec3::new(-700., 50., -210.),
+            look_at: Vec3::new(-600., 50., -700.),
+            speed: 0.,
+        }
+    }
+}
+
+impl SpawnPoint {
+    pub fn transform(&self) -> Transform {
+        let mut transform =
+            Transform::from_translation(self.position).looking_at(self.look_at, Vec3::Y);
+        // Aircraft fly along their local +X, so turn the -Z facing from `looking_at` about the
+        // local up axis.
+        transform.rotation =
+            transform.rotation * Quat::from_rotation_y(std::f32::consts::FRAC_PI_2);
+        transform
+    }
+}
+
+#[derive(Component)]
+pub struct Crashed {
+    pub cause: CrashCause,
+    pub timer: Timer,
+}
+
+#[derive(Component)]
+pub struct Invulnerable {
+    pub timer: Timer,
+}
+
+pub struct PlayerCrashed {
+    pub entity: Entity,
+    pub cause: CrashCause,
+}
+
+pub struct PlayerRespawned {
+    pub entity: Entity,
+}
+
+pub fn is_hard_impact(velocity: Vec3, settings: &CrashSettings) -> bool {
+    let speed = velocity.length();
+    if speed == 0. {
+        return false;
+    }
+    let descent_angle = (-velocity.y / speed).clamp(-1., 1.).asin();
+    speed > settings.max_impact_speed
+        || (descent_angle > settings.max_impact_angle && -velocity.y > settings.max_sink_rate)
+}
+
+pub fn is_out_of_bounds(position: Vec3, settings: &CrashSettings) -> bool {
+    position.x.abs() > TERRAIN_HALF_SIZE
+        || position.z.abs() > TERRAIN_HALF_SIZE
+        || position.y > settings.ceiling
+}
+
+/// Sends at most one `PlayerCrashed` per player, so a hard impact that also destroys the
+/// aircraft is only counted once.
+pub fn crash_detection(
+    mut contact_events: EventReader<ContactEvent>,
+    mut destroyed_events: EventReader<AircraftDestroyed>,
+    mut crash_events: EventWriter<PlayerCrashed>,
+    player_query: Query<
+        (Entity, &Transform, &RigidBodyVelocityComponent),
+        (With<Player>, Without<Crashed>, Without<Invulnerable>),
+    >,
+    settings: Res<CrashSettings>,
+) {
+    let contacts: Vec<(Entity, Entity)> = contact_events
+        .iter()
+        .filter_map(|event| match event {
+            ContactEvent::Started(h1, h2) => Some((h1.entity(), h2.entity())),
+            _ => None,
+        })
+        .collect();
+    let destroyed: Vec<Entity> = destroyed_events.iter().map(|event| event.entity).collect();
+
+    for (player_entity, player_transform, rb_vel) in player_query.iter() {
+        let cause = if player_transform.translation.y < WATER_LEVEL {
+            Some(CrashCause::Water)
+        } else if is_out_of_bounds(player_transform.translation, &settings) {
+            Some(CrashCause::OutOfBounds)
+        } else if contacts
+            .iter()
+            .any(|(e1, e2)| *e1 == player_entity || *e2 == player_entity)
+            && is_hard_impact(rb_vel.linvel.into(), &settings)
+        {
+            Some(CrashCause::Impact)
+        } else if destroyed.contains(&player_entity) {
+            Some(CrashCause::Destroyed)
+        } else {
+            None
+        };
+
+        if let Some(cause) = cause {
+            crash_events.send(PlayerCrashed {
+                entity: player_entity,
+                cause,
+            });
+        }
+    }
+}
+
+pub fn start_crash_sequence(
+    mut commands: Commands,
+    mut crash_events: EventReader<PlayerCrashed>,
+    mut player_query: Query<
+        (
+            &mut RigidBodyVelocityComponent,
+            &mut RigidBodyForcesComponent,
+        ),
+        Without<Crashed>,
+    >,
+    settings: Res<CrashSettings>,
+) {
+    for event in crash_events.iter() {
+        if let Ok((mut rb_vel, mut rb_forces)) = player_query.get_mut(event.entity) {
+            rb_vel.linvel = Vec3::ZERO.into();
+            rb_vel.angvel = Vec3::ZERO.into();
+            rb_forces.gravity_scale = 0.;
+            rb_forces.force = Vec3::ZERO.into();
+            rb_forces.torque = Vec3::ZERO.into();
+
+            commands.entity(event.entity).insert(Crashed {
+                cause: event.cause,
+                timer: Timer::from_seconds(settings.crash_duration, false),
+            });
+        }
+    }
+}
+
+pub fn run_crash_sequence(
+    mut commands: Commands,
+    mut respawn_events: EventWriter<PlayerRespawned>,
+    mut player_query: Query<(
+        Entity,
+        &mut Crashed,
+        &mut RigidBodyPositionComponent,
+        &mut RigidBodyVelocityComponent,
+        &mut RigidBodyForcesComponent,
+    )>,
+    spawn_point: Res<SpawnPoint>,
+    settings: Res<CrashSettings>,
+    time: Res<Time>,
+) {
+    for (entity, mut crashed, mut rb_pos, mut rb_vel, mut rb_forces) in player_query.iter_mut() {
+        rb_vel.linvel = Vec3::ZERO.into();
+        rb_vel.angvel = Vec3::ZERO.into();
+
+        if !crashed.timer.tick(time.delta()).finished() {
+            continue;
+        }
+
+        let spawn_transform = spawn_point.transform();
+        rb_pos.position = Isometry::from_parts(
+            spawn_transform.translation.into(),
+            spawn_transform.rotation.into(),
+        );
+        rb_pos.next_position = rb_pos.position;
+        rb_vel.linvel = (spawn_transform.rotation * Vec3::X * spawn_point.speed).into();
+        rb_forces.gravity_scale = 1.;
+
+        commands
+            .entity(entity)
+            .remove::<Crashed>()
+            .insert(Invulnerable {
+                timer: Timer::from_seconds(settings.invulnerability_duration, false),
+            });
+        respawn_events.send(PlayerRespawned { entity });
+    }
+}
+
+pub fn invulnerability_timer(
+    mut commands: Commands,
+    mut query: Query<(Entity, &mut Invulnerable)>,
+    time: Res<Time>,
+) {
+    for (entity, mut invulnerable) in query.iter_mut() {
+        if invulnerable.timer.tick(time.delta()).finished() {
+            commands.entity(entity).remove::<Invulnerable>();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn descending(speed: f32, degrees: f32) -> Vec3 {
+        let angle = degrees.to_radians();
+        Vec3::new(angle.cos(), -angle.sin(), 0.) * speed
+    }
+
+    #[test]
+    fn fast_contact_is_hard_at_any_angle() {
+        let settings = CrashSettings::default();
+        assert!(is_hard_impact(descending(60., 0.), &settings));
+        assert!(is_hard_impact(descending(60., 5.), &settings));
+    }
+
+    #[test]
+    fn steep_descent_is_hard() {
+        let settings = CrashSettings::default();
+        assert!(is_hard_impact(descending(30., 45.), &settings));
+        assert!(is_hard_impact(Vec3::new(0., -10., 0.), &settings));
+    }
+
+    #[test]
+    fn shallow_touchdown_is_not_hard() {
+        let settings = CrashSettings::default();
+        assert!(!is_hard_impact(descending(35., 5.), &settings));
+        assert!(!is_hard_impact(descending(35., -10.), &settings));
+        assert!(!is_hard_impact(Vec3::ZERO, &settings));
+    }
+
+    #[test]
+    fn slow_steep_contact_is_not_hard() {
+        let settings = CrashSettings::default();
+        // Rolling to a stop or settling onto a slope at walking pace.
+        assert!(!is_hard_impact(descending(2., 60.), &settings));
+        assert!(!is_hard_impact(Vec3::new(0., -3., 0.), &settings));
+    }
+}
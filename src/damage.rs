@@ -0,0 +1,270 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use super::ai::contact_velocity;
+use super::crash::*;
+use super::loadout::WeaponType;
+use super::player::*;
+
+pub const PART_HEALTH: f32 = 100.;
+pub const SPLASH_TO_FUSELAGE: f32 = 0.5;
+pub const COLLISION_DAMAGE_PER_SPEED: f32 = 1.5;
+/// Closing speed along the contact normal below which a collision does no damage.
+pub const MIN_COLLISION_SPEED: f32 = 5.;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AircraftPart {
+    Fuselage,
+    Engine,
+    LeftWing,
+    RightWing,
+    ControlSurfaces,
+}
+
+impl AircraftPart {
+    fn index(self) -> usize {
+        match self {
+            AircraftPart::Fuselage => 0,
+            AircraftPart::Engine => 1,
+            AircraftPart::LeftWing => 2,
+            AircraftPart::RightWing => 3,
+            AircraftPart::ControlSurfaces => 4,
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct AircraftDamage {
+    health: [f32; 5],
+}
+
+impl Default for AircraftDamage {
+    fn default() -> Self {
+        AircraftDamage {
+            health: [PART_HEALTH; 5],
+        }
+    }
+}
+
+impl AircraftDamage {
+    pub fn health(&self, part: AircraftPart) -> f32 {
+        self.health[part.index()]
+    }
+
+    pub fn integrity(&self, part: AircraftPart) -> f32 {
+        (self.health(part) / PART_HEALTH).clamp(0., 1.)
+    }
+
+    pub fn apply(&mut self, part: AircraftPart, amount: f32) {
+        let health = &mut self.health[part.index()];
+        *health = (*health - amount).max(0.);
+    }
+
+    pub fn repair(&mut self) {
+        self.health = [PART_HEALTH; 5];
+    }
+
+    pub fn is_destroyed(&self) -> bool {
+        self.health(AircraftPart::Fuselage) <= 0.
+    }
+
+    pub fn thrust_factor(&self) -> f32 {
+        self.integrity(AircraftPart::Engine)
+    }
+
+    pub fn lift_factor(&self) -> f32 {
+        (self.integrity(AircraftPart::LeftWing) + self.integrity(AircraftPart::RightWing)) / 2.
+    }
+
+    pub fn roll_factor(&self) -> f32 {
+        self.lift_factor() * (0.5 + 0.5 * self.integrity(AircraftPart::ControlSurfaces))
+    }
+
+    pub fn pitch_factor(&self) -> f32 {
+        0.25 + 0.75 * self.integrity(AircraftPart::ControlSurfaces)
+    }
+
+    pub fn yaw_factor(&self) -> f32 {
+        self.pitch_factor()
+    }
+
+    /// Constant roll input caused by one wing producing less lift than the other.
+    pub fn roll_bias(&self) -> f32 {
+        self.integrity(AircraftPart::RightWing) - self.integrity(AircraftPart::LeftWing)
+    }
+}
+
+pub struct DamageEvent {
+    pub target: Entity,
+    pub source: Option<Entity>,
+    pub point: Vec3,
+    pub amount: f32,
+    pub weapon: Option<WeaponType>,
+}
+
+pub struct AircraftDestroyed {
+    pub entity: Entity,
+    pub source: Option<Entity>,
+}
+
+/// Rough fit of the F-35 model: forward is +X, up is +Y and the right wing points to +Z.
+pub fn aircraft_collider_shape() -> ColliderShape {
+    ColliderShape::compound(vec![
+        (
+            Isometry::identity(),
+            ColliderShape::capsule(Point::new(-1.4, 0., 0.), Point::new(1.4, 0., 0.), 0.3),
+        ),
+        (
+            Isometry::translation(-0.2, 0., 0.75),
+            ColliderShape::cuboid(0.45, 0.05, 0.5),
+        ),
+        (
+            Isometry::translation(-0.2, 0., -0.75),
+            ColliderShape::cuboid(0.45, 0.05, 0.5),
+        ),
+        (
+            Isometry::translation(-1.3, 0., 0.),
+            ColliderShape::cuboid(0.2, 0.03, 0.7),
+        ),
+        (
+            Isometry::translation(-1.2, 0.35, 0.),
+            ColliderShape::cuboid(0.25, 0.3, 0.05),
+        ),
+    ])
+}
+
+pub fn part_at_local_point(local_point: Vec3) -> AircraftPart {
+    if local_point.x < -0.9 {
+        if local_point.z.abs() < 0.2 && local_point.y < 0.2 {
+            AircraftPart::Engine
+        } else {
+            AircraftPart::ControlSurfaces
+        }
+    } else if local_point.z > 0.3 {
+        AircraftPart::RightWing
+    } else if local_point.z < -0.3 {
+        AircraftPart::LeftWing
+    } else {
+        AircraftPart::Fuselage
+    }
+}
+
+pub fn apply_damage(
+    mut damage_events: EventReader<DamageEvent>,
+    mut destroyed_events: EventWriter<AircraftDestroyed>,
+    mut damage_query: Query<(&Transform, &mut AircraftDamage), Without<Invulnerable>>,
+) {
+    for event in damage_events.iter() {
+        if let Ok((transform, mut damage)) = damage_query.get_mut(event.target) {
+            if damage.is_destroyed() {
+                continue;
+            }
+
+            let local_point = transform.rotation.inverse() * (event.point - transform.translation);
+            let part = part_at_local_point(local_point);
+            damage.apply(part, event.amount);
+            if part != AircraftPart::Fuselage {
+                damage.apply(AircraftPart::Fuselage, event.amount * SPLASH_TO_FUSELAGE);
+            }
+
+            if damage.is_destroyed() {
+                destroyed_events.send(AircraftDestroyed {
+                    entity: event.target,
+                    source: event.source,
+                });
+            }
+        }
+    }
+}
+
+/// Closing speed of a contact, ignoring the sliding component along the surface.
+pub fn impact_speed(relative_velocity: Vec3, normal: Vec3) -> f32 {
+    relative_velocity.dot(normal.normalize_or_zero()).abs()
+}
+
+pub fn collision_damage(
+    mut contact_events: EventReader<ContactEvent>,
+    mut damage_events: EventWriter<DamageEvent>,
+    narrow_phase: Res<NarrowPhase>,
+    player_query: Query<(Entity, &RigidBodyVelocityComponent), With<Player>>,
+    velocity_query: Query<(Option<&RigidBodyVelocityComponent>, Option<&TargetVelocity>)>,
+) {
+    for event in contact_events.iter() {
+        if let ContactEvent::Started(h1, h2) = event {
+            for (player_entity, rb_vel) in player_query.iter() {
+                let other = if h1.entity() == player_entity {
+                    h2.entity()
+                } else if h2.entity() == player_entity {
+                    h1.entity()
+                } else {
+                    continue;
+                };
+
+                let contact_pair = match narrow_phase.contact_pair(*h1, *h2) {
+                    Some(contact_pair) => contact_pair,
+                    None => continue,
+                };
+                let deepest = contact_pair
+                    .manifolds
+                    .iter()
+                    .flat_map(|manifold| {
+                        manifold
+                            .data
+                            .solver_contacts
+                            .iter()
+                            .map(move |contact| (manifold, contact))
+                    })
+                    .min_by(|(_, a), (_, b)| {
+                        a.dist
+                            .partial_cmp(&b.dist)
+                            .unwrap_or(std::cmp::Ordering::Equal)
+                    });
+                let (manifold, contact) = match deepest {
+                    Some(deepest) => deepest,
+                    None => continue,
+                };
+
+                let other_velocity = velocity_query
+                    .get(other)
+                    .map(|(rb_vel, target_velocity)| contact_velocity(rb_vel, target_velocity))
+                    .unwrap_or(Vec3::ZERO);
+                let relative_velocity = Vec3::from(rb_vel.linvel) - other_velocity;
+                let speed = impact_speed(relative_velocity, manifold.data.normal.into());
+                if speed < MIN_COLLISION_SPEED {
+                    continue;
+                }
+
+                damage_events.send(DamageEvent {
+                    target: player_entity,
+                    source: None,
+                    point: contact.point.into(),
+                    amount: speed * COLLISION_DAMAGE_PER_SPEED,
+                    weapon: None,
+                });
+            }
+        }
+    }
+}
+
+pub fn drone_destroyed(
+    mut commands: Commands,
+    mut destroyed_events: EventReader<AircraftDestroyed>,
+    player_query: Query<Entity, With<Player>>,
+) {
+    for event in destroyed_events.iter() {
+        if player_query.get(event.entity).is_err() {
+            commands.entity(event.entity).despawn_recursive();
+        }
+    }
+}
+
+pub fn repair_on_respawn(
+    mut respawn_events: EventReader<PlayerRespawned>,
+    mut damage_query: Query<&mut AircraftDamage>,
+) {
+    for event in respawn_events.iter() {
+        if let Ok(mut damage) = damage_query.get_mut(event.entity) {
+            damage.repair();
+        }
+    }
+}
@@ -0,0 +1,53 @@
+use bevy::prelude::*;
+use serde::Deserialize;
+
+pub const PLAYER_FACTION: Faction = Faction::Blue;
+pub const DRONE_FACTION: Faction = Faction::Red;
+
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+pub enum Faction {
+    Blue,
+    Red,
+    Neutral,
+}
+
+impl Default for Faction {
+    fn default() -> Self {
+        Faction::Neutral
+    }
+}
+
+/// How one side identifies a contact.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Iff {
+    Friendly,
+    Hostile,
+    Neutral,
+}
+
+impl Iff {
+    pub fn color(&self) -> Color {
+        match self {
+            Iff::Friendly => Color::rgb(0.2, 0.6, 1.),
+            Iff::Hostile => Color::rgb(1., 0.2, 0.2),
+            Iff::Neutral => Color::rgb(0.9, 0.9, 0.2),
+        }
+    }
+}
+
+pub fn iff(own: Faction, other: Faction) -> Iff {
+    match (own, other) {
+        (Faction::Neutral, _) | (_, Faction::Neutral) => Iff::Neutral,
+        (own, other) if own == other => Iff::Friendly,
+        _ => Iff::Hostile,
+    }
+}
+
+/// Whether `own` may lock a weapon onto a contact of faction `other`.
+pub fn can_lock(own: Faction, other: Faction) -> bool {
+    iff(own, other) != Iff::Friendly
+}
+
+pub fn is_hostile(own: Faction, other: Faction) -> bool {
+    iff(own, other) == Iff::Hostile
+}
@@ -0,0 +1,524 @@
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use super::countermeasures::SeekerKind;
+use super::damage::*;
+use super::gun::GRAVITY;
+use super::loadout::WeaponType;
+use super::player::*;
+
+pub const MISSILE_DAMAGE: f32 = 80.;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub enum GuidanceLaw {
+    PurePursuit,
+    ProportionalNavigation,
+    AugmentedProportionalNavigation,
+}
+
+impl Default for GuidanceLaw {
+    fn default() -> Self {
+        GuidanceLaw::ProportionalNavigation
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Seeker {
+    pub gimbal_limit: f32,
+    pub field_of_view: f32,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct MissileMotor {
+    pub burn_time: f32,
+    pub thrust_acceleration: f32,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct MissileParams {
+    pub guidance: GuidanceLaw,
+    pub seeker_kind: SeekerKind,
+    pub navigation_constant: f32,
+    pub pursuit_gain: f32,
+    pub max_g: f32,
+    pub drag: f32,
+    pub seeker: Seeker,
+    pub motor: MissileMotor,
+    pub fuse_radius: f32,
+}
+
+impl Default for MissileParams {
+    fn default() -> Self {
+        MissileParams {
+            guidance: GuidanceLaw::default(),
+            seeker_kind: SeekerKind::default(),
+            navigation_constant: 4.,
+            pursuit_gain: 3.,
+            max_g: 30.,
+            drag: 0.00033,
+            seeker: Seeker {
+                gimbal_limit: 60_f32.to_radians(),
+                field_of_view: 20_f32.to_radians(),
+            },
+            motor: MissileMotor {
+                burn_time: 3.,
+                thrust_acceleration: 120.,
+            },
+            fuse_radius: 3.,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct MissileState {
+    pub position: Vec3,
+    pub velocity: Vec3,
+    pub age: f32,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TargetSample {
+    pub position: Vec3,
+    pub velocity: Vec3,
+    pub acceleration: Vec3,
+}
+
+#[derive(Component)]
+pub struct Missile {
+    pub source: Option<Entity>,
+    pub target: Option<Entity>,
+    pub params: MissileParams,
+    pub velocity: Vec3,
+    pub age: f32,
+    pub lifetime: f32,
+    last_target_velocity: Option<Vec3>,
+}
+
+impl Missile {
+    pub fn new(
+        source: Option<Entity>,
+        target: Option<Entity>,
+        params: MissileParams,
+        velocity: Vec3,
+    ) -> Self {
+        Missile {
+            source,
+            target,
+            params,
+            velocity,
+            age: 0.,
+            lifetime: 8.,
+            last_target_velocity: None,
+        }
+    }
+}
+
+fn perpendicular_to(vector: Vec3, axis: Vec3) -> Vec3 {
+    let axis = axis.normalize_or_zero();
+    vector - axis * vector.dot(axis)
+}
+
+/// Lateral acceleration requested by `law` to bring the missile onto the target.
+pub fn guidance_acceleration(
+    params: &MissileParams,
+    missile_position: Vec3,
+    missile_velocity: Vec3,
+    target: &TargetSample,
+) -> Vec3 {
+    let line_of_sight = target.position - missile_position;
+    let range_squared = line_of_sight.length_squared();
+    if range_squared == 0. {
+        return Vec3::ZERO;
+    }
+
+    match params.guidance {
+        GuidanceLaw::PurePursuit => {
+            let desired = line_of_sight.normalize() * missile_velocity.length();
+            perpendicular_to(desired - missile_velocity, missile_velocity) * params.pursuit_gain
+        }
+        GuidanceLaw::ProportionalNavigation | GuidanceLaw::AugmentedProportionalNavigation => {
+            let relative_velocity = target.velocity - missile_velocity;
+            let rotation_rate = line_of_sight.cross(relative_velocity) / range_squared;
+            let line_of_sight_dir = line_of_sight.normalize();
+            let closing_speed = -relative_velocity.dot(line_of_sight_dir);
+
+            let mut acceleration =
+                params.navigation_constant * closing_speed * rotation_rate.cross(line_of_sight_dir);
+
+            if params.guidance == GuidanceLaw::AugmentedProportionalNavigation {
+                acceleration += perpendicular_to(target.acceleration, line_of_sight_dir)
+                    * (params.navigation_constant / 2.);
+            }
+            acceleration
+        }
+    }
+}
+
+/// Removes the thrust-axis component and clamps the remainder to the airframe's G limit.
+pub fn limit_acceleration(acceleration: Vec3, velocity: Vec3, max_g: f32) -> Vec3 {
+    perpendicular_to(acceleration, velocity).clamp_length_max(max_g * GRAVITY)
+}
+
+pub fn seeker_can_track(seeker: &Seeker, velocity: Vec3, line_of_sight: Vec3) -> bool {
+    velocity.angle_between(line_of_sight) <= seeker.gimbal_limit
+}
+
+pub fn seeker_can_acquire(seeker: &Seeker, boresight: Vec3, line_of_sight: Vec3) -> bool {
+    boresight.angle_between(line_of_sight) <= seeker.field_of_view / 2.
+}
+
+pub fn motor_acceleration(motor: &MissileMotor, age: f32) -> f32 {
+    if age < motor.burn_time {
+        motor.thrust_acceleration
+    } else {
+        0.
+    }
+}
+
+/// Distance between `point` and the segment from `start` to `end`.
+pub fn segment_distance(start: Vec3, end: Vec3, point: Vec3) -> f32 {
+    let segment = end - start;
+    let length_squared = segment.length_squared();
+    if length_squared == 0. {
+        return (point - start).length();
+    }
+    let t = ((point - start).dot(segment) / length_squared).clamp(0., 1.);
+    (point - (start + segment * t)).length()
+}
+
+/// Advances the missile one step. `target` is `None` once the seeker has lost the target.
+pub fn step_missile(
+    params: &MissileParams,
+    state: &MissileState,
+    target: Option<&TargetSample>,
+    delta_seconds: f32,
+) -> MissileState {
+    let speed_dir = state.velocity.normalize_or_zero();
+
+    let guidance = target
+        .map(|target| guidance_acceleration(params, state.position, state.velocity, target))
+        .map(|acceleration| limit_acceleration(acceleration, state.velocity, params.max_g))
+        .unwrap_or(Vec3::ZERO);
+
+    let axial = speed_dir
+        * (motor_acceleration(&params.motor, state.age)
+            - params.drag * state.velocity.length_squared());
+
+    let acceleration = guidance + axial + Vec3::Y * -GRAVITY;
+    let velocity = state.velocity + acceleration * delta_seconds;
+
+    MissileState {
+        position: state.position + (state.velocity + velocity) / 2. * delta_seconds,
+        velocity,
+        age: state.age + delta_seconds,
+    }
+}
+
+/// Whether the proximity fuse triggers while the missile moves from `previous` to `current`.
+pub fn proximity_fuse(
+    params: &MissileParams,
+    previous: &MissileState,
+    current: &MissileState,
+    target_position: Vec3,
+) -> bool {
+    segment_distance(previous.position, current.position, target_position) < params.fuse_radius
+}
+
+pub fn missle_run(
+    mut commands: Commands,
+    mut missile_query: Query<(&mut Missile, &mut Transform, Entity)>,
+    target_query: Query<(&Transform, Option<&TargetVelocity>), Without<Missile>>,
+    mut damage_events: EventWriter<DamageEvent>,
+    time: Res<Time>,
+) {
+    let delta_seconds = time.delta_seconds();
+    if delta_seconds == 0. {
+        return;
+    }
+
+    for (mut missile, mut missile_transform, missile_entity) in missile_query.iter_mut() {
+        let state = MissileState {
+            position: missile_transform.translation,
+            velocity: missile.velocity,
+            age: missile.age,
+        };
+
+        let target_sample = missile
+            .target
+            .and_then(|target| target_query.get(target).ok())
+            .map(|(target_transform, target_velocity)| {
+                let velocity = target_velocity.map(|v| v.linvel).unwrap_or(Vec3::ZERO);
+                TargetSample {
+                    position: target_transform.translation,
+                    velocity,
+                    acceleration: missile
+                        .last_target_velocity
+                        .map(|last| (velocity - last) / delta_seconds)
+                        .unwrap_or(Vec3::ZERO),
+                }
+            });
+
+        let tracking = target_sample.filter(|target| {
+            seeker_can_track(
+                &missile.params.seeker,
+                state.velocity,
+                target.position - state.position,
+            )
+        });
+        if tracking.is_none() {
+            missile.target = None;
+        }
+        missile.last_target_velocity = tracking.map(|target| target.velocity);
+
+        let next_state = step_missile(&missile.params, &state, tracking.as_ref(), delta_seconds);
+
+        missile.velocity = next_state.velocity;
+        missile.age = next_state.age;
+        missile_transform.translation = next_state.position;
+        missile_transform.rotation =
+            Quat::from_rotation_arc(Vec3::Y, next_state.velocity.normalize_or_zero());
+
+        let detonated = match (missile.target, tracking) {
+            (Some(target), Some(target_sample))
+                if proximity_fuse(&missile.params, &state, &next_state, target_sample.position) =>
+            {
+                damage_events.send(DamageEvent {
+                    target,
+                    source: missile.source,
+                    point: next_state.position,
+                    amount: MISSILE_DAMAGE,
+                    weapon: Some(WeaponType::Missile),
+                });
+                true
+            }
+            _ => false,
+        };
+
+        if detonated || missile.age > missile.lifetime {
+            commands.entity(missile_entity).despawn_recursive();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STEP: f32 = 1. / 120.;
+
+    struct Flight {
+        /// Time the proximity fuse fired.
+        hit: Option<f32>,
+        /// Time the seeker lost the target.
+        lost: Option<f32>,
+        missile: MissileState,
+    }
+
+    /// Flies a missile against a scripted target the way `missle_run` does: the seeker drops the
+    /// target for good once it leaves the gimbal, and the fuse is checked along each step.
+    fn fly(
+        params: &MissileParams,
+        missile: MissileState,
+        target: impl Fn(f32) -> TargetSample,
+        seconds: f32,
+    ) -> Flight {
+        let mut flight = Flight {
+            hit: None,
+            lost: None,
+            missile,
+        };
+        let mut time = 0.;
+        while time < seconds {
+            let sample = target(time);
+            let tracking = flight.lost.is_none()
+                && seeker_can_track(
+                    &params.seeker,
+                    flight.missile.velocity,
+                    sample.position - flight.missile.position,
+                );
+            if !tracking && flight.lost.is_none() {
+                flight.lost = Some(time);
+            }
+
+            let next = step_missile(
+                params,
+                &flight.missile,
+                if tracking { Some(&sample) } else { None },
+                STEP,
+            );
+            if tracking
+                && proximity_fuse(params, &flight.missile, &next, target(time + STEP).position)
+            {
+                flight.hit = Some(time + STEP);
+                flight.missile = next;
+                return flight;
+            }
+            flight.missile = next;
+            time += STEP;
+        }
+        flight
+    }
+
+    fn launch() -> MissileState {
+        MissileState {
+            position: Vec3::ZERO,
+            velocity: Vec3::X * 300.,
+            age: 0.,
+        }
+    }
+
+    fn constant_velocity(position: Vec3, velocity: Vec3) -> impl Fn(f32) -> TargetSample {
+        move |time| TargetSample {
+            position: position + velocity * time,
+            velocity,
+            acceleration: Vec3::ZERO,
+        }
+    }
+
+    fn with_law(guidance: GuidanceLaw) -> MissileParams {
+        MissileParams {
+            guidance,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn proportional_navigation_leads_crossing_target() {
+        let target_velocity = Vec3::new(0., 0., 250.);
+        let target = constant_velocity(Vec3::new(2000., 0., -600.), target_velocity);
+
+        let pn = fly(
+            &with_law(GuidanceLaw::ProportionalNavigation),
+            launch(),
+            &target,
+            8.,
+        );
+        let pursuit = fly(&with_law(GuidanceLaw::PurePursuit), launch(), &target, 8.);
+
+        let pn_hit = pn.hit.expect("proportional navigation should intercept");
+        // Pure pursuit keeps pointing at the target and ends up chasing its tail: it either
+        // never catches it or gets there later, from behind.
+        if let Some(pursuit_hit) = pursuit.hit {
+            assert!(pursuit_hit > pn_hit, "{} vs {}", pursuit_hit, pn_hit);
+            assert!(pursuit.missile.velocity.angle_between(target_velocity) < 45_f32.to_radians());
+        }
+        assert!(pn.missile.velocity.angle_between(target_velocity) > 45_f32.to_radians());
+    }
+
+    #[test]
+    fn augmented_navigation_hits_weaving_target() {
+        let amplitude = 150.;
+        let frequency = 1.;
+        let target = move |time: f32| {
+            let phase = frequency * time;
+            TargetSample {
+                position: Vec3::new(2500. - 200. * time, 100., amplitude * phase.sin()),
+                velocity: Vec3::new(-200., 0., amplitude * frequency * phase.cos()),
+                acceleration: Vec3::new(0., 0., -amplitude * frequency * frequency * phase.sin()),
+            }
+        };
+
+        let apn = fly(
+            &with_law(GuidanceLaw::AugmentedProportionalNavigation),
+            launch(),
+            target,
+            8.,
+        );
+        assert!(
+            apn.hit.is_some(),
+            "missile ended at {}",
+            apn.missile.position
+        );
+    }
+
+    #[test]
+    fn seeker_drops_target_leaving_gimbal() {
+        let params = MissileParams::default();
+        let behind = Vec3::new(-500., 0., 0.);
+        assert!(!seeker_can_track(&params.seeker, Vec3::X, behind));
+
+        // A target passing close across the nose sweeps out of the gimbal faster than the
+        // missile can turn.
+        let target = constant_velocity(Vec3::new(300., 0., -50.), Vec3::new(0., 0., 600.));
+        let flight = fly(&params, launch(), target, 4.);
+        assert!(flight.lost.is_some());
+        assert!(flight.hit.is_none());
+    }
+
+    #[test]
+    fn lateral_acceleration_is_limited_to_max_g() {
+        // Pure pursuit of a target well off the nose asks for far more than the airframe allows.
+        let params = with_law(GuidanceLaw::PurePursuit);
+        let state = launch();
+        let target = TargetSample {
+            position: Vec3::new(100., 0., 500.),
+            velocity: Vec3::ZERO,
+            acceleration: Vec3::ZERO,
+        };
+
+        let commanded = guidance_acceleration(&params, state.position, state.velocity, &target);
+        assert!(commanded.length() > params.max_g * GRAVITY);
+        let limited = limit_acceleration(commanded, state.velocity, params.max_g);
+        assert!((limited.length() - params.max_g * GRAVITY).abs() < 1e-2);
+        assert!(limited.dot(state.velocity).abs() < 1e-2);
+
+        let next = step_missile(&params, &state, Some(&target), STEP);
+        let acceleration = (next.velocity - state.velocity) / STEP + Vec3::Y * GRAVITY;
+        let lateral = perpendicular_to(acceleration, state.velocity);
+        assert!(lateral.length() <= params.max_g * GRAVITY + 0.1);
+    }
+
+    #[test]
+    fn fuse_catches_fly_through_between_ticks() {
+        let params = MissileParams::default();
+        let previous = MissileState {
+            position: Vec3::new(-20., 0., 0.),
+            velocity: Vec3::X * 1200.,
+            age: 1.,
+        };
+        let current = MissileState {
+            position: Vec3::new(20., 0., 0.),
+            ..previous
+        };
+        let target = Vec3::new(0., 2., 0.);
+
+        // Neither end of the step is inside the fuse radius.
+        assert!((previous.position - target).length() > params.fuse_radius);
+        assert!((current.position - target).length() > params.fuse_radius);
+        assert!((segment_distance(previous.position, current.position, target) - 2.).abs() < 1e-4);
+        assert!(proximity_fuse(&params, &previous, &current, target));
+        assert!(!proximity_fuse(
+            &params,
+            &previous,
+            &current,
+            Vec3::new(0., 5., 0.)
+        ));
+    }
+
+    #[test]
+    fn motor_burns_then_coasts() {
+        let params = MissileParams {
+            drag: 0.,
+            ..Default::default()
+        };
+        let motor = params.motor;
+        assert_eq!(
+            motor_acceleration(&motor, motor.burn_time - 0.1),
+            motor.thrust_acceleration
+        );
+        assert_eq!(motor_acceleration(&motor, motor.burn_time + 0.1), 0.);
+
+        let burning = MissileState {
+            age: motor.burn_time - 0.5,
+            ..launch()
+        };
+        let coasting = MissileState {
+            age: motor.burn_time + 0.5,
+            ..launch()
+        };
+        let burn_speed_up = step_missile(&params, &burning, None, STEP).velocity.x - 300.;
+        let coast_speed_up = step_missile(&params, &coasting, None, STEP).velocity.x - 300.;
+        assert!((burn_speed_up - motor.thrust_acceleration * STEP).abs() < 1e-3);
+        assert!(coast_speed_up.abs() < 1e-3);
+    }
+}
@@ -0,0 +1,404 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use super::crash::*;
+use super::damage::*;
+use super::input::*;
+use super::loadout::*;
+use super::player::*;
+use super::weapons::*;
+
+pub const GRAVITY: f32 = 9.81;
+pub const MUZZLE_SPEED: f32 = 900.;
+pub const BULLET_DRAG: f32 = 0.0004;
+pub const BULLET_LIFETIME: f32 = 1.5;
+pub const BULLET_DAMAGE: f32 = 6.;
+pub const BULLET_DISPERSION: f32 = 0.003;
+pub const GUN_RANGE: f32 = 1000.;
+/// Time step used when flying trial rounds for the gunsight.
+pub const GUNSIGHT_STEP: f32 = 1. / 120.;
+pub const GUNSIGHT_ITERATIONS: usize = 6;
+
+#[derive(Component)]
+pub struct Bullet {
+    pub source: Option<Entity>,
+    pub velocity: Vec3,
+    pub lifetime: f32,
+}
+
+#[derive(Default)]
+pub struct GunAssets {
+    pub mesh: Handle<Mesh>,
+    pub material: Handle<StandardMaterial>,
+}
+
+/// Advances a projectile by `delta_seconds` under gravity and quadratic drag.
+pub fn step_projectile(
+    position: Vec3,
+    velocity: Vec3,
+    drag: f32,
+    delta_seconds: f32,
+) -> (Vec3, Vec3) {
+    let acceleration = Vec3::Y * -GRAVITY - velocity * velocity.length() * drag;
+    let new_velocity = velocity + acceleration * delta_seconds;
+    let new_position = position + (velocity + new_velocity) / 2. * delta_seconds;
+    (new_position, new_velocity)
+}
+
+/// Time for a projectile leaving the shooter at `muzzle_speed` to meet a target at
+/// `relative_position` moving with `relative_velocity`, ignoring gravity and drag.
+pub fn intercept_time(
+    relative_position: Vec3,
+    relative_velocity: Vec3,
+    muzzle_speed: f32,
+) -> Option<f32> {
+    let a = relative_velocity.length_squared() - muzzle_speed * muzzle_speed;
+    let b = 2. * relative_position.dot(relative_velocity);
+    let c = relative_position.length_squared();
+
+    if a.abs() < f32::EPSILON {
+        return if b < 0. { Some(-c / b) } else { None };
+    }
+
+    let discriminant = b * b - 4. * a * c;
+    if discriminant < 0. {
+        return None;
+    }
+
+    let sqrt_discriminant = discriminant.sqrt();
+    let t1 = (-b - sqrt_discriminant) / (2. * a);
+    let t2 = (-b + sqrt_discriminant) / (2. * a);
+
+    [t1, t2]
+        .iter()
+        .cloned()
+        .filter(|t| *t > 0.)
+        .fold(None, |best: Option<f32>, t| {
+            Some(best.map_or(t, |b| b.min(t)))
+        })
+}
+
+/// Flies a round fired from `position` with `velocity` and returns the time of its closest
+/// approach to a target moving at constant velocity, along with the vector from the round to the
+/// target at that moment. Returns `None` if the round never closes on the target within its
+/// lifetime.
+fn round_closest_approach(
+    position: Vec3,
+    velocity: Vec3,
+    target_position: Vec3,
+    target_velocity: Vec3,
+) -> Option<(f32, Vec3)> {
+    let (mut position, mut velocity) = (position, velocity);
+    let mut time = 0.;
+    while time < BULLET_LIFETIME {
+        let (next_position, next_velocity) =
+            step_projectile(position, velocity, BULLET_DRAG, GUNSIGHT_STEP);
+        let from = position - (target_position + target_velocity * time);
+        let to = next_position - (target_position + target_velocity * (time + GUNSIGHT_STEP));
+
+        let motion = to - from;
+        let fraction = if motion.length_squared() > 0. {
+            (-from.dot(motion) / motion.length_squared()).clamp(0., 1.)
+        } else {
+            0.
+        };
+        if fraction < 1. {
+            // Already opening at the muzzle means the round can never catch the target.
+            if time == 0. && fraction == 0. {
+                return None;
+            }
+            return Some((time + fraction * GUNSIGHT_STEP, -(from + motion * fraction)));
+        }
+
+        position = next_position;
+        velocity = next_velocity;
+        time += GUNSIGHT_STEP;
+    }
+    None
+}
+
+/// Point the gun has to be aimed at for its rounds to hit the target, compensating for
+/// target motion relative to the shooter, bullet drop and drag. Starts from the drag-free lead
+/// and corrects it by the miss of trial rounds flown with `step_projectile`.
+pub fn gunsight_aim_point(
+    shooter_position: Vec3,
+    shooter_velocity: Vec3,
+    target_position: Vec3,
+    target_velocity: Vec3,
+    muzzle_speed: f32,
+) -> Option<Vec3> {
+    let relative_position = target_position - shooter_position;
+    let relative_velocity = target_velocity - shooter_velocity;
+    let time = intercept_time(relative_position, relative_velocity, muzzle_speed)?;
+
+    let mut aim_point = shooter_position
+        + relative_position
+        + relative_velocity * time
+        + Vec3::Y * (0.5 * GRAVITY * time * time);
+    for _ in 0..GUNSIGHT_ITERATIONS {
+        let direction = (aim_point - shooter_position).normalize_or_zero();
+        let (_, miss) = round_closest_approach(
+            shooter_position,
+            shooter_velocity + direction * muzzle_speed,
+            target_position,
+            target_velocity,
+        )?;
+        aim_point += miss;
+    }
+    Some(aim_point)
+}
+
+pub fn setup_gun(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    commands.insert_resource(GunAssets {
+        mesh: meshes.add(Mesh::from(bevy::render::mesh::shape::Capsule {
+            radius: 0.02,
+            depth: 1.5,
+            ..Default::default()
+        })),
+        material: materials.add(StandardMaterial {
+            base_color: Color::rgb(1., 0.9, 0.5),
+            emissive: Color::rgb(1., 0.8, 0.3),
+            unlit: true,
+            ..Default::default()
+        }),
+    });
+}
+
+pub fn fire_gun(
+    mut fire_events: EventWriter<FireWeapon>,
+    player_query: Query<(Entity, &Player, &PlayerInput), Without<Crashed>>,
+) {
+    for (player_entity, player, player_input) in player_query.iter() {
+        if !player_input.fire_gun {
+            continue;
+        }
+        fire_events.send(FireWeapon {
+            shooter: player_entity,
+            weapon: WeaponType::Gun,
+            target: player.target,
+        });
+    }
+}
+
+pub fn bullet_run(
+    mut commands: Commands,
+    mut bullet_query: Query<(Entity, &mut Bullet, &mut Transform)>,
+    mut damage_events: EventWriter<DamageEvent>,
+    query_pipeline: Res<QueryPipeline>,
+    collider_query: QueryPipelineColliderComponentsQuery,
+    time: Res<Time>,
+) {
+    let collider_set = QueryPipelineColliderComponentsSet(&collider_query);
+
+    for (bullet_entity, mut bullet, mut bullet_transform) in bullet_query.iter_mut() {
+        let origin = bullet_transform.translation;
+        let (position, velocity) =
+            step_projectile(origin, bullet.velocity, BULLET_DRAG, time.delta_seconds());
+        let displacement = position - origin;
+
+        let source = bullet.source;
+        let ray = Ray::new(origin.into(), displacement.into());
+        let hit = query_pipeline.cast_ray(
+            &collider_set,
+            &ray,
+            1.,
+            true,
+            InteractionGroups::all(),
+            Some(&|handle: ColliderHandle| Some(handle.entity()) != source),
+        );
+
+        if let Some((handle, toi)) = hit {
+            damage_events.send(DamageEvent {
+                target: handle.entity(),
+                source,
+                point: origin + displacement * toi,
+                amount: BULLET_DAMAGE,
+                weapon: Some(WeaponType::Gun),
+            });
+            commands.entity(bullet_entity).despawn_recursive();
+            continue;
+        }
+
+        bullet.velocity = velocity;
+        bullet.lifetime -= time.delta_seconds();
+        bullet_transform.translation = position;
+        bullet_transform.rotation = Quat::from_rotation_arc(Vec3::Y, velocity.normalize_or_zero());
+
+        if bullet.lifetime < 0. {
+            commands.entity(bullet_entity).despawn_recursive();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn simulate(velocity: Vec3, drag: f32, seconds: f32, steps: u32) -> (Vec3, Vec3) {
+        let delta_seconds = seconds / steps as f32;
+        (0..steps).fold((Vec3::ZERO, velocity), |(position, velocity), _| {
+            step_projectile(position, velocity, drag, delta_seconds)
+        })
+    }
+
+    #[test]
+    fn drops_under_gravity_without_drag() {
+        let (position, velocity) = simulate(Vec3::X * MUZZLE_SPEED, 0., 1., 100);
+        assert!((position.y + 0.5 * GRAVITY).abs() < 1e-3, "{}", position);
+        assert!((position.x - MUZZLE_SPEED).abs() < 1e-2, "{}", position);
+        assert!((velocity.y + GRAVITY).abs() < 1e-3, "{}", velocity);
+    }
+
+    #[test]
+    fn drag_slows_and_shortens_drop() {
+        let seconds = 0.5;
+        let (position, velocity) = simulate(Vec3::X * MUZZLE_SPEED, BULLET_DRAG, seconds, 500);
+
+        // Horizontal speed under quadratic drag alone: v0 / (1 + k v0 t).
+        let expected_speed = MUZZLE_SPEED / (1. + BULLET_DRAG * MUZZLE_SPEED * seconds);
+        assert!(
+            (velocity.x - expected_speed).abs() < expected_speed * 0.01,
+            "{}",
+            velocity
+        );
+        assert!(position.x < MUZZLE_SPEED * seconds);
+
+        let vacuum_drop = 0.5 * GRAVITY * seconds * seconds;
+        assert!(position.y < 0., "{}", position);
+        assert!(position.y > -vacuum_drop, "{}", position);
+    }
+
+    #[test]
+    fn intercepts_constant_velocity_target() {
+        let relative_position = Vec3::new(1000., 0., 0.);
+        let relative_velocity = Vec3::new(0., 0., 100.);
+        let time = intercept_time(relative_position, relative_velocity, MUZZLE_SPEED).unwrap();
+
+        assert!(time > 0.);
+        let meeting_point = relative_position + relative_velocity * time;
+        assert!((meeting_point.length() - MUZZLE_SPEED * time).abs() < 1e-2);
+    }
+
+    #[test]
+    fn time_of_flight_follows_drag() {
+        let range = 1000.;
+        let (time, miss) = round_closest_approach(
+            Vec3::ZERO,
+            Vec3::X * MUZZLE_SPEED,
+            Vec3::X * range,
+            Vec3::ZERO,
+        )
+        .unwrap();
+
+        // Straight-line flight under quadratic drag: t = (e^(k d) - 1) / (k v0).
+        let expected = ((BULLET_DRAG * range).exp() - 1.) / (BULLET_DRAG * MUZZLE_SPEED);
+        assert!((time - expected).abs() < 0.02, "{} vs {}", time, expected);
+        assert!(miss.y > 0., "round should fall below the target: {}", miss);
+    }
+
+    /// Closest distance between a round fired towards `aim_point` and the target.
+    fn miss_distance(
+        shooter_position: Vec3,
+        shooter_velocity: Vec3,
+        aim_point: Vec3,
+        target_position: Vec3,
+        target_velocity: Vec3,
+    ) -> f32 {
+        let delta_seconds = 1. / 2000.;
+        let direction = (aim_point - shooter_position).normalize();
+        let mut position = shooter_position;
+        let mut velocity = shooter_velocity + direction * MUZZLE_SPEED;
+        let mut closest = f32::INFINITY;
+        let mut time = 0.;
+        while time < BULLET_LIFETIME {
+            let target = target_position + target_velocity * time;
+            closest = closest.min((position - target).length());
+            let (next_position, next_velocity) =
+                step_projectile(position, velocity, BULLET_DRAG, delta_seconds);
+            position = next_position;
+            velocity = next_velocity;
+            time += delta_seconds;
+        }
+        closest
+    }
+
+    #[test]
+    fn round_fired_at_aim_point_hits_target() {
+        let cases = [
+            (Vec3::ZERO, Vec3::ZERO, Vec3::new(1000., 0., 0.), Vec3::ZERO),
+            (
+                Vec3::new(0., 500., 0.),
+                Vec3::X * 200.,
+                Vec3::new(600., 520., 50.),
+                Vec3::new(150., 0., 150.),
+            ),
+            (
+                Vec3::ZERO,
+                Vec3::new(150., 20., 0.),
+                Vec3::new(800., 100., -200.),
+                Vec3::new(-50., 0., 200.),
+            ),
+        ];
+        for (shooter_position, shooter_velocity, target_position, target_velocity) in cases {
+            let aim_point = gunsight_aim_point(
+                shooter_position,
+                shooter_velocity,
+                target_position,
+                target_velocity,
+                MUZZLE_SPEED,
+            )
+            .unwrap();
+            let miss = miss_distance(
+                shooter_position,
+                shooter_velocity,
+                aim_point,
+                target_position,
+                target_velocity,
+            );
+            assert!(miss < 0.5, "missed by {} m aiming at {}", miss, aim_point);
+        }
+    }
+
+    #[test]
+    fn drag_free_lead_misses_at_range() {
+        let target_position = Vec3::new(1000., 0., 0.);
+        let time = intercept_time(target_position, Vec3::ZERO, MUZZLE_SPEED).unwrap();
+        let drag_free = target_position + Vec3::Y * (0.5 * GRAVITY * time * time);
+        let miss = miss_distance(
+            Vec3::ZERO,
+            Vec3::ZERO,
+            drag_free,
+            target_position,
+            Vec3::ZERO,
+        );
+        assert!(miss > 1., "{}", miss);
+    }
+
+    #[test]
+    fn faster_receding_target_cannot_be_intercepted() {
+        let relative_position = Vec3::new(100., 0., 0.);
+        let relative_velocity = Vec3::new(1000., 0., 0.);
+        assert_eq!(
+            intercept_time(relative_position, relative_velocity, MUZZLE_SPEED),
+            None
+        );
+        assert_eq!(
+            intercept_time(relative_position, Vec3::X * MUZZLE_SPEED, MUZZLE_SPEED),
+            None
+        );
+        assert_eq!(
+            gunsight_aim_point(
+                Vec3::ZERO,
+                Vec3::ZERO,
+                relative_position,
+                relative_velocity,
+                MUZZLE_SPEED
+            ),
+            None
+        );
+    }
+}
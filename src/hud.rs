@@ -0,0 +1,643 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use super::gun::GRAVITY;
+use super::input::*;
+use super::navigation::*;
+use super::player::*;
+
+pub const PITCH_LADDER_STEP: i32 = 10;
+pub const PITCH_LADDER_VISIBLE: f32 = 25.;
+pub const RUNG_WIDTH: f32 = 60.;
+pub const RUNG_GAP: f32 = 50.;
+pub const THROTTLE_BAR_HEIGHT: f32 = 100.;
+pub const FLIGHT_PATH_MARKER_SIZE: f32 = 14.;
+const HUD_COLOR: Color = Color::rgb(0., 1., 0.);
+
+/// Flight data shown on the HUD, independent of layout.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct HudModel {
+    /// Nose above the horizon in degrees.
+    pub pitch: f32,
+    /// Bank angle in degrees, positive with the right wing down.
+    pub roll: f32,
+    /// Compass heading of the nose in degrees.
+    pub heading: f32,
+    /// Airspeed in km/h.
+    pub airspeed: f32,
+    /// Altitude in metres.
+    pub altitude: f32,
+    /// Climb rate in m/s.
+    pub vertical_speed: f32,
+    /// Direction of flight relative to the nose in degrees, +x right and +y up.
+    pub flight_path: Vec2,
+    /// Angle of attack in degrees.
+    pub aoa: f32,
+    /// Load factor along the aircraft's up axis.
+    pub g_load: f32,
+    pub throttle: f32,
+}
+
+/// Builds the HUD model from the aircraft's state. `acceleration` is the rate of change of
+/// `velocity`.
+pub fn hud_model(
+    position: Vec3,
+    rotation: Quat,
+    velocity: Vec3,
+    acceleration: Vec3,
+    throttle: f32,
+) -> HudModel {
+    let forward = rotation * Vec3::X;
+    let up = rotation * Vec3::Y;
+    let right = rotation * Vec3::Z;
+
+    let local_velocity = rotation.inverse() * velocity;
+    let flight_path = if local_velocity.length_squared() > 0. {
+        Vec2::new(
+            local_velocity.z.atan2(local_velocity.x).to_degrees(),
+            local_velocity.y.atan2(local_velocity.x).to_degrees(),
+        )
+    } else {
+        Vec2::ZERO
+    };
+
+    HudModel {
+        pitch: forward.y.clamp(-1., 1.).asin().to_degrees(),
+        roll: (-right.y).atan2(up.y).to_degrees(),
+        heading: compass_heading(forward),
+        airspeed: velocity.length() * 3.6,
+        altitude: position.y,
+        vertical_speed: velocity.y,
+        flight_path,
+        aoa: -flight_path.y,
+        g_load: (acceleration + Vec3::Y * GRAVITY).dot(up) / GRAVITY,
+        throttle: throttle.clamp(0., 1.),
+    }
+}
+
+/// Screen distance from the centre of a point `degrees` off the boresight, for a vertical field
+/// of view of `fov` radians.
+pub fn degrees_to_pixels(degrees: f32, fov: f32, window_height: f32) -> f32 {
+    let degrees = degrees.clamp(-89., 89.);
+    window_height / 2. * degrees.to_radians().tan() / (fov / 2.).tan()
+}
+
+/// Text heading tape centred on `heading`, one character per two degrees.
+pub fn heading_tape(heading: f32, half_width: usize) -> String {
+    let degrees_per_char = 2.;
+    let width = half_width * 2 + 1;
+    let mut tape = vec![' '; width];
+    let start = heading - half_width as f32 * degrees_per_char;
+
+    let first_label = (start / 10.).ceil() as i32 * 10;
+    let mut label = first_label;
+    while (label as f32) <= start + (width - 1) as f32 * degrees_per_char {
+        let column = ((label as f32 - start) / degrees_per_char).round() as i32;
+        let text = format!("{:03}", label.rem_euclid(360));
+        for (offset, c) in text.chars().enumerate() {
+            let index = column - 1 + offset as i32;
+            if index >= 0 && (index as usize) < width {
+                tape[index as usize] = c;
+            }
+        }
+        label += 10;
+    }
+
+    let mut value: String = tape.into_iter().collect();
+    value += &format!("\n{:>width$}", "^", width = half_width + 1);
+    value
+}
+
+/// Vertical text tape with `value` boxed in the middle and `step` spaced marks above and below.
+pub fn vertical_tape(value: f32, step: f32, half_lines: i32) -> String {
+    let nearest = (value / step).round() as i32;
+    let mut lines = Vec::new();
+    for line in (-half_lines..=half_lines).rev() {
+        if line == 0 {
+            lines.push(format!("[{:>5.0}]", value));
+        } else {
+            let mark = (nearest + line) as f32 * step;
+            lines.push(format!(" {:>5.0} ", mark));
+        }
+    }
+    lines.join("\n")
+}
+
+#[derive(Component)]
+pub struct AirspeedTape;
+
+#[derive(Component)]
+pub struct AltitudeTape;
+
+#[derive(Component)]
+pub struct HeadingTape;
+
+#[derive(Component)]
+pub struct FlightDataText;
+
+#[derive(Component)]
+pub struct PitchLadder;
+
+#[derive(Component)]
+pub struct PitchRung {
+    pub pitch: f32,
+}
+
+#[derive(Component)]
+pub struct FlightPathMarker;
+
+#[derive(Component)]
+pub struct ThrottleBar;
+
+/// Root node of a flight HUD element, hidden while watching a replay.
+#[derive(Component, Default)]
+pub struct FlightHud {
+    /// Where the node sat before it was hidden.
+    hidden_from: Option<Rect<Val>>,
+}
+
+fn line(parent: &mut ChildBuilder, left: f32, bottom: f32, width: f32, height: f32, color: Color) {
+    parent.spawn_bundle(NodeBundle {
+        style: Style {
+            size: Size::new(Val::Px(width), Val::Px(height)),
+            position_type: PositionType::Absolute,
+            position: Rect {
+                left: Val::Px(left),
+                bottom: Val::Px(bottom),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        color: color.into(),
+        ..Default::default()
+    });
+}
+
+fn hud_text(
+    asset_server: &AssetServer,
+    position: Rect<Val>,
+    font_size: f32,
+    horizontal: HorizontalAlign,
+) -> TextBundle {
+    TextBundle {
+        style: Style {
+            align_self: AlignSelf::FlexStart,
+            position_type: PositionType::Absolute,
+            position,
+            ..Default::default()
+        },
+        text: Text::with_section(
+            "",
+            TextStyle {
+                font: asset_server.load("fonts/FiraMono-Medium.ttf"),
+                font_size,
+                color: HUD_COLOR,
+            },
+            TextAlignment {
+                horizontal,
+                vertical: VerticalAlign::Center,
+            },
+        ),
+        ..Default::default()
+    }
+}
+
+pub fn setup_hud(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn_bundle(hud_text(
+            &asset_server,
+            Rect {
+                bottom: Val::Percent(40.),
+                left: Val::Percent(22.),
+                ..Default::default()
+            },
+            18.,
+            HorizontalAlign::Right,
+        ))
+        .insert(FlightHud::default())
+        .insert(AirspeedTape);
+    commands
+        .spawn_bundle(hud_text(
+            &asset_server,
+            Rect {
+                bottom: Val::Percent(40.),
+                right: Val::Percent(22.),
+                ..Default::default()
+            },
+            18.,
+            HorizontalAlign::Left,
+        ))
+        .insert(FlightHud::default())
+        .insert(AltitudeTape);
+    commands
+        .spawn_bundle(hud_text(
+            &asset_server,
+            Rect {
+                top: Val::Percent(8.),
+                left: Val::Percent(38.),
+                ..Default::default()
+            },
+            18.,
+            HorizontalAlign::Center,
+        ))
+        .insert(FlightHud::default())
+        .insert(HeadingTape);
+    commands
+        .spawn_bundle(hud_text(
+            &asset_server,
+            Rect {
+                bottom: Val::Percent(22.),
+                left: Val::Percent(22.),
+                ..Default::default()
+            },
+            18.,
+            HorizontalAlign::Left,
+        ))
+        .insert(FlightHud::default())
+        .insert(FlightDataText);
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Px(8.), Val::Px(THROTTLE_BAR_HEIGHT)),
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    bottom: Val::Percent(22.),
+                    left: Val::Percent(18.),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            color: Color::rgba(0., 0.3, 0., 0.5).into(),
+            ..Default::default()
+        })
+        .insert(FlightHud::default())
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        size: Size::new(Val::Percent(100.), Val::Percent(0.)),
+                        position_type: PositionType::Absolute,
+                        position: Rect {
+                            left: Val::Px(0.),
+                            bottom: Val::Px(0.),
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    },
+                    color: HUD_COLOR.into(),
+                    ..Default::default()
+                })
+                .insert(ThrottleBar);
+        });
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Percent(50.),
+                    bottom: Val::Percent(50.),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            color: Color::NONE.into(),
+            ..Default::default()
+        })
+        .insert(FlightHud::default())
+        .insert(PitchLadder)
+        .with_children(|parent| {
+            for step in -90 / PITCH_LADDER_STEP..=90 / PITCH_LADDER_STEP {
+                let pitch = (step * PITCH_LADDER_STEP) as f32;
+                let (width, color) = if step == 0 {
+                    (RUNG_WIDTH * 2., HUD_COLOR)
+                } else if step > 0 {
+                    (RUNG_WIDTH, HUD_COLOR)
+                } else {
+                    (RUNG_WIDTH, Color::rgba(0., 1., 0., 0.5))
+                };
+                parent
+                    .spawn_bundle(NodeBundle {
+                        style: Style {
+                            position_type: PositionType::Absolute,
+                            position: Rect {
+                                left: Val::Px(-10000.),
+                                bottom: Val::Px(-10000.),
+                                ..Default::default()
+                            },
+                            ..Default::default()
+                        },
+                        color: Color::NONE.into(),
+                        ..Default::default()
+                    })
+                    .insert(PitchRung { pitch })
+                    .with_children(|rung| {
+                        line(rung, -RUNG_GAP - width, 0., width, 1., color);
+                        line(rung, RUNG_GAP, 0., width, 1., color);
+                        if step != 0 {
+                            rung.spawn_bundle(TextBundle {
+                                style: Style {
+                                    position_type: PositionType::Absolute,
+                                    position: Rect {
+                                        left: Val::Px(RUNG_GAP + width + 4.),
+                                        bottom: Val::Px(-8.),
+                                        ..Default::default()
+                                    },
+                                    ..Default::default()
+                                },
+                                text: Text::with_section(
+                                    format!("{}", pitch.abs() as i32),
+                                    TextStyle {
+                                        font: asset_server.load("fonts/FiraMono-Medium.ttf"),
+                                        font_size: 14.,
+                                        color,
+                                    },
+                                    Default::default(),
+                                ),
+                                ..Default::default()
+                            });
+                        }
+                    });
+            }
+        });
+
+    let half = FLIGHT_PATH_MARKER_SIZE / 2.;
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Px(-100.),
+                    bottom: Val::Px(-100.),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            color: Color::NONE.into(),
+            ..Default::default()
+        })
+        .insert(FlightHud::default())
+        .insert(FlightPathMarker)
+        .with_children(|parent| {
+            line(parent, -half, -half, FLIGHT_PATH_MARKER_SIZE, 1., HUD_COLOR);
+            line(parent, -half, half, FLIGHT_PATH_MARKER_SIZE, 1., HUD_COLOR);
+            line(parent, -half, -half, 1., FLIGHT_PATH_MARKER_SIZE, HUD_COLOR);
+            line(parent, half, -half, 1., FLIGHT_PATH_MARKER_SIZE, HUD_COLOR);
+            line(parent, -half - 10., 0., 10., 1., HUD_COLOR);
+            line(parent, half, 0., 10., 1., HUD_COLOR);
+            line(parent, 0., half, 1., 7., HUD_COLOR);
+        });
+}
+
+pub fn hide_flight_hud(mut hud_query: Query<(&mut FlightHud, &mut Style)>) {
+    for (mut hud, mut style) in hud_query.iter_mut() {
+        if hud.hidden_from.is_none() {
+            hud.hidden_from = Some(style.position);
+            style.position = Rect {
+                left: Val::Px(-10000.),
+                ..Default::default()
+            };
+        }
+    }
+}
+
+pub fn show_flight_hud(mut hud_query: Query<(&mut FlightHud, &mut Style)>) {
+    for (mut hud, mut style) in hud_query.iter_mut() {
+        if let Some(position) = hud.hidden_from.take() {
+            style.position = position;
+        }
+    }
+}
+
+pub fn hud_system(
+    player_query: Query<(&Transform, &RigidBodyVelocityComponent, &PlayerInput), With<Player>>,
+    camera_query: Query<&PerspectiveProjection, With<MainCamera>>,
+    mut text_query: QuerySet<(
+        QueryState<&mut Text, With<AirspeedTape>>,
+        QueryState<&mut Text, With<AltitudeTape>>,
+        QueryState<&mut Text, With<HeadingTape>>,
+        QueryState<&mut Text, With<FlightDataText>>,
+    )>,
+    mut node_query: QuerySet<(
+        QueryState<&mut Transform, (With<PitchLadder>, Without<Player>)>,
+        QueryState<(&mut Style, &PitchRung)>,
+        QueryState<&mut Style, With<FlightPathMarker>>,
+        QueryState<&mut Style, With<ThrottleBar>>,
+    )>,
+    mut last_velocity: Local<Option<Vec3>>,
+    mut max_g: Local<f32>,
+    windows: Res<Windows>,
+    time: Res<Time>,
+) {
+    let (player_transform, rb_vel, player_input) = match player_query.get_single() {
+        Ok(player) => player,
+        Err(_) => {
+            *last_velocity = None;
+            *max_g = 0.;
+            return;
+        }
+    };
+    let (window_width, window_height) = match windows.get_primary() {
+        Some(window) => (window.width(), window.height()),
+        None => return,
+    };
+    let fov = camera_query
+        .iter()
+        .next()
+        .map_or(std::f32::consts::FRAC_PI_3, |projection| projection.fov);
+
+    let velocity: Vec3 = rb_vel.linvel.into();
+    let acceleration = match *last_velocity {
+        Some(last_velocity) if time.delta_seconds() > 0. => {
+            (velocity - last_velocity) / time.delta_seconds()
+        }
+        _ => Vec3::ZERO,
+    };
+    *last_velocity = Some(velocity);
+
+    let model = hud_model(
+        player_transform.translation,
+        player_transform.rotation,
+        velocity,
+        acceleration,
+        player_input.accel,
+    );
+    *max_g = max_g.max(model.g_load);
+
+    for mut text in text_query.q0().iter_mut() {
+        text.sections[0].value = vertical_tape(model.airspeed, 50., 3);
+    }
+    for mut text in text_query.q1().iter_mut() {
+        text.sections[0].value = format!(
+            "{}\nVS {:+.0}",
+            vertical_tape(model.altitude, 100., 3),
+            model.vertical_speed
+        );
+    }
+    for mut text in text_query.q2().iter_mut() {
+        text.sections[0].value = heading_tape(model.heading, 15);
+    }
+    for mut text in text_query.q3().iter_mut() {
+        text.sections[0].value = format!(
+            "G {:.1}\nMAX {:.1}\nAOA {:.1}\nTHR {:.0}%{}",
+            model.g_load,
+            *max_g,
+            model.aoa,
+            model.throttle * 100.,
+            if player_input.brake > 0. { " BRK" } else { "" }
+        );
+    }
+
+    for mut transform in node_query.q0().iter_mut() {
+        transform.rotation = Quat::from_rotation_z(model.roll.to_radians());
+    }
+    for (mut style, rung) in node_query.q1().iter_mut() {
+        let offset = rung.pitch - model.pitch;
+        style.position = if offset.abs() <= PITCH_LADDER_VISIBLE {
+            Rect {
+                left: Val::Px(0.),
+                bottom: Val::Px(degrees_to_pixels(offset, fov, window_height)),
+                ..Default::default()
+            }
+        } else {
+            Rect {
+                left: Val::Px(-10000.),
+                bottom: Val::Px(-10000.),
+                ..Default::default()
+            }
+        };
+    }
+    let marker = Vec2::new(
+        degrees_to_pixels(model.flight_path.x, fov, window_height),
+        degrees_to_pixels(model.flight_path.y, fov, window_height),
+    )
+    .clamp(
+        -Vec2::new(window_width, window_height) / 2.,
+        Vec2::new(window_width, window_height) / 2.,
+    );
+    for mut style in node_query.q2().iter_mut() {
+        style.position = Rect {
+            left: Val::Px(window_width / 2. + marker.x),
+            bottom: Val::Px(window_height / 2. + marker.y),
+            ..Default::default()
+        };
+    }
+    for mut style in node_query.q3().iter_mut() {
+        style.size.height = Val::Percent(model.throttle * 100.);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-3,
+            "expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn level_flight() {
+        let model = hud_model(
+            Vec3::new(0., 500., 0.),
+            Quat::IDENTITY,
+            Vec3::X * 100.,
+            Vec3::ZERO,
+            0.5,
+        );
+        assert_close(model.pitch, 0.);
+        assert_close(model.roll, 0.);
+        assert_close(model.heading, 90.);
+        assert_close(model.airspeed, 360.);
+        assert_close(model.altitude, 500.);
+        assert_close(model.vertical_speed, 0.);
+        assert_close(model.aoa, 0.);
+        assert_close(model.g_load, 1.);
+        assert_close(model.throttle, 0.5);
+    }
+
+    #[test]
+    fn right_bank_is_positive_roll() {
+        let rotation = Quat::from_rotation_x(std::f32::consts::FRAC_PI_2);
+        let model = hud_model(Vec3::ZERO, rotation, Vec3::X * 100., Vec3::ZERO, 0.);
+        assert_close(model.roll, 90.);
+        assert_close(model.pitch, 0.);
+        // With the wings vertical gravity no longer loads the aircraft's up axis.
+        assert_close(model.g_load, 0.);
+    }
+
+    #[test]
+    fn climb() {
+        let rotation = Quat::from_rotation_z(10_f32.to_radians());
+        let velocity = rotation * Vec3::X * 100.;
+        let model = hud_model(Vec3::ZERO, rotation, velocity, Vec3::ZERO, 1.);
+        assert_close(model.pitch, 10.);
+        assert_close(model.roll, 0.);
+        assert_close(model.vertical_speed, velocity.y);
+        assert_close(model.flight_path.x, 0.);
+        assert_close(model.flight_path.y, 0.);
+    }
+
+    #[test]
+    fn angle_of_attack_from_sinking_flight_path() {
+        let rotation = Quat::from_rotation_z(5_f32.to_radians());
+        let model = hud_model(Vec3::ZERO, rotation, Vec3::X * 100., Vec3::ZERO, 1.);
+        assert_close(model.flight_path.y, -5.);
+        assert_close(model.aoa, 5.);
+    }
+
+    #[test]
+    fn g_load_in_pull_up() {
+        let model = hud_model(
+            Vec3::ZERO,
+            Quat::IDENTITY,
+            Vec3::X * 100.,
+            Vec3::Y * GRAVITY * 3.,
+            1.,
+        );
+        assert_close(model.g_load, 4.);
+    }
+
+    #[test]
+    fn heading_tape_wraps_through_north() {
+        for heading in [0., 359.] {
+            let tape = heading_tape(heading, 15);
+            let mut lines = tape.lines();
+            let scale = lines.next().unwrap();
+            assert_eq!(scale.chars().count(), 31);
+            assert!(scale.contains("350"), "{:?}", scale);
+            assert!(scale.contains("000"), "{:?}", scale);
+            assert!(scale.contains("010"), "{:?}", scale);
+            assert!(
+                !scale.contains("360") && !scale.contains('-'),
+                "{:?}",
+                scale
+            );
+            assert_eq!(lines.next().unwrap().find('^'), Some(15));
+        }
+    }
+
+    #[test]
+    fn heading_tape_centres_current_heading() {
+        let tape = heading_tape(0., 15);
+        let scale: Vec<char> = tape.lines().next().unwrap().chars().collect();
+        assert_eq!(scale[14..17].iter().collect::<String>(), "000");
+    }
+
+    #[test]
+    fn vertical_tape_boxes_value_between_marks() {
+        let tape = vertical_tape(1234., 100., 3);
+        let lines: Vec<&str> = tape.lines().collect();
+        assert_eq!(lines.len(), 7);
+        assert_eq!(lines[3], "[ 1234]");
+        assert_eq!(lines[0].trim(), "1500");
+        assert_eq!(lines[2].trim(), "1300");
+        assert_eq!(lines[4].trim(), "1100");
+        assert_eq!(lines[6].trim(), "900");
+    }
+}
@@ -1,22 +1,114 @@
 use bevy::{input::gamepad::GamepadButton, prelude::*};
+use serde::{Deserialize, Serialize};
 
-#[derive(Default)]
+/// Controls read this frame for one local player.
+#[derive(Default, Component)]
 pub struct PlayerInput {
     pub axis: Vec2,
     pub accel: f32,
     pub brake: f32,
     pub yaw: f32,
     pub camera_axis: Vec2,
+    pub fire_gun: bool,
+}
+
+/// Device driving a local player's `PlayerInput`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Component)]
+pub enum InputDevice {
+    Keyboard,
+    Gamepad(Gamepad),
+    Unassigned,
+}
+
+impl InputDevice {
+    /// Whether this device pressed its `key` or gamepad `button` this frame.
+    pub fn just_pressed(
+        &self,
+        keyboard_input: &Input<KeyCode>,
+        key: KeyCode,
+        button_inputs: &Input<GamepadButton>,
+        button: GamepadButtonType,
+    ) -> bool {
+        match self {
+            InputDevice::Keyboard => keyboard_input.just_pressed(key),
+            InputDevice::Gamepad(gamepad) => {
+                button_inputs.just_pressed(GamepadButton(*gamepad, button))
+            }
+            InputDevice::Unassigned => false,
+        }
+    }
+}
+
+/// Device for the local player in `slot`, given the connected gamepads in connection order.
+/// Each player takes a gamepad of their own, and the first player left without one gets the
+/// keyboard.
+pub fn input_device(slot: usize, gamepads: &[Gamepad]) -> InputDevice {
+    match gamepads.get(slot) {
+        Some(gamepad) => InputDevice::Gamepad(*gamepad),
+        None if slot == gamepads.len() => InputDevice::Keyboard,
+        None => InputDevice::Unassigned,
+    }
+}
+
+/// Keyboard controls, stored in the save file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeyBindings {
+    pub roll_left: KeyCode,
+    pub roll_right: KeyCode,
+    pub pitch_up: KeyCode,
+    pub pitch_down: KeyCode,
+    pub throttle: KeyCode,
+    pub brake: KeyCode,
+    pub fire_gun: KeyCode,
+    pub flare: KeyCode,
+    pub chaff: KeyCode,
+    pub wingman_attack: KeyCode,
+    pub wingman_cover: KeyCode,
+    pub wingman_rejoin: KeyCode,
+    pub cycle_formation: KeyCode,
+    pub radar_range: KeyCode,
+    pub radar_mode: KeyCode,
+    pub map: KeyCode,
+    pub camera_mode: KeyCode,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        KeyBindings {
+            roll_left: KeyCode::Left,
+            roll_right: KeyCode::Right,
+            pitch_up: KeyCode::Up,
+            pitch_down: KeyCode::Down,
+            throttle: KeyCode::Space,
+            brake: KeyCode::LShift,
+            fire_gun: KeyCode::LControl,
+            flare: KeyCode::F,
+            chaff: KeyCode::G,
+            wingman_attack: KeyCode::F1,
+            wingman_cover: KeyCode::F2,
+            wingman_rejoin: KeyCode::F3,
+            cycle_formation: KeyCode::F4,
+            radar_range: KeyCode::R,
+            radar_mode: KeyCode::N,
+            map: KeyCode::M,
+            camera_mode: KeyCode::C,
+        }
+    }
 }
 
 pub fn gamepad_system(
-    gamepads: Res<Gamepads>,
-    _button_inputs: Res<Input<GamepadButton>>,
+    button_inputs: Res<Input<GamepadButton>>,
     button_axes: Res<Axis<GamepadButton>>,
     axes: Res<Axis<GamepadAxis>>,
-    mut player_input: ResMut<PlayerInput>,
+    mut player_query: Query<(&InputDevice, &mut PlayerInput)>,
 ) {
-    for gamepad in gamepads.iter().cloned() {
+    for (device, mut player_input) in player_query.iter_mut() {
+        let gamepad = match device {
+            InputDevice::Gamepad(gamepad) => *gamepad,
+            _ => continue,
+        };
+
         let right_trigger = button_axes
             .get(GamepadButton(gamepad, GamepadButtonType::RightTrigger2))
             .unwrap();
@@ -71,5 +163,8 @@ pub fn gamepad_system(
         }
 
         player_input.yaw = left_shoulder - right_shoulder;
+
+        player_input.fire_gun =
+            button_inputs.pressed(GamepadButton(gamepad, GamepadButtonType::West));
     }
 }
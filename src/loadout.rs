@@ -0,0 +1,263 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use super::countermeasures::SeekerKind;
+use super::crash::*;
+use super::guidance::*;
+use super::input::*;
+use super::player::*;
+
+pub const LOADOUTS_PATH: &str = "assets/loadouts.ron";
+pub const PLAYER_LOADOUT: &str = "f35_standard";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+pub enum WeaponType {
+    Missile,
+    Gun,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct HardpointDef {
+    pub weapon: WeaponType,
+    pub capacity: u32,
+    pub offset: [f32; 3],
+    pub cycle_time: f32,
+    pub reload_time: Option<f32>,
+    #[serde(default)]
+    pub guidance: GuidanceLaw,
+    #[serde(default)]
+    pub seeker: SeekerKind,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct LoadoutDef {
+    pub hardpoints: Vec<HardpointDef>,
+    pub fuel_capacity: f32,
+    pub fuel_burn_rate: f32,
+    pub idle_fuel_burn_rate: f32,
+}
+
+impl Default for LoadoutDef {
+    fn default() -> Self {
+        let hardpoint = |z: f32| HardpointDef {
+            weapon: WeaponType::Missile,
+            capacity: 4,
+            offset: [0., -0.25, z],
+            cycle_time: 0.5,
+            reload_time: None,
+            guidance: GuidanceLaw::default(),
+            seeker: SeekerKind::default(),
+        };
+        LoadoutDef {
+            hardpoints: vec![
+                hardpoint(-0.6),
+                hardpoint(0.6),
+                HardpointDef {
+                    weapon: WeaponType::Gun,
+                    capacity: 500,
+                    offset: [1.2, 0.1, 0.2],
+                    cycle_time: 0.02,
+                    reload_time: None,
+                    guidance: GuidanceLaw::default(),
+                    seeker: SeekerKind::default(),
+                },
+            ],
+            fuel_capacity: 3000.,
+            fuel_burn_rate: 12.,
+            idle_fuel_burn_rate: 1.,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Loadouts {
+    pub definitions: HashMap<String, LoadoutDef>,
+}
+
+impl Loadouts {
+    pub fn load(filename: &str) -> Self {
+        let definitions = std::fs::read_to_string(filename)
+            .map_err(|e| e.to_string())
+            .and_then(|contents| ron::from_str(&contents).map_err(|e| e.to_string()));
+
+        match definitions {
+            Ok(definitions) => Loadouts { definitions },
+            Err(e) => {
+                println!("Failed to load {}: {}", filename, e);
+                Loadouts::default()
+            }
+        }
+    }
+
+    pub fn get(&self, name: &str) -> LoadoutDef {
+        self.definitions.get(name).cloned().unwrap_or_else(|| {
+            println!("Unknown loadout {}, using default", name);
+            LoadoutDef::default()
+        })
+    }
+}
+
+pub struct Hardpoint {
+    pub weapon: WeaponType,
+    pub count: u32,
+    pub capacity: u32,
+    pub offset: Vec3,
+    pub cycle_time: f32,
+    pub reload_time: Option<f32>,
+    pub guidance: GuidanceLaw,
+    pub seeker: SeekerKind,
+    pub cooldown: f32,
+    pub reload_progress: f32,
+}
+
+impl Hardpoint {
+    pub fn is_ready(&self) -> bool {
+        self.count > 0 && self.cooldown <= 0.
+    }
+}
+
+#[derive(Component)]
+pub struct Loadout {
+    pub hardpoints: Vec<Hardpoint>,
+}
+
+impl Loadout {
+    pub fn from_def(def: &LoadoutDef) -> Self {
+        Loadout {
+            hardpoints: def
+                .hardpoints
+                .iter()
+                .map(|hardpoint| Hardpoint {
+                    weapon: hardpoint.weapon,
+                    count: hardpoint.capacity,
+                    capacity: hardpoint.capacity,
+                    offset: Vec3::from(hardpoint.offset),
+                    cycle_time: hardpoint.cycle_time,
+                    reload_time: hardpoint.reload_time,
+                    guidance: hardpoint.guidance,
+                    seeker: hardpoint.seeker,
+                    cooldown: 0.,
+                    reload_progress: 0.,
+                })
+                .collect(),
+        }
+    }
+
+    pub fn count(&self, weapon: WeaponType) -> u32 {
+        self.hardpoints
+            .iter()
+            .filter(|hardpoint| hardpoint.weapon == weapon)
+            .map(|hardpoint| hardpoint.count)
+            .sum()
+    }
+
+    pub fn capacity(&self, weapon: WeaponType) -> u32 {
+        self.hardpoints
+            .iter()
+            .filter(|hardpoint| hardpoint.weapon == weapon)
+            .map(|hardpoint| hardpoint.capacity)
+            .sum()
+    }
+
+    /// Takes one round from the fullest ready hardpoint and returns that hardpoint.
+    pub fn take(&mut self, weapon: WeaponType) -> Option<&Hardpoint> {
+        let hardpoint = self
+            .hardpoints
+            .iter_mut()
+            .filter(|hardpoint| hardpoint.weapon == weapon && hardpoint.is_ready())
+            .max_by_key(|hardpoint| hardpoint.count)?;
+
+        hardpoint.count -= 1;
+        hardpoint.cooldown = hardpoint.cycle_time;
+        Some(hardpoint)
+    }
+
+    pub fn rearm(&mut self) {
+        for hardpoint in self.hardpoints.iter_mut() {
+            hardpoint.count = hardpoint.capacity;
+            hardpoint.cooldown = 0.;
+            hardpoint.reload_progress = 0.;
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct Fuel {
+    pub amount: f32,
+    pub capacity: f32,
+    pub burn_rate: f32,
+    pub idle_burn_rate: f32,
+}
+
+impl Fuel {
+    pub fn from_def(def: &LoadoutDef) -> Self {
+        Fuel {
+            amount: def.fuel_capacity,
+            capacity: def.fuel_capacity,
+            burn_rate: def.fuel_burn_rate,
+            idle_burn_rate: def.idle_fuel_burn_rate,
+        }
+    }
+
+    pub fn burn(&mut self, throttle: f32, delta_seconds: f32) {
+        let rate = self.idle_burn_rate + throttle.clamp(0., 1.) * self.burn_rate;
+        self.amount = (self.amount - rate * delta_seconds).max(0.);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.amount <= 0.
+    }
+
+    pub fn fraction(&self) -> f32 {
+        if self.capacity <= 0. {
+            0.
+        } else {
+            self.amount / self.capacity
+        }
+    }
+
+    pub fn refuel(&mut self) {
+        self.amount = self.capacity;
+    }
+}
+
+pub fn reload_hardpoints(mut loadout_query: Query<&mut Loadout>, time: Res<Time>) {
+    for mut loadout in loadout_query.iter_mut() {
+        for hardpoint in loadout.hardpoints.iter_mut() {
+            hardpoint.cooldown = (hardpoint.cooldown - time.delta_seconds()).max(0.);
+
+            if let Some(reload_time) = hardpoint.reload_time {
+                if hardpoint.count < hardpoint.capacity {
+                    hardpoint.reload_progress += time.delta_seconds();
+                    if hardpoint.reload_progress >= reload_time {
+                        hardpoint.reload_progress = 0.;
+                        hardpoint.count += 1;
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub fn burn_fuel(
+    mut fuel_query: Query<(&PlayerInput, &mut Fuel), (With<Player>, Without<Crashed>)>,
+    time: Res<Time>,
+) {
+    for (player_input, mut fuel) in fuel_query.iter_mut() {
+        fuel.burn(player_input.accel, time.delta_seconds());
+    }
+}
+
+pub fn rearm_on_respawn(
+    mut respawn_events: EventReader<PlayerRespawned>,
+    mut query: Query<(&mut Loadout, &mut Fuel)>,
+) {
+    for event in respawn_events.iter() {
+        if let Ok((mut loadout, mut fuel)) = query.get_mut(event.entity) {
+            loadout.rearm();
+            fuel.refuel();
+        }
+    }
+}
@@ -1,22 +1,83 @@
 use bevy::{core::FixedTimestep, pbr::AmbientLight, prelude::*};
 use bevy_rapier3d::prelude::*;
 
+mod ai;
+mod camera;
+mod countermeasures;
+mod crash;
+mod damage;
+mod faction;
+mod guidance;
+mod gun;
+mod hud;
 mod input;
+mod loadout;
+mod map;
+mod menu;
+mod mission;
+mod navigation;
 // mod particles;
 mod player;
+mod radar;
+mod replay;
+mod save;
+mod sensor;
 mod sky;
+mod stats;
 mod terrain;
 mod ui;
+mod warning;
+mod weapons;
+mod wingman;
 
+use ai::*;
+use camera::*;
+use countermeasures::*;
+use crash::*;
+use damage::*;
+use faction::*;
+use guidance::*;
+use gun::*;
+use hud::*;
 use input::*;
+use loadout::*;
+use map::*;
+use menu::*;
+use mission::*;
+use navigation::*;
 // use particles::*;
 use player::*;
+use radar::*;
+use replay::*;
+use save::*;
+use sensor::*;
 use sky::*;
+use stats::*;
 use terrain::*;
 use ui::*;
+use warning::*;
+use weapons::*;
+use wingman::*;
 
 const PLAYER_MOVEMENT_LABEL: &str = "player_movement";
+const ASSIGN_INPUT_DEVICES_LABEL: &str = "assign_input_devices";
 const FIRE_MISSILE_LABEL: &str = "fire_missile";
+const FIRE_GUN_LABEL: &str = "fire_gun";
+const CRASH_DETECTION_LABEL: &str = "crash_detection";
+const APPLY_DAMAGE_LABEL: &str = "apply_damage";
+const MISSILE_WARNING_LABEL: &str = "missile_warning";
+const DRONE_AI_LABEL: &str = "drone_ai";
+const WINGMAN_COMMANDS_LABEL: &str = "wingman_commands";
+const WINGMAN_ORDERS_LABEL: &str = "wingman_orders";
+const MISSION_CASUALTIES_LABEL: &str = "mission_casualties";
+const ADVANCE_WAYPOINTS_LABEL: &str = "advance_waypoints";
+const WEAPON_FIRE_LABEL: &str = "weapon_fire";
+const SENSOR_UPDATE_LABEL: &str = "sensor_update";
+const MAP_CONTROLS_LABEL: &str = "map_controls";
+const REPLAY_CONTROLS_LABEL: &str = "replay_controls";
+const REPLAY_PLAYBACK_LABEL: &str = "replay_playback";
+
+pub const DRONE_LOADOUT: &str = "drone_light";
 
 fn main() {
     App::new()
@@ -26,37 +87,261 @@ fn main() {
             ..Default::default()
         })
         .insert_resource(ClearColor(Color::rgb(0.3, 0.56, 0.83)))
-        .insert_resource(PlayerInput::default())
         .init_resource::<UiTargets>()
+        .init_resource::<MissileWarning>()
+        .add_event::<WarningTone>()
+        .init_resource::<CrashSettings>()
+        .insert_resource(MissionList::load(MISSIONS_DIR))
+        .init_resource::<SpawnPoint>()
+        .insert_resource(MissionState::new(&MissionDef::default()))
+        .init_resource::<Navigation>()
+        .init_resource::<MissionDef>()
+        .init_resource::<LastSortie>()
+        .init_resource::<SortieStats>()
+        .add_event::<MissionMessage>()
+        .add_event::<MissionEnded>()
+        .insert_resource(Loadouts::load(LOADOUTS_PATH))
+        .add_event::<PlayerCrashed>()
+        .add_event::<PlayerRespawned>()
+        .add_event::<DamageEvent>()
+        .add_event::<AircraftDestroyed>()
+        .add_event::<FireWeapon>()
+        .add_event::<WeaponFired>()
+        .init_resource::<Difficulty>()
+        .init_resource::<KeyBindings>()
+        .init_resource::<RadarSettings>()
+        .init_resource::<MapView>()
+        .init_resource::<CameraSettings>()
+        .init_resource::<CameraState>()
+        .init_resource::<ReplayRecorder>()
+        .init_resource::<ReplayViewer>()
+        .insert_resource(SaveGame::load(&save_path()))
+        .init_resource::<Formation>()
+        .add_event::<WingmanOrder>()
         .add_plugins(DefaultPlugins)
         .add_plugin(RapierPhysicsPlugin::<NoUserData>::default())
         .add_plugin(SkyBoxPlugin)
+        .add_state(AppState::MainMenu)
         .add_startup_system(setup.system())
         .add_startup_system(setup_terrain.system())
         .add_startup_system(setup_ui.system())
-        .add_startup_system(setup_player.system())
-        .add_system(player_input.system())
-        .add_system(player_movement.system().label(PLAYER_MOVEMENT_LABEL))
+        .add_startup_system(setup_hud.system())
+        .add_startup_system(setup_map.system())
+        .add_startup_system(setup_replay_ui.system())
+        .add_startup_system(setup_menu.system())
+        .add_startup_system(setup_camera.system())
+        .add_startup_system(setup_gun.system())
+        .add_startup_system(setup_countermeasures.system())
+        .add_startup_system(setup_weapons.system())
+        .add_startup_system(setup_warning_tones.system())
+        .add_system(assign_input_devices.system().label(ASSIGN_INPUT_DEVICES_LABEL))
+        .add_system(player_input.system().after(ASSIGN_INPUT_DEVICES_LABEL))
+        .add_system(gamepad_system.system().after(ASSIGN_INPUT_DEVICES_LABEL))
+        .add_system(menu_overlay_system.system())
+        .add_system(apply_settings.system())
+        .add_system(build_map_image.system())
+        .add_system(camera_controls.system())
         .add_system_to_stage(
             bevy_rapier3d::physics::PhysicsStages::SyncTransforms,
-            camera_follow_player
+            update_camera
                 .system()
                 .after(bevy_rapier3d::physics::PhysicsSystems::SyncTransforms),
         )
-        .add_system(text_update_system.system())
-        .add_system(gamepad_system.system())
-        .add_system(target_ui.system())
-        .add_system(fire_missle.system().label(FIRE_MISSILE_LABEL))
-        .add_system(missle_run.system().after(FIRE_MISSILE_LABEL))
-        .add_system(radar.system())
-        .add_system(drone_movement.system())
+        .add_system_set(
+            SystemSet::on_enter(AppState::MainMenu).with_system(cleanup_mission.system()),
+        )
+        .add_system_set(SystemSet::on_update(AppState::MainMenu).with_system(main_menu.system()))
+        .add_system_set(
+            SystemSet::on_update(AppState::MissionSelect).with_system(mission_select.system()),
+        )
+        .add_system_set(
+            SystemSet::on_enter(AppState::Briefing).with_system(cleanup_mission.system()),
+        )
+        .add_system_set(SystemSet::on_update(AppState::Briefing).with_system(briefing.system()))
+        .add_system_set(
+            SystemSet::on_enter(AppState::InFlight)
+                .with_system(setup_player.system())
+                .with_system(setup_mission.system())
+                .with_system(reset_stats.system())
+                .with_system(reset_camera.system())
+                .with_system(start_recording.system())
+                .with_system(resume_physics.system()),
+        )
+        .add_system_set(
+            SystemSet::on_resume(AppState::InFlight).with_system(resume_physics.system()),
+        )
+        .add_system_set(SystemSet::on_pause(AppState::InFlight).with_system(close_map.system()))
+        .add_system_set(SystemSet::on_exit(AppState::InFlight).with_system(close_map.system()))
+        .add_system_set(
+            SystemSet::on_update(AppState::InFlight)
+                .with_system(pause_game.system())
+                .with_system(end_mission.system())
+                .with_system(player_movement.system().label(PLAYER_MOVEMENT_LABEL))
+                .with_system(hud_system.system().after(PLAYER_MOVEMENT_LABEL))
+                .with_system(sensor_update.system().label(SENSOR_UPDATE_LABEL))
+                .with_system(target_ui.system().after(SENSOR_UPDATE_LABEL))
+                .with_system(fire_missle.system().label(FIRE_MISSILE_LABEL))
+                .with_system(missle_run.system().after(WEAPON_FIRE_LABEL))
+                .with_system(radar_controls.system())
+                .with_system(radar.system().after(SENSOR_UPDATE_LABEL))
+                .with_system(map_controls.system().label(MAP_CONTROLS_LABEL))
+                .with_system(
+                    map_view
+                        .system()
+                        .after(MAP_CONTROLS_LABEL)
+                        .after(SENSOR_UPDATE_LABEL),
+                )
+                .with_system(
+                    drone_ai
+                        .system()
+                        .label(DRONE_AI_LABEL)
+                        .after(SENSOR_UPDATE_LABEL),
+                )
+                .with_system(wingman_commands.system().label(WINGMAN_COMMANDS_LABEL))
+                .with_system(
+                    apply_wingman_orders
+                        .system()
+                        .label(WINGMAN_ORDERS_LABEL)
+                        .after(WINGMAN_COMMANDS_LABEL),
+                )
+                .with_system(
+                    wingman_ai
+                        .system()
+                        .label(DRONE_AI_LABEL)
+                        .after(WINGMAN_ORDERS_LABEL)
+                        .after(SENSOR_UPDATE_LABEL),
+                )
+                .with_system(drone_movement.system().after(DRONE_AI_LABEL))
+                .with_system(
+                    weapon_fire_system
+                        .system()
+                        .label(WEAPON_FIRE_LABEL)
+                        .after(FIRE_MISSILE_LABEL)
+                        .after(FIRE_GUN_LABEL)
+                        .after(DRONE_AI_LABEL),
+                )
+                .with_system(
+                    crash_detection
+                        .system()
+                        .label(CRASH_DETECTION_LABEL)
+                        .after(APPLY_DAMAGE_LABEL),
+                )
+                .with_system(start_crash_sequence.system().after(CRASH_DETECTION_LABEL))
+                .with_system(run_crash_sequence.system())
+                .with_system(invulnerability_timer.system())
+                .with_system(collision_damage.system())
+                .with_system(apply_damage.system().label(APPLY_DAMAGE_LABEL))
+                .with_system(drone_destroyed.system().after(APPLY_DAMAGE_LABEL))
+                .with_system(repair_on_respawn.system())
+                .with_system(reload_hardpoints.system())
+                .with_system(burn_fuel.system())
+                .with_system(rearm_on_respawn.system())
+                .with_system(loadout_text_system.system())
+                .with_system(track_target_velocity.system())
+                .with_system(fire_gun.system().label(FIRE_GUN_LABEL))
+                .with_system(bullet_run.system().after(WEAPON_FIRE_LABEL))
+                .with_system(gun_pipper.system())
+                .with_system(player_countermeasures.system())
+                .with_system(ai_countermeasures.system())
+                .with_system(countermeasures_cooldown.system())
+                .with_system(decoy_run.system())
+                .with_system(decoy_seduction.system())
+                .with_system(refill_on_respawn.system())
+                .with_system(missile_warning_system.system().label(MISSILE_WARNING_LABEL))
+                .with_system(missile_warning_ui.system().after(MISSILE_WARNING_LABEL))
+                .with_system(play_warning_tone.system().after(MISSILE_WARNING_LABEL))
+                .with_system(select_target.system().after(SENSOR_UPDATE_LABEL))
+                .with_system(
+                    mission_casualties
+                        .system()
+                        .label(MISSION_CASUALTIES_LABEL)
+                        .after(APPLY_DAMAGE_LABEL),
+                )
+                .with_system(mission_runtime.system().after(MISSION_CASUALTIES_LABEL))
+                .with_system(mission_text_system.system())
+                .with_system(advance_waypoints.system().label(ADVANCE_WAYPOINTS_LABEL))
+                .with_system(navigation_ui.system().after(ADVANCE_WAYPOINTS_LABEL))
+                .with_system(combat_stats.system())
+                .with_system(record_replay.system())
+                .with_system(flight_stats.system()),
+        )
+        .add_system_set(SystemSet::on_enter(AppState::Paused).with_system(freeze_physics.system()))
+        .add_system_set(SystemSet::on_update(AppState::Paused).with_system(paused_menu.system()))
+        .add_system_set(
+            SystemSet::on_enter(AppState::Debrief)
+                .with_system(freeze_physics.system())
+                .with_system(write_sortie_report.system())
+                .with_system(save_progress.system())
+                .with_system(save_replay.system()),
+        )
+        .add_system_set(SystemSet::on_update(AppState::Debrief).with_system(debrief.system()))
+        .add_system_set(
+            SystemSet::on_enter(AppState::Replay)
+                .with_system(cleanup_mission.system())
+                .with_system(freeze_physics.system())
+                .with_system(hide_flight_hud.system()),
+        )
+        .add_system_set(
+            SystemSet::on_update(AppState::Replay)
+                .with_system(replay_controls.system().label(REPLAY_CONTROLS_LABEL))
+                .with_system(
+                    replay_playback
+                        .system()
+                        .label(REPLAY_PLAYBACK_LABEL)
+                        .after(REPLAY_CONTROLS_LABEL),
+                )
+                .with_system(replay_camera.system().after(REPLAY_PLAYBACK_LABEL))
+                .with_system(replay_ui.system().after(REPLAY_CONTROLS_LABEL)),
+        )
+        .add_system_set(
+            SystemSet::on_exit(AppState::Replay)
+                .with_system(exit_replay.system())
+                .with_system(show_flight_hud.system()),
+        )
         .run();
 }
 
 #[derive(Component)]
 pub struct Drone;
 
-fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+pub fn drone_collider(transform: &Transform) -> ColliderBundle {
+    ColliderBundle {
+        shape: ColliderShapeComponent(aircraft_collider_shape()),
+        position: ColliderPositionComponent(
+            Isometry::from_parts(transform.translation.into(), transform.rotation.into()).into(),
+        ),
+        ..Default::default()
+    }
+}
+
+pub fn spawn_drone(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    loadouts: &Loadouts,
+    transform: Transform,
+    faction: Faction,
+) -> Entity {
+    commands
+        .spawn_bundle((transform, GlobalTransform::identity()))
+        .with_children(|parent| {
+            parent.spawn_scene(asset_server.load("f35.gltf#Scene0"));
+        })
+        .insert(Target)
+        .insert(TargetVelocity::default())
+        .insert(Drone)
+        .insert(faction)
+        .insert(DroneAi::default())
+        .insert(DroneControl::default())
+        .insert(AircraftDamage::default())
+        .insert(Loadout::from_def(&loadouts.get(DRONE_LOADOUT)))
+        .insert(Countermeasures::new(DRONE_FLARES, DRONE_CHAFF))
+        .insert(Sensor::drone())
+        .insert(SensorContacts::default())
+        .insert_bundle(drone_collider(&transform))
+        .id()
+}
+
+fn setup(mut commands: Commands) {
     commands.insert_resource(AmbientLight {
         color: Color::WHITE,
         brightness: 0.01,
@@ -70,45 +355,4 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
         transform: Transform::from_rotation(Quat::from_rotation_x(- std::f32::consts::FRAC_PI_8)),
         ..Default::default()
     });
-
-    commands
-        .spawn_bundle((
-            Transform::from_translation(Vec3::new(50.0, 300.0, 0.0)),
-            GlobalTransform::identity(),
-        ))
-        .with_children(|parent| {
-            parent.spawn_scene(asset_server.load("f35.gltf#Scene0"));
-        })
-        .insert(Target)
-        .insert(Drone);
-
-    commands
-        .spawn_bundle((
-            Transform::from_translation(Vec3::new(0.0, 350.0, -50.0)),
-            GlobalTransform::identity(),
-        ))
-        .with_children(|parent| {
-            parent.spawn_scene(asset_server.load("f35.gltf#Scene0"));
-        })
-        .insert(Target)
-        .insert(Drone);
-}
-
-fn drone_movement(mut drone_query: Query<(&mut Transform, &Drone)>, timer: Res<Time>) {
-    for (mut drone_transform, _) in drone_query.iter_mut() {
-        let pitch_delta = 0. * timer.delta_seconds() * PITCH_SPEED;
-        let roll_delta = 0. * timer.delta_seconds() * ROLL_SPEED;
-        let yaw_delta = 0.25 * timer.delta_seconds() * YAW_SPEED;
-
-        let ypr_rotation = Quat::from_rotation_x(roll_delta)
-            * Quat::from_rotation_y(yaw_delta)
-            * Quat::from_rotation_z(pitch_delta);
-
-        let velocity = 190.;
-
-        drone_transform.rotation = drone_transform.rotation * ypr_rotation;
-
-        drone_transform.translation = drone_transform.translation
-            + (drone_transform.rotation * Vec3::X * velocity * timer.delta_seconds());
-    }
 }
@@ -0,0 +1,498 @@
+use bevy::{
+    input::mouse::{MouseMotion, MouseScrollUnit, MouseWheel},
+    prelude::*,
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+};
+
+use super::faction::*;
+use super::input::*;
+use super::mission::*;
+use super::navigation::*;
+use super::player::*;
+use super::sensor::*;
+use super::terrain::*;
+
+pub const MAP_MIN_ZOOM: f32 = 1.;
+pub const MAP_MAX_ZOOM: f32 = 8.;
+/// Zoom factor applied per mouse wheel line or key press.
+pub const MAP_ZOOM_STEP: f32 = 1.25;
+pub const MAP_AREA_RING_DOTS: usize = 32;
+const MAP_FONT_SIZE: f32 = 14.;
+
+/// Colours from sea level to the highest peak.
+const HYPSOMETRIC_TINTS: [(f32, [f32; 3]); 5] = [
+    (0., [0.34, 0.54, 0.31]),
+    (0.25, [0.56, 0.68, 0.39]),
+    (0.5, [0.79, 0.73, 0.49]),
+    (0.75, [0.62, 0.5, 0.37]),
+    (1., [0.95, 0.95, 0.95]),
+];
+const WATER_TINT: [f32; 3] = [0.13, 0.24, 0.46];
+/// Hillshade light, from the north-west and 45 degrees up.
+const HILLSHADE_AZIMUTH: f32 = 315.;
+const HILLSHADE_ALTITUDE: f32 = 45.;
+/// Brightness of slopes facing away from the light.
+const HILLSHADE_AMBIENT: f32 = 0.35;
+
+#[derive(Component)]
+pub struct TacticalMap;
+
+#[derive(Component)]
+pub struct MapImage;
+
+#[derive(Component)]
+pub struct MapLabel;
+
+#[derive(Component)]
+pub struct MapMarker;
+
+pub struct MapView {
+    pub open: bool,
+    /// Map coordinates at the centre of the screen.
+    pub center: Vec2,
+    /// 1 fits the whole terrain to the screen height.
+    pub zoom: f32,
+}
+
+impl Default for MapView {
+    fn default() -> Self {
+        MapView {
+            open: false,
+            center: Vec2::ZERO,
+            zoom: MAP_MIN_ZOOM,
+        }
+    }
+}
+
+impl MapView {
+    pub fn pixels_per_metre(&self, screen_size: Vec2) -> f32 {
+        self.zoom * screen_size.y / (TERRAIN_HALF_SIZE * 2.)
+    }
+
+    /// Screen position of a map coordinate, from the bottom left of the screen.
+    pub fn to_screen(&self, map_position: Vec2, screen_size: Vec2) -> Vec2 {
+        screen_size / 2. + (map_position - self.center) * self.pixels_per_metre(screen_size)
+    }
+
+    /// Moves the view by `delta` screen pixels, keeping the centre over the terrain.
+    pub fn pan(&mut self, delta: Vec2, screen_size: Vec2) {
+        self.center -= delta / self.pixels_per_metre(screen_size);
+        self.center = self.center.clamp(
+            Vec2::splat(-TERRAIN_HALF_SIZE),
+            Vec2::splat(TERRAIN_HALF_SIZE),
+        );
+    }
+
+    pub fn zoom_by(&mut self, factor: f32) {
+        self.zoom = (self.zoom * factor).clamp(MAP_MIN_ZOOM, MAP_MAX_ZOOM);
+    }
+}
+
+/// Map coordinates of a world position: east along x and north along y.
+pub fn map_coordinates(position: Vec3) -> Vec2 {
+    Vec2::new(position.dot(EAST), position.dot(NORTH))
+}
+
+/// Colour for terrain `fraction` of the way from sea level to the highest peak.
+pub fn hypsometric_tint(fraction: f32) -> [f32; 3] {
+    let fraction = fraction.clamp(0., 1.);
+    for pair in HYPSOMETRIC_TINTS.windows(2) {
+        let (low, low_color) = pair[0];
+        let (high, high_color) = pair[1];
+        if fraction <= high {
+            let t = (fraction - low) / (high - low);
+            return [
+                low_color[0] + (high_color[0] - low_color[0]) * t,
+                low_color[1] + (high_color[1] - low_color[1]) * t,
+                low_color[2] + (high_color[2] - low_color[2]) * t,
+            ];
+        }
+    }
+    HYPSOMETRIC_TINTS[HYPSOMETRIC_TINTS.len() - 1].1
+}
+
+/// Brightness of a surface with `normal` under the hillshade light.
+pub fn hillshade(normal: Vec3) -> f32 {
+    let azimuth = HILLSHADE_AZIMUTH.to_radians();
+    let altitude = HILLSHADE_ALTITUDE.to_radians();
+    let towards_light =
+        (NORTH * azimuth.cos() + EAST * azimuth.sin()) * altitude.cos() + Vec3::Y * altitude.sin();
+    let lit = normal.normalize_or_zero().dot(towards_light).max(0.);
+    HILLSHADE_AMBIENT + (1. - HILLSHADE_AMBIENT) * lit
+}
+
+/// Top-down RGBA image of the terrain, one pixel per vertex with north at the top. Land is tinted
+/// by height and shaded by slope; anything below `WATER_LEVEL` is drawn as water.
+pub fn terrain_map_image(terrain: &TerrainHeights) -> Vec<u8> {
+    let (width, length) = (terrain.width, terrain.length);
+    if width == 0 || length == 0 || terrain.heights.len() != width * length {
+        return Vec::new();
+    }
+    let height_at = |x: usize, z: usize| terrain.heights[z * width + x];
+    let highest = terrain
+        .heights
+        .iter()
+        .fold(WATER_LEVEL + 1., |highest, height| highest.max(*height));
+
+    let mut pixels = Vec::with_capacity(width * length * 4);
+    for z in 0..length {
+        for x in 0..width {
+            let height = height_at(x, z);
+            let color = if height < WATER_LEVEL {
+                WATER_TINT
+            } else {
+                let (west, east) = (x.saturating_sub(1), (x + 1).min(width - 1));
+                let (north, south) = (z.saturating_sub(1), (z + 1).min(length - 1));
+                let slope_x = (height_at(east, z) - height_at(west, z))
+                    / ((east - west).max(1) as f32 * terrain.spacing);
+                let slope_z = (height_at(x, south) - height_at(x, north))
+                    / ((south - north).max(1) as f32 * terrain.spacing);
+                let shade = hillshade(Vec3::new(-slope_x, 1., -slope_z));
+                let tint = hypsometric_tint((height - WATER_LEVEL) / (highest - WATER_LEVEL));
+                [tint[0] * shade, tint[1] * shade, tint[2] * shade]
+            };
+            pixels.extend(color.iter().map(|channel| (channel * 255.) as u8));
+            pixels.push(255);
+        }
+    }
+    pixels
+}
+
+/// Points around the edge of a circular area, in map coordinates.
+pub fn area_ring(center: Vec3, radius: f32) -> Vec<Vec2> {
+    let center = map_coordinates(center);
+    (0..MAP_AREA_RING_DOTS)
+        .map(|i| {
+            let angle = i as f32 / MAP_AREA_RING_DOTS as f32 * std::f32::consts::TAU;
+            center + Vec2::new(angle.cos(), angle.sin()) * radius
+        })
+        .collect()
+}
+
+pub fn setup_map(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Px(-10000.),
+                    ..Default::default()
+                },
+                overflow: Overflow::Hidden,
+                ..Default::default()
+            },
+            color: Color::rgb(0.05, 0.08, 0.12).into(),
+            ..Default::default()
+        })
+        .insert(TacticalMap)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(ImageBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .insert(MapImage);
+            parent
+                .spawn_bundle(TextBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        position: Rect {
+                            left: Val::Px(10.),
+                            top: Val::Px(10.),
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    },
+                    text: Text::with_section(
+                        "",
+                        TextStyle {
+                            font: asset_server.load("fonts/FiraMono-Medium.ttf"),
+                            font_size: 20.0,
+                            color: Color::WHITE,
+                        },
+                        Default::default(),
+                    ),
+                    ..Default::default()
+                })
+                .insert(MapLabel);
+        });
+}
+
+fn spawn_map_marker(commands: &mut Commands, map: Entity, font: Handle<Font>) -> Entity {
+    let mut child_id: Entity = Entity::from_raw(0);
+    commands.entity(map).with_children(|parent| {
+        child_id = parent
+            .spawn_bundle(TextBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    ..Default::default()
+                },
+                text: Text::with_section(
+                    "",
+                    TextStyle {
+                        font,
+                        font_size: MAP_FONT_SIZE,
+                        color: Color::WHITE,
+                    },
+                    Default::default(),
+                ),
+                ..Default::default()
+            })
+            .insert(MapMarker)
+            .id();
+    });
+    child_id
+}
+
+/// Renders the terrain into the map image once the heights are known.
+pub fn build_map_image(
+    terrain: Option<Res<TerrainHeights>>,
+    mut images: ResMut<Assets<Image>>,
+    mut image_query: Query<&mut UiImage, With<MapImage>>,
+    mut built: Local<bool>,
+) {
+    let terrain = match terrain {
+        Some(terrain) if !*built => terrain,
+        _ => return,
+    };
+    *built = true;
+
+    let pixels = terrain_map_image(&terrain);
+    if pixels.is_empty() {
+        return;
+    }
+    let handle = images.add(Image::new(
+        Extent3d {
+            width: terrain.width as u32,
+            height: terrain.length as u32,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        pixels,
+        TextureFormat::Rgba8UnormSrgb,
+    ));
+    for mut image in image_query.iter_mut() {
+        image.0 = handle.clone();
+    }
+}
+
+pub fn map_controls(
+    keyboard_input: Res<Input<KeyCode>>,
+    key_bindings: Res<KeyBindings>,
+    mouse_input: Res<Input<MouseButton>>,
+    mut mouse_motion: EventReader<MouseMotion>,
+    mut mouse_wheel: EventReader<MouseWheel>,
+    gamepads: Res<Gamepads>,
+    button_inputs: Res<Input<GamepadButton>>,
+    player_query: Query<&Transform, With<Player>>,
+    windows: Res<Windows>,
+    mut view: ResMut<MapView>,
+) {
+    let player_position = player_query
+        .get_single()
+        .ok()
+        .map(|transform| map_coordinates(transform.translation));
+
+    if keyboard_input.just_pressed(key_bindings.map)
+        || gamepads.iter().any(|gamepad| {
+            button_inputs.just_pressed(GamepadButton(*gamepad, GamepadButtonType::Select))
+        })
+    {
+        view.open = !view.open;
+        if let Some(player_position) = player_position {
+            view.center = player_position;
+        }
+    }
+    if !view.open {
+        mouse_motion.iter().for_each(drop);
+        mouse_wheel.iter().for_each(drop);
+        return;
+    }
+    let screen_size = match windows.get_primary() {
+        Some(window) => Vec2::new(window.width(), window.height()),
+        None => return,
+    };
+
+    for event in mouse_wheel.iter() {
+        let lines = match event.unit {
+            MouseScrollUnit::Line => event.y,
+            MouseScrollUnit::Pixel => event.y / 100.,
+        };
+        view.zoom_by(MAP_ZOOM_STEP.powf(lines));
+    }
+    if keyboard_input.just_pressed(KeyCode::PageUp) {
+        view.zoom_by(MAP_ZOOM_STEP);
+    }
+    if keyboard_input.just_pressed(KeyCode::PageDown) {
+        view.zoom_by(1. / MAP_ZOOM_STEP);
+    }
+
+    // Mouse motion is y-down, the map is y-up.
+    let dragged = mouse_motion
+        .iter()
+        .fold(Vec2::ZERO, |dragged, motion| dragged + motion.delta);
+    if mouse_input.pressed(MouseButton::Left) {
+        view.pan(Vec2::new(dragged.x, -dragged.y), screen_size);
+    }
+
+    if keyboard_input.just_pressed(KeyCode::Home) {
+        if let Some(player_position) = player_position {
+            view.center = player_position;
+        }
+    }
+}
+
+pub fn map_view(
+    view: Res<MapView>,
+    mission: Res<MissionDef>,
+    mission_state: Res<MissionState>,
+    navigation: Res<Navigation>,
+    player_query: Query<(&Transform, Option<&Faction>, &Player, &SensorContacts)>,
+    target_query: Query<Option<&Faction>, With<Target>>,
+    mut map_query: Query<(Entity, &mut Style), With<TacticalMap>>,
+    mut image_query: Query<&mut Style, (With<MapImage>, Without<TacticalMap>)>,
+    mut label_query: Query<&mut Text, (With<MapLabel>, Without<MapMarker>)>,
+    mut marker_query: Query<
+        (&mut Style, &mut Text),
+        (With<MapMarker>, Without<TacticalMap>, Without<MapImage>),
+    >,
+    mut markers: Local<Vec<Entity>>,
+    windows: Res<Windows>,
+    asset_server: Res<AssetServer>,
+    mut commands: Commands,
+) {
+    let (map, mut map_style) = map_query.single_mut();
+    if !view.open {
+        map_style.position = Rect {
+            left: Val::Px(-10000.),
+            ..Default::default()
+        };
+        return;
+    }
+    map_style.position = Rect::default();
+    let screen_size = match windows.get_primary() {
+        Some(window) => Vec2::new(window.width(), window.height()),
+        None => return,
+    };
+
+    let terrain_size = TERRAIN_HALF_SIZE * 2. * view.pixels_per_metre(screen_size);
+    let south_west = view.to_screen(Vec2::splat(-TERRAIN_HALF_SIZE), screen_size);
+    for mut style in image_query.iter_mut() {
+        style.size = Size::new(Val::Px(terrain_size), Val::Px(terrain_size));
+        style.position = Rect {
+            left: Val::Px(south_west.x),
+            bottom: Val::Px(south_west.y),
+            ..Default::default()
+        };
+    }
+    for mut text in label_query.iter_mut() {
+        text.sections[0].value = format!(
+            "MAP x{:.1}  wheel/PgUp/PgDn zoom  drag pan  Home centre",
+            view.zoom
+        );
+    }
+
+    let mut markers_to_draw: Vec<(Vec2, String, Color)> = Vec::new();
+    for (objective, completed) in mission
+        .objectives
+        .iter()
+        .zip(mission_state.completed.iter())
+    {
+        if let ObjectiveKind::ReachArea { center, radius } = objective.kind {
+            let color = if *completed {
+                Color::GRAY
+            } else {
+                Color::ORANGE
+            };
+            markers_to_draw.extend(
+                area_ring(Vec3::from(center), radius)
+                    .into_iter()
+                    .map(|position| (position, "·".to_string(), color)),
+            );
+        }
+    }
+    for (index, waypoint) in navigation.waypoints.iter().enumerate() {
+        let color = if index == navigation.active {
+            Color::YELLOW
+        } else {
+            Color::WHITE
+        };
+        markers_to_draw.push((
+            map_coordinates(waypoint.position),
+            format!("◇ {}", waypoint.name),
+            color,
+        ));
+    }
+    if let Ok((player_transform, player_faction, player, contacts)) = player_query.get_single() {
+        let player_faction = player_faction.copied().unwrap_or_default();
+        for (entity, track) in contacts.detected() {
+            let target_faction = match target_query.get(entity) {
+                Ok(target_faction) => target_faction.copied().unwrap_or_default(),
+                Err(_) => continue,
+            };
+            let symbol = if player.target == Some(entity) {
+                "[●]"
+            } else {
+                "●"
+            };
+            markers_to_draw.push((
+                map_coordinates(track.position),
+                symbol.to_string(),
+                iff(player_faction, target_faction).color(),
+            ));
+        }
+        markers_to_draw.push((
+            map_coordinates(player_transform.translation),
+            arrow_glyph(map_coordinates(player_transform.rotation * Vec3::X)).to_string(),
+            Color::CYAN,
+        ));
+    }
+
+    while markers.len() > markers_to_draw.len() {
+        if let Some(marker) = markers.pop() {
+            commands.entity(marker).despawn_recursive();
+        }
+    }
+    while markers.len() < markers_to_draw.len() {
+        markers.push(spawn_map_marker(
+            &mut commands,
+            map,
+            asset_server.load("fonts/FiraMono-Medium.ttf"),
+        ));
+    }
+
+    markers
+        .iter()
+        .zip(markers_to_draw)
+        .for_each(|(marker, (position, symbol, color))| {
+            if let Ok((mut style, mut text)) = marker_query.get_mut(*marker) {
+                let glyph_offset = if symbol.starts_with('[') {
+                    MAP_FONT_SIZE * 0.8
+                } else {
+                    MAP_FONT_SIZE * 0.3
+                };
+                let screen_position = view.to_screen(position, screen_size);
+                style.position = Rect {
+                    left: Val::Px(screen_position.x - glyph_offset),
+                    bottom: Val::Px(screen_position.y - MAP_FONT_SIZE * 0.5),
+                    ..Default::default()
+                };
+                text.sections[0].value = symbol;
+                text.sections[0].style.color = color;
+            }
+        });
+}
+
+pub fn close_map(mut view: ResMut<MapView>, mut map_query: Query<&mut Style, With<TacticalMap>>) {
+    view.open = false;
+    for mut style in map_query.iter_mut() {
+        style.position = Rect {
+            left: Val::Px(-10000.),
+            ..Default::default()
+        };
+    }
+}
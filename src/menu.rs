@@ -0,0 +1,378 @@
+use bevy::{app::AppExit, ecs::schedule::StateError, prelude::*};
+use bevy_rapier3d::prelude::*;
+
+use super::countermeasures::*;
+use super::guidance::*;
+use super::gun::*;
+use super::mission::*;
+use super::player::*;
+use super::replay::*;
+use super::save::*;
+use super::stats::*;
+use super::Drone;
+
+pub const MISSION_END_DELAY: f32 = 3.;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AppState {
+    MainMenu,
+    MissionSelect,
+    Briefing,
+    InFlight,
+    Paused,
+    Debrief,
+    Replay,
+}
+
+#[derive(Component)]
+pub struct MenuOverlay;
+
+#[derive(Component)]
+pub struct MenuText;
+
+/// Result of the last sortie, shown in the debrief.
+#[derive(Default)]
+pub struct LastSortie {
+    pub mission_name: String,
+    pub status: Option<MissionStatus>,
+}
+
+pub fn setup_menu(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                position_type: PositionType::Absolute,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            color: Color::rgba(0., 0.05, 0., 0.85).into(),
+            ..Default::default()
+        })
+        .insert(MenuOverlay)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(TextBundle {
+                    text: Text::with_section(
+                        "",
+                        TextStyle {
+                            font: asset_server.load("fonts/FiraMono-Medium.ttf"),
+                            font_size: 24.0,
+                            color: Color::GREEN,
+                        },
+                        Default::default(),
+                    ),
+                    ..Default::default()
+                })
+                .insert(MenuText);
+        });
+}
+
+fn confirm_pressed(
+    keyboard_input: &mut Input<KeyCode>,
+    gamepads: &Gamepads,
+    button_inputs: &mut Input<GamepadButton>,
+) -> bool {
+    pressed(keyboard_input, KeyCode::Return)
+        || gamepad_pressed(gamepads, button_inputs, GamepadButtonType::South)
+}
+
+pub fn back_pressed(
+    keyboard_input: &mut Input<KeyCode>,
+    gamepads: &Gamepads,
+    button_inputs: &mut Input<GamepadButton>,
+) -> bool {
+    pressed(keyboard_input, KeyCode::Escape)
+        || gamepad_pressed(gamepads, button_inputs, GamepadButtonType::Start)
+}
+
+/// Like `just_pressed`, but consumes the press so the state entered this frame doesn't see it.
+pub fn pressed(keyboard_input: &mut Input<KeyCode>, key: KeyCode) -> bool {
+    let pressed = keyboard_input.just_pressed(key);
+    if pressed {
+        keyboard_input.reset(key);
+    }
+    pressed
+}
+
+pub fn gamepad_pressed(
+    gamepads: &Gamepads,
+    button_inputs: &mut Input<GamepadButton>,
+    button_type: GamepadButtonType,
+) -> bool {
+    let mut pressed = false;
+    for gamepad in gamepads.iter() {
+        let button = GamepadButton(*gamepad, button_type);
+        if button_inputs.just_pressed(button) {
+            button_inputs.reset(button);
+            pressed = true;
+        }
+    }
+    pressed
+}
+
+/// Checks the result of a state change. Another system may already have queued a change this
+/// frame, in which case that one goes ahead and this one is dropped.
+pub fn queue_state(result: Result<(), StateError>) {
+    match result {
+        Ok(()) | Err(StateError::AlreadyInState) | Err(StateError::StateAlreadyQueued) => {}
+        Err(e) => println!("Failed to change state: {:?}", e),
+    }
+}
+
+pub fn main_menu(
+    mut state: ResMut<State<AppState>>,
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    mut button_inputs: ResMut<Input<GamepadButton>>,
+    mut exit_events: EventWriter<AppExit>,
+    mut save: ResMut<SaveGame>,
+    mut viewer: ResMut<ReplayViewer>,
+) {
+    if pressed(&mut keyboard_input, KeyCode::D) {
+        save.settings.difficulty = save.settings.difficulty.next();
+        save.write(&save_path());
+    }
+    if pressed(&mut keyboard_input, KeyCode::R) {
+        if let Some(replay) = Replay::load(&replay_path()) {
+            *viewer = ReplayViewer::new(replay);
+            queue_state(state.push(AppState::Replay));
+            return;
+        }
+    }
+
+    if confirm_pressed(&mut keyboard_input, &gamepads, &mut button_inputs) {
+        queue_state(state.set(AppState::MissionSelect));
+    } else if back_pressed(&mut keyboard_input, &gamepads, &mut button_inputs) {
+        exit_events.send(AppExit);
+    }
+}
+
+pub fn mission_select(
+    mut commands: Commands,
+    mut state: ResMut<State<AppState>>,
+    mut missions: ResMut<MissionList>,
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    mut button_inputs: ResMut<Input<GamepadButton>>,
+    mut save: ResMut<SaveGame>,
+) {
+    let count = missions.missions.len();
+    if pressed(&mut keyboard_input, KeyCode::Up)
+        || gamepad_pressed(&gamepads, &mut button_inputs, GamepadButtonType::DPadUp)
+    {
+        missions.selected = (missions.selected + count - 1) % count;
+    }
+    if pressed(&mut keyboard_input, KeyCode::Down)
+        || gamepad_pressed(&gamepads, &mut button_inputs, GamepadButtonType::DPadDown)
+    {
+        missions.selected = (missions.selected + 1) % count;
+    }
+    if pressed(&mut keyboard_input, KeyCode::Left)
+        || gamepad_pressed(&gamepads, &mut button_inputs, GamepadButtonType::DPadLeft)
+    {
+        save.cycle_aircraft(-1);
+        save.write(&save_path());
+    }
+    if pressed(&mut keyboard_input, KeyCode::Right)
+        || gamepad_pressed(&gamepads, &mut button_inputs, GamepadButtonType::DPadRight)
+    {
+        save.cycle_aircraft(1);
+        save.write(&save_path());
+    }
+
+    if confirm_pressed(&mut keyboard_input, &gamepads, &mut button_inputs) {
+        start_mission(&mut commands, missions.selected_mission());
+        queue_state(state.set(AppState::Briefing));
+    } else if back_pressed(&mut keyboard_input, &gamepads, &mut button_inputs) {
+        queue_state(state.set(AppState::MainMenu));
+    }
+}
+
+pub fn briefing(
+    mut state: ResMut<State<AppState>>,
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    mut button_inputs: ResMut<Input<GamepadButton>>,
+) {
+    if confirm_pressed(&mut keyboard_input, &gamepads, &mut button_inputs) {
+        queue_state(state.set(AppState::InFlight));
+    } else if back_pressed(&mut keyboard_input, &gamepads, &mut button_inputs) {
+        queue_state(state.set(AppState::MissionSelect));
+    }
+}
+
+pub fn pause_game(
+    mut state: ResMut<State<AppState>>,
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    mut button_inputs: ResMut<Input<GamepadButton>>,
+) {
+    if back_pressed(&mut keyboard_input, &gamepads, &mut button_inputs) {
+        queue_state(state.push(AppState::Paused));
+    }
+}
+
+pub fn paused_menu(
+    mut state: ResMut<State<AppState>>,
+    mission: Res<MissionDef>,
+    mut last_sortie: ResMut<LastSortie>,
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    mut button_inputs: ResMut<Input<GamepadButton>>,
+) {
+    if back_pressed(&mut keyboard_input, &gamepads, &mut button_inputs) {
+        queue_state(state.pop());
+    } else if pressed(&mut keyboard_input, KeyCode::Q) {
+        *last_sortie = LastSortie {
+            mission_name: mission.name.clone(),
+            status: Some(MissionStatus::Failure("Aborted".to_string())),
+        };
+        queue_state(state.replace(AppState::Debrief));
+    }
+}
+
+/// Moves to the debrief a few seconds after the mission runtime reports a result.
+pub fn end_mission(
+    mut state: ResMut<State<AppState>>,
+    mission: Res<MissionDef>,
+    mut last_sortie: ResMut<LastSortie>,
+    mut ended_events: EventReader<MissionEnded>,
+    mut end_timer: Local<Option<Timer>>,
+    time: Res<Time>,
+) {
+    if let Some(event) = ended_events.iter().last() {
+        *last_sortie = LastSortie {
+            mission_name: mission.name.clone(),
+            status: Some(event.status.clone()),
+        };
+        *end_timer = Some(Timer::from_seconds(MISSION_END_DELAY, false));
+    }
+
+    let finished = end_timer
+        .as_mut()
+        .map_or(false, |timer| timer.tick(time.delta()).finished());
+    if finished {
+        *end_timer = None;
+        // Win over a pause queued the same frame, or the sortie would never end.
+        queue_state(state.overwrite_set(AppState::Debrief));
+    }
+}
+
+pub fn debrief(
+    mut state: ResMut<State<AppState>>,
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    mut button_inputs: ResMut<Input<GamepadButton>>,
+    recorder: Res<ReplayRecorder>,
+    mut viewer: ResMut<ReplayViewer>,
+) {
+    if pressed(&mut keyboard_input, KeyCode::R) && !recorder.replay.frames.is_empty() {
+        *viewer = ReplayViewer::new(recorder.replay.clone());
+        queue_state(state.push(AppState::Replay));
+    } else if confirm_pressed(&mut keyboard_input, &gamepads, &mut button_inputs) {
+        queue_state(state.set(AppState::MissionSelect));
+    } else if back_pressed(&mut keyboard_input, &gamepads, &mut button_inputs) {
+        queue_state(state.set(AppState::MainMenu));
+    }
+}
+
+pub fn freeze_physics(mut rapier_config: ResMut<RapierConfiguration>) {
+    rapier_config.physics_pipeline_active = false;
+}
+
+pub fn resume_physics(mut rapier_config: ResMut<RapierConfiguration>) {
+    rapier_config.physics_pipeline_active = true;
+}
+
+/// Despawns everything left over from the previous sortie.
+pub fn cleanup_mission(
+    mut commands: Commands,
+    query: Query<
+        Entity,
+        Or<(
+            With<Player>,
+            With<Drone>,
+            With<Missile>,
+            With<Bullet>,
+            With<Decoy>,
+        )>,
+    >,
+) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+pub fn menu_overlay_system(
+    state: Res<State<AppState>>,
+    missions: Res<MissionList>,
+    mission: Res<MissionDef>,
+    last_sortie: Res<LastSortie>,
+    stats: Res<SortieStats>,
+    save: Res<SaveGame>,
+    mut overlay_query: Query<&mut Style, With<MenuOverlay>>,
+    mut text_query: Query<&mut Text, With<MenuText>>,
+) {
+    let value = match state.current() {
+        AppState::MainMenu => format!(
+            "ACE BEVY\n\nPILOT {}  SORTIES {}  KILLS {}\n\n[ENTER] Play\n[D] Difficulty: {}\n[R] Last replay\n[ESC] Quit",
+            save.profile.callsign,
+            save.profile.sorties,
+            save.profile.kills,
+            save.settings.difficulty.name()
+        ),
+        AppState::MissionSelect => {
+            let mut value = "SELECT MISSION\n".to_string();
+            for (index, (_, mission)) in missions.missions.iter().enumerate() {
+                let cursor = if index == missions.selected { ">" } else { " " };
+                let completed = if save.is_completed(&mission.name) {
+                    " *"
+                } else {
+                    ""
+                };
+                value += &format!("\n{} {}{}", cursor, mission.name, completed);
+            }
+            value += &format!("\n\nAIRCRAFT < {} >", save.selected_aircraft.to_uppercase());
+            value + "\n\n[ENTER] Briefing  [ESC] Back"
+        }
+        AppState::Briefing => {
+            let mut value = format!("{}\n\n{}\n", mission.name.to_uppercase(), mission.briefing);
+            for objective in mission.objectives.iter() {
+                value += &format!("\n- {}", objective.description);
+            }
+            value + "\n\n[ENTER] Launch  [ESC] Back"
+        }
+        AppState::InFlight | AppState::Replay => String::new(),
+        AppState::Paused => "PAUSED\n\n[ESC] Resume\n[Q] Abort mission".to_string(),
+        AppState::Debrief => {
+            let result = match &last_sortie.status {
+                Some(MissionStatus::Success) => "MISSION COMPLETE".to_string(),
+                Some(MissionStatus::Failure(reason)) => format!("MISSION FAILED\n{}", reason),
+                Some(MissionStatus::InProgress) | None => String::new(),
+            };
+            format!(
+                "DEBRIEF - {}\n\n{}\n\n{}\n\n[R] Replay  [ENTER] Mission select  [ESC] Main menu",
+                last_sortie.mission_name.to_uppercase(),
+                result,
+                stats.summary()
+            )
+        }
+    };
+
+    let visible = !matches!(state.current(), AppState::InFlight | AppState::Replay);
+    for mut style in overlay_query.iter_mut() {
+        style.position = if visible {
+            Rect::default()
+        } else {
+            Rect {
+                left: Val::Px(-10000.),
+                ..Default::default()
+            }
+        };
+    }
+    for mut text in text_query.iter_mut() {
+        text.sections[0].value = value.clone();
+    }
+}
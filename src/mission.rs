@@ -0,0 +1,498 @@
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use super::crash::*;
+use super::damage::*;
+use super::faction::*;
+use super::loadout::*;
+use super::navigation::*;
+use super::player::*;
+use super::spawn_drone;
+
+pub const MISSIONS_DIR: &str = "assets/missions";
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SpawnDef {
+    pub position: [f32; 3],
+    pub look_at: [f32; 3],
+    #[serde(default)]
+    pub speed: f32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AircraftDef {
+    pub faction: Faction,
+    pub position: [f32; 3],
+    /// Degrees clockwise from +X when seen from above.
+    #[serde(default)]
+    pub heading: f32,
+    #[serde(default)]
+    pub loadout: Option<String>,
+    #[serde(default)]
+    pub tag: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct WaypointDef {
+    pub name: String,
+    pub position: [f32; 3],
+    pub radius: f32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub enum ObjectiveKind {
+    /// Destroy `count` aircraft carrying `tag`, or any hostile aircraft when `tag` is `None`.
+    DestroyTargets {
+        tag: Option<String>,
+        count: u32,
+    },
+    ReachArea {
+        center: [f32; 3],
+        radius: f32,
+    },
+    SurviveTime {
+        seconds: f32,
+    },
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ObjectiveDef {
+    pub description: String,
+    pub kind: ObjectiveKind,
+    /// Index of an objective that must be complete before this one can be.
+    #[serde(default)]
+    pub after: Option<usize>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub enum TriggerCondition {
+    Time(f32),
+    EnterArea { center: [f32; 3], radius: f32 },
+    ObjectiveComplete(usize),
+    TargetsDestroyed { tag: Option<String>, count: u32 },
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub enum TriggerAction {
+    SpawnAircraft(AircraftDef),
+    Message(String),
+    CompleteMission,
+    FailMission(String),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TriggerDef {
+    pub condition: TriggerCondition,
+    pub actions: Vec<TriggerAction>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MissionDef {
+    pub name: String,
+    #[serde(default)]
+    pub briefing: String,
+    pub player_spawn: SpawnDef,
+    #[serde(default)]
+    pub wingmen: usize,
+    #[serde(default)]
+    pub aircraft: Vec<AircraftDef>,
+    #[serde(default)]
+    pub waypoints: Vec<WaypointDef>,
+    #[serde(default)]
+    pub objectives: Vec<ObjectiveDef>,
+    #[serde(default)]
+    pub triggers: Vec<TriggerDef>,
+    #[serde(default)]
+    pub time_limit: Option<f32>,
+    #[serde(default)]
+    pub max_player_losses: Option<u32>,
+    /// Aircraft loadouts unlocked by completing the mission.
+    #[serde(default)]
+    pub unlocks: Vec<String>,
+}
+
+impl Default for MissionDef {
+    fn default() -> Self {
+        let drone = |position: [f32; 3]| AircraftDef {
+            faction: DRONE_FACTION,
+            position,
+            heading: 0.,
+            loadout: None,
+            tag: None,
+        };
+        MissionDef {
+            name: "Free Flight".to_string(),
+            briefing: String::new(),
+            player_spawn: SpawnDef {
+                position: [-700., 50., -210.],
+                look_at: [-600., 50., -700.],
+                speed: 0.,
+            },
+            wingmen: 2,
+            aircraft: vec![
+                drone([50., 300., 0.]),
+                drone([0., 350., -50.]),
+                drone([0., 325., 0.]),
+            ],
+            waypoints: Vec::new(),
+            objectives: Vec::new(),
+            triggers: Vec::new(),
+            time_limit: None,
+            max_player_losses: None,
+            unlocks: Vec::new(),
+        }
+    }
+}
+
+impl MissionDef {
+    pub fn load(filename: &str) -> Self {
+        let mission = std::fs::read_to_string(filename)
+            .map_err(|e| e.to_string())
+            .and_then(|contents| ron::from_str(&contents).map_err(|e| e.to_string()));
+
+        match mission {
+            Ok(mission) => mission,
+            Err(e) => {
+                println!("Failed to load {}: {}", filename, e);
+                MissionDef::default()
+            }
+        }
+    }
+
+    pub fn spawn_point(&self) -> SpawnPoint {
+        SpawnPoint {
+            position: Vec3::from(self.player_spawn.position),
+            look_at: Vec3::from(self.player_spawn.look_at),
+            speed: self.player_spawn.speed,
+        }
+    }
+}
+
+impl AircraftDef {
+    pub fn transform(&self) -> Transform {
+        Transform {
+            translation: Vec3::from(self.position),
+            rotation: Quat::from_rotation_y(-self.heading.to_radians()),
+            ..Default::default()
+        }
+    }
+}
+
+/// Mission files found in `MISSIONS_DIR`, sorted by file name.
+#[derive(Default)]
+pub struct MissionList {
+    pub missions: Vec<(String, MissionDef)>,
+    pub selected: usize,
+}
+
+impl MissionList {
+    pub fn load(directory: &str) -> Self {
+        let mut paths: Vec<String> = match std::fs::read_dir(directory) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .filter(|path| {
+                    path.extension()
+                        .map_or(false, |extension| extension == "ron")
+                })
+                .filter_map(|path| path.to_str().map(|path| path.to_string()))
+                .collect(),
+            Err(e) => {
+                println!("Failed to read {}: {}", directory, e);
+                Vec::new()
+            }
+        };
+        paths.sort();
+
+        let mut missions: Vec<(String, MissionDef)> = paths
+            .into_iter()
+            .map(|path| {
+                let mission = MissionDef::load(&path);
+                (path, mission)
+            })
+            .collect();
+        if missions.is_empty() {
+            missions.push((String::new(), MissionDef::default()));
+        }
+
+        MissionList {
+            missions,
+            selected: 0,
+        }
+    }
+
+    pub fn selected_mission(&self) -> &MissionDef {
+        &self.missions[self.selected].1
+    }
+}
+
+/// Makes `mission` the current mission and resets its runtime state.
+pub fn start_mission(commands: &mut Commands, mission: &MissionDef) {
+    commands.insert_resource(mission.spawn_point());
+    commands.insert_resource(MissionState::new(mission));
+    commands.insert_resource(Navigation::from_mission(mission));
+    commands.insert_resource(mission.clone());
+}
+
+/// Identifies an aircraft for `DestroyTargets` objectives and triggers.
+#[derive(Component)]
+pub struct MissionTag(pub String);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MissionStatus {
+    InProgress,
+    Success,
+    Failure(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct KillRecord {
+    pub faction: Faction,
+    pub tag: Option<String>,
+}
+
+/// Everything the objective and trigger rules are evaluated against.
+pub struct MissionSnapshot<'a> {
+    pub elapsed: f32,
+    pub player_position: Option<Vec3>,
+    pub player_faction: Faction,
+    pub kills: &'a [KillRecord],
+    pub completed: &'a [bool],
+}
+
+pub fn count_kills(snapshot: &MissionSnapshot, tag: &Option<String>) -> u32 {
+    snapshot
+        .kills
+        .iter()
+        .filter(|kill| match tag {
+            Some(tag) => kill.tag.as_ref() == Some(tag),
+            None => is_hostile(snapshot.player_faction, kill.faction),
+        })
+        .count() as u32
+}
+
+fn in_area(snapshot: &MissionSnapshot, center: [f32; 3], radius: f32) -> bool {
+    snapshot.player_position.map_or(false, |position| {
+        (position - Vec3::from(center)).length() <= radius
+    })
+}
+
+pub fn objective_complete(kind: &ObjectiveKind, snapshot: &MissionSnapshot) -> bool {
+    match kind {
+        ObjectiveKind::DestroyTargets { tag, count } => count_kills(snapshot, tag) >= *count,
+        ObjectiveKind::ReachArea { center, radius } => in_area(snapshot, *center, *radius),
+        ObjectiveKind::SurviveTime { seconds } => snapshot.elapsed >= *seconds,
+    }
+}
+
+pub fn trigger_condition_met(condition: &TriggerCondition, snapshot: &MissionSnapshot) -> bool {
+    match condition {
+        TriggerCondition::Time(time) => snapshot.elapsed >= *time,
+        TriggerCondition::EnterArea { center, radius } => in_area(snapshot, *center, *radius),
+        TriggerCondition::ObjectiveComplete(index) => {
+            snapshot.completed.get(*index).copied().unwrap_or(false)
+        }
+        TriggerCondition::TargetsDestroyed { tag, count } => count_kills(snapshot, tag) >= *count,
+    }
+}
+
+/// Outcome from the mission-wide rules; triggers can end the mission on top of this.
+pub fn evaluate_mission(
+    mission: &MissionDef,
+    snapshot: &MissionSnapshot,
+    player_losses: u32,
+) -> MissionStatus {
+    if let Some(max_player_losses) = mission.max_player_losses {
+        if player_losses > max_player_losses {
+            return MissionStatus::Failure("Too many aircraft lost".to_string());
+        }
+    }
+    if !snapshot.completed.is_empty() && snapshot.completed.iter().all(|completed| *completed) {
+        return MissionStatus::Success;
+    }
+    if let Some(time_limit) = mission.time_limit {
+        if snapshot.elapsed > time_limit {
+            return MissionStatus::Failure("Out of time".to_string());
+        }
+    }
+    MissionStatus::InProgress
+}
+
+pub struct MissionState {
+    pub status: MissionStatus,
+    pub elapsed: f32,
+    pub completed: Vec<bool>,
+    pub fired_triggers: Vec<bool>,
+    pub kills: Vec<KillRecord>,
+    pub player_losses: u32,
+}
+
+impl MissionState {
+    pub fn new(mission: &MissionDef) -> Self {
+        MissionState {
+            status: MissionStatus::InProgress,
+            elapsed: 0.,
+            completed: vec![false; mission.objectives.len()],
+            fired_triggers: vec![false; mission.triggers.len()],
+            kills: Vec::new(),
+            player_losses: 0,
+        }
+    }
+}
+
+pub struct MissionMessage {
+    pub text: String,
+}
+
+pub struct MissionEnded {
+    pub status: MissionStatus,
+}
+
+pub fn setup_mission(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    loadouts: Res<Loadouts>,
+    mission: Res<MissionDef>,
+) {
+    for aircraft in mission.aircraft.iter() {
+        spawn_mission_aircraft(&mut commands, &asset_server, &loadouts, aircraft);
+    }
+}
+
+pub fn spawn_mission_aircraft(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    loadouts: &Loadouts,
+    aircraft: &AircraftDef,
+) -> Entity {
+    let entity = spawn_drone(
+        commands,
+        asset_server,
+        loadouts,
+        aircraft.transform(),
+        aircraft.faction,
+    );
+    if let Some(loadout) = &aircraft.loadout {
+        commands
+            .entity(entity)
+            .insert(Loadout::from_def(&loadouts.get(loadout)));
+    }
+    if let Some(tag) = &aircraft.tag {
+        commands.entity(entity).insert(MissionTag(tag.clone()));
+    }
+    entity
+}
+
+pub fn mission_casualties(
+    mut destroyed_events: EventReader<AircraftDestroyed>,
+    mut crash_events: EventReader<PlayerCrashed>,
+    aircraft_query: Query<(Option<&Faction>, Option<&MissionTag>)>,
+    mut state: ResMut<MissionState>,
+) {
+    for event in destroyed_events.iter() {
+        if let Ok((faction, tag)) = aircraft_query.get(event.entity) {
+            state.kills.push(KillRecord {
+                faction: faction.copied().unwrap_or_default(),
+                tag: tag.map(|tag| tag.0.clone()),
+            });
+        }
+    }
+    for _ in crash_events.iter() {
+        state.player_losses += 1;
+    }
+}
+
+pub fn mission_runtime(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    loadouts: Res<Loadouts>,
+    mission: Res<MissionDef>,
+    mut state: ResMut<MissionState>,
+    player_query: Query<(&Transform, Option<&Faction>), With<Player>>,
+    mut message_events: EventWriter<MissionMessage>,
+    mut ended_events: EventWriter<MissionEnded>,
+    time: Res<Time>,
+) {
+    if state.status != MissionStatus::InProgress {
+        return;
+    }
+    state.elapsed += time.delta_seconds();
+
+    let (player_position, player_faction) = match player_query.iter().next() {
+        Some((transform, faction)) => (
+            Some(transform.translation),
+            faction.copied().unwrap_or(PLAYER_FACTION),
+        ),
+        None => (None, PLAYER_FACTION),
+    };
+
+    let state = &mut *state;
+    let completed: Vec<bool> = {
+        let snapshot = MissionSnapshot {
+            elapsed: state.elapsed,
+            player_position,
+            player_faction,
+            kills: &state.kills,
+            completed: &state.completed,
+        };
+        mission
+            .objectives
+            .iter()
+            .zip(state.completed.iter())
+            .map(|(objective, completed)| {
+                let unlocked = objective.after.map_or(true, |after| {
+                    snapshot.completed.get(after).copied().unwrap_or(false)
+                });
+                *completed || (unlocked && objective_complete(&objective.kind, &snapshot))
+            })
+            .collect()
+    };
+    for (objective, (was_completed, completed)) in mission
+        .objectives
+        .iter()
+        .zip(state.completed.iter().zip(completed.iter()))
+    {
+        if *completed && !was_completed {
+            message_events.send(MissionMessage {
+                text: format!("Objective complete: {}", objective.description),
+            });
+        }
+    }
+    state.completed = completed;
+
+    let snapshot = MissionSnapshot {
+        elapsed: state.elapsed,
+        player_position,
+        player_faction,
+        kills: &state.kills,
+        completed: &state.completed,
+    };
+    let mut status = evaluate_mission(&mission, &snapshot, state.player_losses);
+
+    for (trigger, fired) in mission.triggers.iter().zip(state.fired_triggers.iter_mut()) {
+        if *fired || !trigger_condition_met(&trigger.condition, &snapshot) {
+            continue;
+        }
+        *fired = true;
+
+        for action in trigger.actions.iter() {
+            match action {
+                TriggerAction::SpawnAircraft(aircraft) => {
+                    spawn_mission_aircraft(&mut commands, &asset_server, &loadouts, aircraft);
+                }
+                TriggerAction::Message(text) => {
+                    message_events.send(MissionMessage { text: text.clone() })
+                }
+                TriggerAction::CompleteMission => status = MissionStatus::Success,
+                TriggerAction::FailMission(reason) => {
+                    status = MissionStatus::Failure(reason.clone())
+                }
+            }
+        }
+    }
+
+    if status != MissionStatus::InProgress {
+        state.status = status.clone();
+        ended_events.send(MissionEnded { status });
+    }
+}
@@ -0,0 +1,381 @@
+use bevy::prelude::*;
+
+use super::mission::*;
+use super::player::*;
+
+/// World direction that compass headings are measured from.
+pub const NORTH: Vec3 = Vec3::new(0., 0., -1.);
+pub const EAST: Vec3 = Vec3::new(1., 0., 0.);
+
+#[derive(Debug, Clone)]
+pub struct Waypoint {
+    pub name: String,
+    pub position: Vec3,
+    pub radius: f32,
+}
+
+#[derive(Default)]
+pub struct Navigation {
+    pub waypoints: Vec<Waypoint>,
+    pub active: usize,
+}
+
+impl Navigation {
+    pub fn from_mission(mission: &MissionDef) -> Self {
+        Navigation {
+            waypoints: mission
+                .waypoints
+                .iter()
+                .map(|waypoint| Waypoint {
+                    name: waypoint.name.clone(),
+                    position: Vec3::from(waypoint.position),
+                    radius: waypoint.radius,
+                })
+                .collect(),
+            active: 0,
+        }
+    }
+
+    pub fn active_waypoint(&self) -> Option<&Waypoint> {
+        self.waypoints.get(self.active)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NavSolution {
+    pub distance: f32,
+    /// Compass heading to the waypoint in degrees, 0 = north, 90 = east.
+    pub heading: f32,
+    /// Angle from the nose in the horizontal plane in radians, positive to the right.
+    pub relative_bearing: f32,
+    pub eta: Option<f32>,
+}
+
+/// Compass heading of `direction` in degrees in `[0, 360)`.
+pub fn compass_heading(direction: Vec3) -> f32 {
+    let heading = direction.dot(EAST).atan2(direction.dot(NORTH)).to_degrees();
+    if heading < 0. {
+        heading + 360.
+    } else {
+        heading
+    }
+}
+
+pub fn navigate(
+    position: Vec3,
+    rotation: Quat,
+    velocity: Vec3,
+    waypoint_position: Vec3,
+) -> NavSolution {
+    let offset = waypoint_position - position;
+    let distance = offset.length();
+
+    let mut forward = rotation * Vec3::X;
+    forward.y = 0.;
+    let mut horizontal_offset = offset;
+    horizontal_offset.y = 0.;
+    let relative_bearing =
+        if forward.length_squared() > 0. && horizontal_offset.length_squared() > 0. {
+            let angle = forward.angle_between(horizontal_offset);
+            if forward.cross(horizontal_offset).y > 0. {
+                -angle
+            } else {
+                angle
+            }
+        } else {
+            0.
+        };
+
+    let closing_speed = if distance > 0. {
+        velocity.dot(offset / distance)
+    } else {
+        0.
+    };
+    let eta = if closing_speed > 0. {
+        Some(distance / closing_speed)
+    } else {
+        None
+    };
+
+    NavSolution {
+        distance,
+        heading: compass_heading(offset),
+        relative_bearing,
+        eta,
+    }
+}
+
+pub fn waypoint_reached(position: Vec3, waypoint: &Waypoint) -> bool {
+    (waypoint.position - position).length() <= waypoint.radius
+}
+
+/// Offset from the screen centre at which to draw an arrow pointing at a point off screen.
+/// `camera_local` is the point in camera space (-Z forward, +X right, +Y up).
+pub fn edge_indicator(camera_local: Vec3, half_size: Vec2, margin: f32) -> Vec2 {
+    let direction = Vec2::new(camera_local.x, camera_local.y);
+    let direction = if direction.length_squared() > 0. {
+        direction.normalize()
+    } else {
+        -Vec2::Y
+    };
+
+    let bounds = (half_size - Vec2::splat(margin)).max(Vec2::ZERO);
+    let scale_x = if direction.x != 0. {
+        bounds.x / direction.x.abs()
+    } else {
+        f32::INFINITY
+    };
+    let scale_y = if direction.y != 0. {
+        bounds.y / direction.y.abs()
+    } else {
+        f32::INFINITY
+    };
+    direction * scale_x.min(scale_y)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScreenPosition {
+    /// Pixel coordinates from the bottom-left corner of the screen.
+    OnScreen(Vec2),
+    /// Point at the screen edge towards the target and its offset from the screen centre.
+    OffScreen { position: Vec2, direction: Vec2 },
+}
+
+/// Projects `point` to the screen of a camera at `camera_transform`. Unlike
+/// `Camera::world_to_screen` this needs no window, and points behind the camera are reported as
+/// off screen instead of being mirrored onto it.
+pub fn project_to_screen(
+    projection_matrix: Mat4,
+    camera_transform: &GlobalTransform,
+    point: Vec3,
+    screen_size: Vec2,
+    margin: f32,
+) -> ScreenPosition {
+    let camera_local = camera_transform
+        .compute_matrix()
+        .inverse()
+        .transform_point3(point);
+    let clip = projection_matrix * camera_local.extend(1.);
+
+    if clip.w > 0. {
+        let ndc = Vec2::new(clip.x, clip.y) / clip.w;
+        if ndc.x.abs() <= 1. && ndc.y.abs() <= 1. {
+            return ScreenPosition::OnScreen((ndc + Vec2::ONE) / 2. * screen_size);
+        }
+    }
+
+    let direction = edge_indicator(camera_local, screen_size / 2., margin);
+    ScreenPosition::OffScreen {
+        position: screen_size / 2. + direction,
+        direction,
+    }
+}
+
+/// Arrow glyph closest to the screen direction `direction`.
+pub fn arrow_glyph(direction: Vec2) -> &'static str {
+    const ARROWS: [&str; 8] = ["→", "↗", "↑", "↖", "←", "↙", "↓", "↘"];
+    let octant = (direction.y.atan2(direction.x) / std::f32::consts::FRAC_PI_4).round() as i32;
+    ARROWS[octant.rem_euclid(8) as usize]
+}
+
+pub fn format_eta(eta: Option<f32>) -> String {
+    match eta {
+        Some(eta) if eta < 6000. => format!("{:02}:{:02}", eta as u32 / 60, eta as u32 % 60),
+        _ => "--:--".to_string(),
+    }
+}
+
+pub fn advance_waypoints(
+    mut navigation: ResMut<Navigation>,
+    mut message_events: EventWriter<MissionMessage>,
+    player_query: Query<&Transform, With<Player>>,
+) {
+    let player_position = match player_query.iter().next() {
+        Some(transform) => transform.translation,
+        None => return,
+    };
+
+    let reached = navigation.active_waypoint().map_or(false, |waypoint| {
+        waypoint_reached(player_position, waypoint)
+    });
+    if reached {
+        navigation.active += 1;
+        if let Some(waypoint) = navigation.active_waypoint() {
+            message_events.send(MissionMessage {
+                text: format!("Steer to {}", waypoint.name),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::FRAC_PI_2;
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-3,
+            "expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn compass_headings() {
+        assert_close(compass_heading(NORTH), 0.);
+        assert_close(compass_heading(EAST), 90.);
+        assert_close(compass_heading(-NORTH), 180.);
+        assert_close(compass_heading(-EAST), 270.);
+        assert_close(compass_heading(NORTH - EAST), 315.);
+    }
+
+    #[test]
+    fn bearing_is_positive_to_the_right() {
+        // The nose points east, so south is to the right and north to the left.
+        let right = navigate(Vec3::ZERO, Quat::IDENTITY, Vec3::ZERO, -NORTH * 1000.);
+        assert_close(right.relative_bearing, FRAC_PI_2);
+        assert_close(right.heading, 180.);
+
+        let left = navigate(Vec3::ZERO, Quat::IDENTITY, Vec3::ZERO, NORTH * 1000.);
+        assert_close(left.relative_bearing, -FRAC_PI_2);
+        assert_close(left.heading, 0.);
+
+        let ahead = navigate(Vec3::ZERO, Quat::IDENTITY, Vec3::ZERO, EAST * 1000.);
+        assert_close(ahead.relative_bearing, 0.);
+    }
+
+    #[test]
+    fn eta_from_closing_speed() {
+        let solution = navigate(Vec3::ZERO, Quat::IDENTITY, EAST * 100., EAST * 1000.);
+        assert_close(solution.distance, 1000.);
+        assert_close(solution.eta.unwrap(), 10.);
+    }
+
+    #[test]
+    fn no_eta_without_closure() {
+        let waypoint = EAST * 1000.;
+        assert_eq!(
+            navigate(Vec3::ZERO, Quat::IDENTITY, NORTH * 100., waypoint).eta,
+            None
+        );
+        assert_eq!(
+            navigate(Vec3::ZERO, Quat::IDENTITY, -EAST * 100., waypoint).eta,
+            None
+        );
+        assert_eq!(
+            navigate(waypoint, Quat::IDENTITY, EAST * 100., waypoint).eta,
+            None
+        );
+    }
+
+    #[test]
+    fn formats_eta() {
+        assert_eq!(format_eta(Some(75.)), "01:15");
+        assert_eq!(format_eta(Some(0.)), "00:00");
+        assert_eq!(format_eta(None), "--:--");
+        assert_eq!(format_eta(Some(10000.)), "--:--");
+    }
+
+    #[test]
+    fn edge_indicator_clamps_to_margin() {
+        let half_size = Vec2::new(400., 300.);
+        let margin = 20.;
+
+        let right = edge_indicator(Vec3::new(1000., 0., -5.), half_size, margin);
+        assert_close(right.x, 380.);
+        assert_close(right.y, 0.);
+
+        let diagonal = edge_indicator(Vec3::new(5000., 5000., 10.), half_size, margin);
+        assert_close(diagonal.y, 280.);
+        assert_close(diagonal.x, 280.);
+
+        let below = edge_indicator(Vec3::new(0., 0., 10.), half_size, margin);
+        assert_close(below.x, 0.);
+        assert_close(below.y, -280.);
+
+        let tiny = edge_indicator(Vec3::new(1., 1., 0.), Vec2::splat(10.), margin);
+        assert_eq!(tiny, Vec2::ZERO);
+    }
+
+    fn projection() -> Mat4 {
+        Mat4::perspective_infinite_reverse_rh(std::f32::consts::FRAC_PI_3, 800. / 600., 0.1)
+    }
+
+    const SCREEN_SIZE: Vec2 = Vec2::new(800., 600.);
+
+    #[test]
+    fn point_ahead_projects_to_centre() {
+        let camera = GlobalTransform::from_translation(Vec3::new(10., 50., 0.));
+        let position = project_to_screen(
+            projection(),
+            &camera,
+            Vec3::new(10., 50., -100.),
+            SCREEN_SIZE,
+            20.,
+        );
+        match position {
+            ScreenPosition::OnScreen(position) => {
+                assert!(
+                    (position - SCREEN_SIZE / 2.).length() < 1e-2,
+                    "{}",
+                    position
+                )
+            }
+            _ => panic!("expected on screen, got {:?}", position),
+        }
+    }
+
+    #[test]
+    fn point_behind_is_off_screen_on_its_own_side() {
+        let position = project_to_screen(
+            projection(),
+            &GlobalTransform::identity(),
+            Vec3::new(50., 0., 100.),
+            SCREEN_SIZE,
+            20.,
+        );
+        assert_eq!(
+            position,
+            ScreenPosition::OffScreen {
+                position: Vec2::new(780., 300.),
+                direction: Vec2::new(380., 0.),
+            }
+        );
+    }
+
+    #[test]
+    fn point_off_the_side_maps_to_that_edge() {
+        let camera = GlobalTransform::identity();
+        let right = project_to_screen(
+            projection(),
+            &camera,
+            Vec3::new(1000., 0., -10.),
+            SCREEN_SIZE,
+            20.,
+        );
+        assert_eq!(
+            right,
+            ScreenPosition::OffScreen {
+                position: Vec2::new(780., 300.),
+                direction: Vec2::new(380., 0.),
+            }
+        );
+
+        let above = project_to_screen(
+            projection(),
+            &camera,
+            Vec3::new(0., 1000., -10.),
+            SCREEN_SIZE,
+            20.,
+        );
+        assert_eq!(
+            above,
+            ScreenPosition::OffScreen {
+                position: Vec2::new(400., 580.),
+                direction: Vec2::new(0., 280.),
+            }
+        );
+    }
+}
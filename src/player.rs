@@ -4,10 +4,20 @@ use bevy_rapier3d::na::Unit;
 use bevy_rapier3d::na::Vector3;
 use bevy_rapier3d::prelude::*;
 
+use super::countermeasures::*;
+use super::crash::*;
+use super::damage::*;
+use super::faction::*;
 use super::input::*;
+use super::loadout::*;
+use super::mission::*;
+use super::save::*;
+use super::sensor::*;
 // use super::particles::*;
 use super::sky::*;
-use super::Drone;
+use super::spawn_drone;
+use super::weapons::*;
+use super::wingman::*;
 
 pub const ROLL_SPEED: f32 = 20.;
 pub const PITCH_SPEED: f32 = 8.;
@@ -16,9 +26,15 @@ pub const MIN_SPEED: f32 = 0.;
 pub const MAX_SPEED: f32 = 500.;
 pub const ACCEL: f32 = 75.;
 pub const BRAKE: f32 = 0.05;
+pub const PLAYER_FLARES: u32 = 30;
+pub const PLAYER_CHAFF: u32 = 30;
+pub const DRONE_FLARES: u32 = 10;
+pub const DRONE_CHAFF: u32 = 10;
 
 #[derive(Default, Component)]
 pub struct Player {
+    /// Local player number, starting at zero, used to assign an input device.
+    pub slot: usize,
     pub missiles_fired: u32,
     pub target: Option<Entity>,
 }
@@ -26,17 +42,16 @@ pub struct Player {
 #[derive(Component)]
 pub struct MainCamera;
 
-#[derive(Component)]
-pub struct Missile {
-    pub target: Option<Entity>,
-    pub velocity: f32,
-    pub lifetime: f32,
-}
-
 #[derive(Component)]
 pub struct Target;
 
-pub fn setup_player(mut commands: Commands, asset_server: Res<AssetServer>) {
+#[derive(Default, Component)]
+pub struct TargetVelocity {
+    pub linvel: Vec3,
+    last_position: Option<Vec3>,
+}
+
+pub fn setup_camera(mut commands: Commands) {
     commands
         .spawn_bundle(PerspectiveCameraBundle {
             perspective_projection: PerspectiveProjection {
@@ -48,24 +63,30 @@ pub fn setup_player(mut commands: Commands, asset_server: Res<AssetServer>) {
         })
         .insert(MainCamera)
         .insert(SkyBoxCamera);
+}
 
-    let mut start_transform = Transform::from_translation(Vec3::new(-700., 50., -210.));
-    start_transform.look_at(Vec3::new(-600., 50., -700.), Vec3::Y);
-
-    let target = commands
-        .spawn_bundle((
-            Transform::from_translation(Vec3::new(0.0, 325.0, 0.0)),
-            GlobalTransform::identity(),
-        ))
-        .with_children(|parent| {
-            parent.spawn_scene(asset_server.load("f35.gltf#Scene0"));
-        })
-        .insert(Target)
-        .insert(Drone)
-        .id();
+pub fn setup_player(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    spawn_point: Res<SpawnPoint>,
+    loadouts: Res<Loadouts>,
+    mission: Res<MissionDef>,
+    save: Res<SaveGame>,
+) {
+    let start_transform = spawn_point.transform();
 
     let rigid_body = RigidBodyBundle {
-        position: start_transform.translation.into(),
+        position: RigidBodyPositionComponent(
+            Isometry::from_parts(
+                start_transform.translation.into(),
+                start_transform.rotation.into(),
+            )
+            .into(),
+        ),
+        velocity: RigidBodyVelocityComponent(RigidBodyVelocity {
+            linvel: (start_transform.rotation * Vec3::X * spawn_point.speed).into(),
+            ..Default::default()
+        }),
         forces: RigidBodyForcesComponent(RigidBodyForces {
             gravity_scale: 1.,
             ..Default::default()
@@ -85,123 +106,115 @@ pub fn setup_player(mut commands: Commands, asset_server: Res<AssetServer>) {
         ..Default::default()
     };
 
+    let loadout_def = loadouts.get(&save.selected_aircraft);
+
     let collider = ColliderBundle {
-        shape: ColliderShapeComponent(ColliderShape::ball(1.)),
+        shape: ColliderShapeComponent(aircraft_collider_shape()),
         material: ColliderMaterialComponent(ColliderMaterial::default()),
+        flags: ColliderFlagsComponent(ColliderFlags {
+            active_events: ActiveEvents::CONTACT_EVENTS,
+            ..Default::default()
+        }),
         ..Default::default()
     };
 
-    commands
+    let player = commands
         .spawn()
         .insert(Transform::default())
         .with_children(|parent| {
             parent.spawn_scene(asset_server.load("f35.gltf#Scene0"));
         })
         .insert(Player {
-            target: Some(target),
+            slot: 0,
+            target: None,
             missiles_fired: 0,
             ..Default::default()
         })
+        .insert(PLAYER_FACTION)
+        .insert(AircraftDamage::default())
+        .insert(Loadout::from_def(&loadout_def))
+        .insert(Fuel::from_def(&loadout_def))
+        .insert(Countermeasures::new(PLAYER_FLARES, PLAYER_CHAFF))
+        .insert(PlayerInput::default())
+        .insert(InputDevice::Unassigned)
+        .insert(Sensor::fighter())
+        .insert(SensorContacts::default())
         .insert_bundle(rigid_body)
         .insert_bundle(collider)
         .insert(RigidBodyPositionSync::Discrete)
-        .insert(ColliderDebugRender::with_id(1));
-}
-
-pub fn camera_follow_player(
-    mut query_set: QuerySet<(
-        QueryState<&mut Transform, With<MainCamera>>,
-        QueryState<(&Transform, &RigidBodyVelocityComponent), With<Player>>,
-        QueryState<(&mut PerspectiveProjection, &mut Camera), With<MainCamera>>,
-    )>,
-    player_input: Res<PlayerInput>,
-    windows: Res<Windows>,
-    time: Res<Time>,
-) {
-    let mut player_translation = Vec3::ZERO;
-    let mut player_rotation = Quat::default();
-    let mut player_speed = MIN_SPEED;
-
-    if let Some((player_transform, rb_vel)) = query_set.q1().iter().next() {
-        player_translation = player_transform.translation;
-        player_rotation = player_transform.rotation;
-        player_speed = rb_vel.linvel.magnitude();
-    }
-    let speed_ratio = (player_speed - MIN_SPEED) / (MAX_SPEED - MIN_SPEED);
-
-    if let Some(mut camera_transform) = query_set.q0().iter_mut().next() {
-        let camera_x = -player_input.camera_axis.x * std::f32::consts::PI;
-        let camera_y = player_input.camera_axis.y * std::f32::consts::FRAC_2_PI;
-
-        let axis_rot = Quat::from_rotation_y(camera_x) * Quat::from_rotation_z(camera_y);
+        .insert(ColliderDebugRender::with_id(1))
+        .id();
 
-        let mut new_transform = Transform::from_translation(
-            player_translation + player_rotation * axis_rot * Vec3::new(-6.0, 0.8, 0.0),
-        );
-        new_transform = new_transform.looking_at(
-            player_translation + (player_rotation * Vec3::Y).normalize() * 0.8,
-            (player_rotation * Vec3::Y).normalize(),
+    for slot in 0..mission.wingmen {
+        let offset = slot_offset(Formation::default(), slot);
+        let wingman = spawn_drone(
+            &mut commands,
+            &asset_server,
+            &loadouts,
+            Transform {
+                translation: start_transform.translation + start_transform.rotation * offset,
+                rotation: start_transform.rotation,
+                ..Default::default()
+            },
+            PLAYER_FACTION,
         );
-
-        camera_transform.translation = camera_transform
-            .translation
-            .lerp(new_transform.translation, 0.75);
-        camera_transform.rotation = camera_transform.rotation.lerp(new_transform.rotation, 0.75);
-
-        // camera_transform.translation = new_transform.translation;
-        // camera_transform.rotation = new_transform.rotation;
-
-        // let new_transform =
-        //     Transform::from_translation(player_translation + Vec3::new(-15.0, 6.0, 0.0))
-        //         .looking_at(player_translation, Vec3::Y);
-        // camera_transform.translation = new_transform.translation;
-        // camera_transform.rotation = new_transform.rotation;
+        commands.entity(wingman).insert(Wingman::new(player, slot));
     }
+}
 
-    if let Some(window) = windows.get_primary() {
-        for (mut perspective_projection, mut camera) in query_set.q2().iter_mut() {
-            perspective_projection.fov =
-                std::f32::consts::PI / 3.0 + (speed_ratio * std::f32::consts::PI / 4.);
-
-            perspective_projection.update(window.width(), window.height());
-            camera.projection_matrix = perspective_projection.get_projection_matrix();
-            camera.depth_calculation = perspective_projection.depth_calculation();
+pub fn assign_input_devices(
+    gamepads: Res<Gamepads>,
+    mut player_query: Query<(&Player, &mut InputDevice, &mut PlayerInput)>,
+) {
+    let mut connected: Vec<Gamepad> = gamepads.iter().cloned().collect();
+    connected.sort_by_key(|gamepad| gamepad.0);
+
+    for (player, mut device, mut player_input) in player_query.iter_mut() {
+        let assigned = input_device(player.slot, &connected);
+        if *device != assigned {
+            *device = assigned;
+            *player_input = PlayerInput::default();
         }
     }
 }
 
 pub fn player_input(
-    mut player_input: ResMut<PlayerInput>,
+    mut player_query: Query<(&InputDevice, &mut PlayerInput)>,
     keyboard_input: Res<Input<KeyCode>>,
-    gamepads: Res<Gamepads>,
+    key_bindings: Res<KeyBindings>,
 ) {
-    if gamepads.iter().count() == 0 {
+    for (device, mut player_input) in player_query.iter_mut() {
+        if *device != InputDevice::Keyboard {
+            continue;
+        }
+
         let mut axis = Vec2::ZERO;
-        if keyboard_input.pressed(KeyCode::Left) {
+        if keyboard_input.pressed(key_bindings.roll_left) {
             axis.x += -1.;
         }
-        if keyboard_input.pressed(KeyCode::Right) {
+        if keyboard_input.pressed(key_bindings.roll_right) {
             axis.x += 1.;
         }
-        if keyboard_input.pressed(KeyCode::Up) {
+        if keyboard_input.pressed(key_bindings.pitch_up) {
             axis.y += 1.;
         }
-        if keyboard_input.pressed(KeyCode::Down) {
+        if keyboard_input.pressed(key_bindings.pitch_down) {
             axis.y += -1.;
         }
 
         player_input.axis = axis;
 
-        if keyboard_input.pressed(KeyCode::Space) {
+        if keyboard_input.pressed(key_bindings.throttle) {
             player_input.accel = 1.;
         } else {
             player_input.accel = 0.;
         }
-        if keyboard_input.pressed(KeyCode::LShift) {
+        if keyboard_input.pressed(key_bindings.brake) {
             player_input.brake = 1.;
         } else {
             player_input.brake = 0.;
         }
+        player_input.fire_gun = keyboard_input.pressed(key_bindings.fire_gun);
     }
 }
 
@@ -223,27 +236,31 @@ fn calculate_lift(
 }
 
 pub fn player_movement(
-    player_input: Res<PlayerInput>,
     mut player_query: Query<
         (
+            &PlayerInput,
             &mut RigidBodyForcesComponent,
             &RigidBodyVelocityComponent,
             &RigidBodyPositionComponent,
             &RigidBodyMassPropsComponent,
+            &AircraftDamage,
+            &Fuel,
         ),
-        With<Player>,
+        (With<Player>, Without<Crashed>),
     >,
 ) {
-    if let Some((mut rb_forces, rb_vel, rb_pos, rb_mprops)) = player_query.iter_mut().next() {
-        let pitch_axis = -player_input.axis.y;
-        let roll_axis = player_input.axis.x;
-        let yaw_axis = player_input.yaw;
+    for (player_input, mut rb_forces, rb_vel, rb_pos, rb_mprops, damage, fuel) in
+        player_query.iter_mut()
+    {
+        let pitch_axis = -player_input.axis.y * damage.pitch_factor();
+        let roll_axis = player_input.axis.x * damage.roll_factor() + damage.roll_bias();
+        let yaw_axis = player_input.yaw * damage.yaw_factor();
 
         let lift_up = calculate_lift(
             rb_vel.linvel,
             rb_pos.position.rotation,
             Vector3::from(Vec3::Y),
-            10.,
+            10. * damage.lift_factor(),
         );
         let lift_side = calculate_lift(
             rb_vel.linvel,
@@ -252,8 +269,17 @@ pub fn player_movement(
             15.,
         );
 
-        let thrust_raw: Vector3<f32> =
-            Vec3::new(player_input.accel * ACCEL * rb_mprops.mass(), 0., 0.).into();
+        let throttle = if fuel.is_empty() {
+            0.
+        } else {
+            player_input.accel
+        };
+        let thrust_raw: Vector3<f32> = Vec3::new(
+            throttle * ACCEL * damage.thrust_factor() * rb_mprops.mass(),
+            0.,
+            0.,
+        )
+        .into();
         let thrust: Vector3<f32> = rb_pos.position.rotation * thrust_raw;
 
         let drag_amount = 0.01 * f32::powi(rb_vel.linvel.magnitude(), 2);
@@ -282,117 +308,67 @@ pub fn player_movement(
     }
 }
 
-pub fn fire_missle(
-    mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    mut gamepad_event: EventReader<GamepadEvent>,
-    mut player_query: Query<(&Transform, &mut Player, &RigidBodyVelocityComponent)>,
-) {
-    if let Some((player_transform, mut player, rb_vel)) = player_query.iter_mut().next() {
-        for event in gamepad_event.iter() {
-            match &event {
-                GamepadEvent(
-                    _,
-                    GamepadEventType::ButtonChanged(GamepadButtonType::East, value),
-                ) => {
-                    if *value > 0. {
-                        commands
-                            .spawn_bundle(PbrBundle {
-                                mesh: meshes.add(Mesh::from(bevy::render::mesh::shape::Capsule {
-                                    radius: 0.03,
-                                    depth: 0.5,
-                                    ..Default::default()
-                                })),
-                                material: materials.add(StandardMaterial {
-                                    base_color: Color::GRAY,
-                                    ..Default::default()
-                                }),
-                                transform: Transform {
-                                    translation: player_transform.translation
-                                        - (player_transform.rotation * Vec3::Y * 0.25)
-                                        + player_transform.rotation
-                                            * Vec3::Z
-                                            * if player.missiles_fired % 2 == 0 {
-                                                0.6
-                                            } else {
-                                                -0.6
-                                            },
-                                    rotation: player_transform.rotation
-                                        * Quat::from_rotation_z(-std::f32::consts::FRAC_PI_2),
-                                    ..Default::default()
-                                },
-                                ..Default::default()
-                            })
-                            .insert(Missile {
-                                target: None,
-                                velocity: rb_vel.linvel.magnitude(),
-                                lifetime: 5.,
-                            })
-                            // .insert(Emitter {
-                            //     direction: Vec3::X,
-                            //     spread: 0.,
-                            //     speed: 0.5,
-                            //     lifetime: 3.,
-                            //     last_emitted: None,
-                            // })
-                            ;
-                        player.missiles_fired = player.missiles_fired + 1;
-                    }
-                }
-                _ => {}
+pub fn track_target_velocity(mut query: Query<(&Transform, &mut TargetVelocity)>, time: Res<Time>) {
+    for (transform, mut target_velocity) in query.iter_mut() {
+        if let Some(last_position) = target_velocity.last_position {
+            if time.delta_seconds() > 0. {
+                target_velocity.linvel =
+                    (transform.translation - last_position) / time.delta_seconds();
             }
         }
+        target_velocity.last_position = Some(transform.translation);
     }
 }
 
-pub fn missle_run(
-    mut commands: Commands,
-    mut missile_query: Query<(&mut Missile, Entity)>,
-    mut transforms_query: QuerySet<(
-        QueryState<&mut Transform, With<Missile>>,
-        QueryState<&Transform, With<Target>>,
-    )>,
-    time: Res<Time>,
+/// Picks the nearest hostile target whenever the player has none.
+pub fn select_target(
+    mut player_query: Query<(&Transform, &Faction, &SensorContacts, &mut Player)>,
+    target_query: Query<&Faction, With<Target>>,
 ) {
-    for (mut missile, missile_entity) in missile_query.iter_mut() {
-        let target_translation = missile.target.map(|target| {
-            let target_transform = transforms_query.q1().get(target).unwrap();
-            target_transform.translation
-        });
-
-        for mut missile_transform in transforms_query.q0().get_mut(missile_entity) {
-            let current_dir = (missile_transform.rotation * Vec3::Y).normalize_or_zero();
-
-            let target_dir = match target_translation {
-                Some(dir) => (dir - missile_transform.translation).normalize_or_zero(),
-                None => current_dir,
-            };
-
-            let velocity =
-                if current_dir.angle_between(target_dir).abs() < std::f32::consts::FRAC_PI_2 {
-                    current_dir
-                        .lerp(target_dir, time.delta_seconds() * 1.5)
-                        .normalize_or_zero()
-                        * missile.velocity
-                } else {
-                    current_dir * missile.velocity
-                };
-
-            missile.velocity = (missile.velocity + time.delta_seconds() * 50.).clamp(0., 400.);
-
-            missile_transform.translation =
-                missile_transform.translation + velocity * time.delta_seconds();
-            missile_transform.rotation =
-                Quat::from_rotation_arc(Vec3::Y, velocity.normalize_or_zero());
-
-            let distance_to_target = target_translation
-                .map(|t| (t - missile_transform.translation).length())
-                .unwrap_or(f32::INFINITY);
-            missile.lifetime -= time.delta_seconds();
-            if distance_to_target < 2. || missile.lifetime < 0. {
-                commands.entity(missile_entity).despawn_recursive();
+    for (player_transform, player_faction, contacts, mut player) in player_query.iter_mut() {
+        if player.target.map_or(false, |target| {
+            target_query.get(target).is_ok() && contacts.is_detected(target)
+        }) {
+            continue;
+        }
+
+        player.target = contacts
+            .detected()
+            .filter(|(target, _)| {
+                target_query.get(*target).map_or(false, |target_faction| {
+                    is_hostile(*player_faction, *target_faction)
+                })
+            })
+            .map(|(target, track)| {
+                (
+                    target,
+                    (track.position - player_transform.translation).length(),
+                )
+            })
+            .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
+            .map(|(target, _)| target);
+    }
+}
+
+pub fn fire_missle(
+    button_inputs: Res<Input<GamepadButton>>,
+    mut fire_events: EventWriter<FireWeapon>,
+    mut player_query: Query<(Entity, &mut Player, &Loadout, &InputDevice), Without<Crashed>>,
+) {
+    for (player_entity, mut player, loadout, device) in player_query.iter_mut() {
+        let pressed = match device {
+            InputDevice::Gamepad(gamepad) => {
+                button_inputs.just_pressed(GamepadButton(*gamepad, GamepadButtonType::East))
             }
+            _ => false,
+        };
+        if pressed && loadout.count(WeaponType::Missile) > 0 {
+            fire_events.send(FireWeapon {
+                shooter: player_entity,
+                weapon: WeaponType::Missile,
+                target: player.target,
+            });
+            player.missiles_fired = player.missiles_fired + 1;
         }
     }
 }
@@ -0,0 +1,119 @@
+use bevy::prelude::*;
+
+use super::input::*;
+
+pub const RADAR_RANGES: [f32; 4] = [500., 1000., 2000., 4000.];
+pub const RADAR_SWEEP_PERIOD: f32 = 2.;
+/// Height difference beyond which a contact is drawn as above or below the player.
+pub const RADAR_ALTITUDE_BAND: f32 = 150.;
+/// Fraction of a contact's brightness lost by the time the sweep comes round again.
+pub const RADAR_SWEEP_FADE: f32 = 0.7;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RadarMode {
+    HeadingUp,
+    NorthUp,
+}
+
+pub struct RadarSettings {
+    pub range_index: usize,
+    pub mode: RadarMode,
+}
+
+impl Default for RadarSettings {
+    fn default() -> Self {
+        RadarSettings {
+            range_index: 1,
+            mode: RadarMode::HeadingUp,
+        }
+    }
+}
+
+impl RadarSettings {
+    pub fn range(&self) -> f32 {
+        RADAR_RANGES[self.range_index % RADAR_RANGES.len()]
+    }
+
+    pub fn label(&self) -> String {
+        format!(
+            "{}km {}",
+            self.range() / 1000.,
+            match self.mode {
+                RadarMode::HeadingUp => "HDG",
+                RadarMode::NorthUp => "N-UP",
+            }
+        )
+    }
+}
+
+/// Position of a contact `offset` from the player on the scope, in `[-1, 1]` on each axis with +y
+/// at the top. `heading` is the player's compass heading in degrees. Contacts beyond `range` are
+/// clamped to the edge, which is reported by the second value.
+pub fn scope_position(offset: Vec3, heading: f32, mode: RadarMode, range: f32) -> (Vec2, bool) {
+    let east = offset.x;
+    let north = -offset.z;
+    let position = match mode {
+        RadarMode::NorthUp => Vec2::new(east, north),
+        RadarMode::HeadingUp => {
+            let (sin, cos) = heading.to_radians().sin_cos();
+            Vec2::new(east * cos - north * sin, east * sin + north * cos)
+        }
+    } / range;
+
+    let extent = position.x.abs().max(position.y.abs());
+    if extent > 1. {
+        (position / extent, true)
+    } else {
+        (position, false)
+    }
+}
+
+/// Angle of the sweep line clockwise from the top of the scope, in radians.
+pub fn sweep_angle(seconds: f64) -> f32 {
+    ((seconds / RADAR_SWEEP_PERIOD as f64).fract() * std::f64::consts::TAU) as f32
+}
+
+/// Brightness of a contact at `position` on the scope, brightest just after the sweep passes.
+pub fn sweep_fade(sweep_angle: f32, position: Vec2) -> f32 {
+    let contact_angle = position.x.atan2(position.y);
+    let since_sweep = (sweep_angle - contact_angle).rem_euclid(std::f32::consts::TAU);
+    1. - RADAR_SWEEP_FADE * since_sweep / std::f32::consts::TAU
+}
+
+pub fn contact_symbol(altitude_difference: f32) -> &'static str {
+    if altitude_difference > RADAR_ALTITUDE_BAND {
+        "▲"
+    } else if altitude_difference < -RADAR_ALTITUDE_BAND {
+        "▼"
+    } else {
+        "●"
+    }
+}
+
+pub fn radar_controls(
+    keyboard_input: Res<Input<KeyCode>>,
+    key_bindings: Res<KeyBindings>,
+    gamepads: Res<Gamepads>,
+    button_inputs: Res<Input<GamepadButton>>,
+    mut settings: ResMut<RadarSettings>,
+) {
+    let gamepad_pressed = |button_type: GamepadButtonType| {
+        gamepads
+            .iter()
+            .any(|gamepad| button_inputs.just_pressed(GamepadButton(*gamepad, button_type)))
+    };
+
+    if keyboard_input.just_pressed(key_bindings.radar_range)
+        || gamepad_pressed(GamepadButtonType::RightThumb)
+    {
+        settings.range_index = (settings.range_index + 1) % RADAR_RANGES.len();
+    }
+    if keyboard_input.just_pressed(key_bindings.radar_mode)
+        || gamepad_pressed(GamepadButtonType::LeftThumb)
+    {
+        settings.mode = match settings.mode {
+            RadarMode::HeadingUp => RadarMode::NorthUp,
+            RadarMode::NorthUp => RadarMode::HeadingUp,
+        };
+    }
+}
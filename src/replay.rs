@@ -0,0 +1,953 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use bevy::{input::mouse::MouseMotion, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use super::camera::*;
+use super::countermeasures::*;
+use super::damage::*;
+use super::guidance::*;
+use super::menu::*;
+use super::mission::*;
+use super::player::*;
+use super::save::SAVE_DIR_NAME;
+use super::weapons::*;
+
+/// Seconds between recorded frames.
+pub const REPLAY_TICK: f32 = 1. / 20.;
+/// Longest stretch of a sortie kept, in seconds. Older frames are dropped.
+pub const REPLAY_MAX_SECONDS: f32 = 15. * 60.;
+pub const REPLAY_DIR_NAME: &str = "replays";
+pub const REPLAY_FILENAME: &str = "last.ron";
+pub const REPLAY_SPEEDS: [f32; 5] = [0.125, 0.25, 0.5, 1., 2.];
+/// Playback rate while rewinding or fast-forwarding.
+pub const REPLAY_SEEK_SPEED: f32 = 5.;
+/// Seconds the director holds a shot before cutting to another.
+pub const DIRECTOR_SHOT_LENGTH: f32 = 6.;
+pub const TIMELINE_WIDTH: f32 = 600.;
+pub const TIMELINE_HEIGHT: f32 = 10.;
+pub const TIMELINE_BOTTOM: f32 = 30.;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ReplayKind {
+    Aircraft { player: bool },
+    Missile,
+    Flare,
+    Chaff,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ReplayObject {
+    /// Identifies the same object from frame to frame.
+    pub id: u64,
+    pub kind: ReplayKind,
+    pub position: [f32; 3],
+    pub rotation: [f32; 4],
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ReplayFrame {
+    /// Seconds since the start of the recording.
+    pub time: f32,
+    pub objects: Vec<ReplayObject>,
+}
+
+/// Transforms of every aircraft, missile and decoy through a sortie.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Replay {
+    pub mission: String,
+    pub frames: Vec<ReplayFrame>,
+}
+
+/// An object's state at some moment of a replay, between recorded frames.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReplaySample {
+    pub id: u64,
+    pub kind: ReplayKind,
+    pub transform: Transform,
+    pub velocity: Vec3,
+    /// Turn rate as a world scaled axis per second.
+    pub angular_velocity: Vec3,
+}
+
+impl Replay {
+    pub fn start_time(&self) -> f32 {
+        self.frames.first().map_or(0., |frame| frame.time)
+    }
+
+    pub fn end_time(&self) -> f32 {
+        self.frames.last().map_or(0., |frame| frame.time)
+    }
+
+    /// Objects at `time`, interpolated between the frames either side of it. Objects that
+    /// appear or vanish between those frames are shown as of the earlier one.
+    pub fn sample(&self, time: f32) -> Vec<ReplaySample> {
+        let next_index = self.frames.partition_point(|frame| frame.time <= time);
+        let (previous, next) = match next_index {
+            0 => match self.frames.first() {
+                Some(first) => (first, first),
+                None => return Vec::new(),
+            },
+            index if index >= self.frames.len() => {
+                let last = &self.frames[self.frames.len() - 1];
+                (last, last)
+            }
+            index => (&self.frames[index - 1], &self.frames[index]),
+        };
+        let span = next.time - previous.time;
+        let t = if span > 0. {
+            ((time - previous.time) / span).clamp(0., 1.)
+        } else {
+            0.
+        };
+
+        previous
+            .objects
+            .iter()
+            .map(|object| {
+                let from = object_transform(object);
+                match next.objects.iter().find(|other| other.id == object.id) {
+                    Some(other) if span > 0. => {
+                        let to = object_transform(other);
+                        let mut turn = to.rotation * from.rotation.inverse();
+                        if turn.w < 0. {
+                            turn = -turn;
+                        }
+                        ReplaySample {
+                            id: object.id,
+                            kind: object.kind,
+                            transform: Transform {
+                                translation: from.translation.lerp(to.translation, t),
+                                rotation: from.rotation.slerp(to.rotation, t),
+                                ..Default::default()
+                            },
+                            velocity: (to.translation - from.translation) / span,
+                            angular_velocity: turn.to_scaled_axis() / span,
+                        }
+                    }
+                    _ => ReplaySample {
+                        id: object.id,
+                        kind: object.kind,
+                        transform: from,
+                        velocity: Vec3::ZERO,
+                        angular_velocity: Vec3::ZERO,
+                    },
+                }
+            })
+            .collect()
+    }
+
+    pub fn load(path: &Path) -> Option<Self> {
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return None,
+            Err(e) => {
+                println!("Failed to load {}: {}", path.display(), e);
+                return None;
+            }
+        };
+        match ron::from_str(&contents) {
+            Ok(replay) => Some(replay),
+            Err(e) => {
+                println!("Failed to load {}: {}", path.display(), e);
+                None
+            }
+        }
+    }
+
+    pub fn write(&self, path: &Path) {
+        let temporary = path.with_extension("ron.tmp");
+        let written = path
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .map_err(|e| e.to_string())
+            .and_then(|_| ron::to_string(self).map_err(|e| e.to_string()))
+            .and_then(|contents| std::fs::write(&temporary, contents).map_err(|e| e.to_string()))
+            .and_then(|_| std::fs::rename(&temporary, path).map_err(|e| e.to_string()));
+        if let Err(e) = written {
+            println!("Failed to write {}: {}", path.display(), e);
+        }
+    }
+}
+
+fn object_transform(object: &ReplayObject) -> Transform {
+    Transform {
+        translation: Vec3::from(object.position),
+        rotation: Quat::from_xyzw(
+            object.rotation[0],
+            object.rotation[1],
+            object.rotation[2],
+            object.rotation[3],
+        ),
+        ..Default::default()
+    }
+}
+
+pub fn replay_path() -> PathBuf {
+    dirs::data_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join(SAVE_DIR_NAME)
+        .join(REPLAY_DIR_NAME)
+        .join(REPLAY_FILENAME)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Shot {
+    Chase(u64),
+    FlyBy(u64),
+    Missile(u64),
+}
+
+impl Shot {
+    pub fn subject(&self) -> u64 {
+        match self {
+            Shot::Chase(id) | Shot::FlyBy(id) | Shot::Missile(id) => *id,
+        }
+    }
+}
+
+/// Picks what the auto-director shows. A shot is held until it runs its length or its subject
+/// disappears, except that missiles are followed until they hit. New missiles take priority;
+/// otherwise the player's aircraft is shown, alternating between chase and fly-by shots.
+pub fn choose_shot(samples: &[ReplaySample], current: Option<Shot>, shot_age: f32) -> Option<Shot> {
+    let present = |id: u64| samples.iter().any(|sample| sample.id == id);
+    match current {
+        Some(Shot::Missile(id)) if present(id) => return current,
+        Some(shot) if present(shot.subject()) && shot_age < DIRECTOR_SHOT_LENGTH => return current,
+        _ => {}
+    }
+
+    let missile = samples
+        .iter()
+        .filter(|sample| sample.kind == ReplayKind::Missile)
+        .map(|sample| sample.id)
+        .max();
+    let last_missile = match current {
+        Some(Shot::Missile(id)) => Some(id),
+        _ => None,
+    };
+    if let Some(missile) = missile.filter(|missile| Some(*missile) != last_missile) {
+        return Some(Shot::Missile(missile));
+    }
+
+    let subject = samples
+        .iter()
+        .find(|sample| sample.kind == ReplayKind::Aircraft { player: true })
+        .or_else(|| {
+            samples
+                .iter()
+                .find(|sample| matches!(sample.kind, ReplayKind::Aircraft { .. }))
+        })?
+        .id;
+    Some(match current {
+        Some(Shot::Chase(_)) => Shot::FlyBy(subject),
+        _ => Shot::Chase(subject),
+    })
+}
+
+#[derive(Default)]
+pub struct ReplayRecorder {
+    pub replay: Replay,
+    elapsed: f32,
+    since_frame: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplayCamera {
+    Auto,
+    Free,
+}
+
+pub struct ReplayViewer {
+    pub replay: Replay,
+    pub time: f32,
+    pub paused: bool,
+    pub speed_index: usize,
+    pub camera: ReplayCamera,
+    samples: Vec<ReplaySample>,
+    ghosts: HashMap<u64, Entity>,
+    shot: Option<Shot>,
+    shot_age: f32,
+    fly_by_anchor: Vec3,
+    rig: CameraRig,
+}
+
+impl Default for ReplayViewer {
+    fn default() -> Self {
+        ReplayViewer::new(Replay::default())
+    }
+}
+
+impl ReplayViewer {
+    pub fn new(replay: Replay) -> Self {
+        ReplayViewer {
+            time: replay.start_time(),
+            replay,
+            paused: false,
+            speed_index: REPLAY_SPEEDS
+                .iter()
+                .position(|speed| *speed == 1.)
+                .unwrap_or(0),
+            camera: ReplayCamera::Auto,
+            samples: Vec::new(),
+            ghosts: HashMap::new(),
+            shot: None,
+            shot_age: 0.,
+            fly_by_anchor: Vec3::ZERO,
+            rig: CameraRig::default(),
+        }
+    }
+
+    pub fn speed(&self) -> f32 {
+        REPLAY_SPEEDS[self.speed_index.min(REPLAY_SPEEDS.len() - 1)]
+    }
+
+    pub fn seek(&mut self, time: f32) {
+        self.time = time.clamp(self.replay.start_time(), self.replay.end_time());
+    }
+
+    /// Start and end of the recording and the playhead, as `mm:ss`.
+    pub fn label(&self) -> String {
+        let clock = |seconds: f32| {
+            let seconds = seconds.max(0.) as u32;
+            format!("{:02}:{:02}", seconds / 60, seconds % 60)
+        };
+        format!(
+            "REPLAY {}  {} / {}  x{}{}  CAM {}\n[SPACE] Pause  [</>] Rewind/Forward  [UP/DOWN] Speed  [C] Camera  [TAB] Cut  [ESC] Exit",
+            self.replay.mission.to_uppercase(),
+            clock(self.time - self.replay.start_time()),
+            clock(self.replay.end_time() - self.replay.start_time()),
+            self.speed(),
+            if self.paused { "  PAUSED" } else { "" },
+            match self.camera {
+                ReplayCamera::Auto => "AUTO",
+                ReplayCamera::Free => "FREE",
+            }
+        )
+    }
+}
+
+#[derive(Component)]
+pub struct ReplayGhost;
+
+#[derive(Component)]
+pub struct ReplayTimeline;
+
+#[derive(Component)]
+pub struct ReplayTimelineFill;
+
+#[derive(Component)]
+pub struct ReplayText;
+
+pub fn setup_replay_ui(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Px(TIMELINE_WIDTH), Val::Px(TIMELINE_HEIGHT)),
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Px(-10000.),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            color: Color::rgba(1., 1., 1., 0.2).into(),
+            ..Default::default()
+        })
+        .insert(ReplayTimeline)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        size: Size::new(Val::Percent(0.), Val::Percent(100.)),
+                        ..Default::default()
+                    },
+                    color: Color::GREEN.into(),
+                    ..Default::default()
+                })
+                .insert(ReplayTimelineFill);
+            parent
+                .spawn_bundle(TextBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        position: Rect {
+                            left: Val::Px(0.),
+                            bottom: Val::Px(TIMELINE_HEIGHT + 6.),
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    },
+                    text: Text::with_section(
+                        "",
+                        TextStyle {
+                            font: asset_server.load("fonts/FiraMono-Medium.ttf"),
+                            font_size: 16.0,
+                            color: Color::GREEN,
+                        },
+                        Default::default(),
+                    ),
+                    ..Default::default()
+                })
+                .insert(ReplayText);
+        });
+}
+
+pub fn start_recording(mut recorder: ResMut<ReplayRecorder>, mission: Res<MissionDef>) {
+    *recorder = ReplayRecorder {
+        replay: Replay {
+            mission: mission.name.clone(),
+            frames: Vec::new(),
+        },
+        elapsed: 0.,
+        since_frame: REPLAY_TICK,
+    };
+}
+
+pub fn record_replay(
+    mut recorder: ResMut<ReplayRecorder>,
+    aircraft_query: Query<(Entity, &Transform, Option<&Player>), With<AircraftDamage>>,
+    missile_query: Query<(Entity, &Transform), With<Missile>>,
+    decoy_query: Query<(Entity, &Transform, &Decoy)>,
+    time: Res<Time>,
+) {
+    recorder.elapsed += time.delta_seconds();
+    recorder.since_frame += time.delta_seconds();
+    if recorder.since_frame < REPLAY_TICK {
+        return;
+    }
+    recorder.since_frame = 0.;
+
+    let object = |entity: Entity, kind: ReplayKind, transform: &Transform| ReplayObject {
+        id: entity.to_bits(),
+        kind,
+        position: transform.translation.into(),
+        rotation: transform.rotation.to_array(),
+    };
+    let mut objects: Vec<ReplayObject> = aircraft_query
+        .iter()
+        .map(|(entity, transform, player)| {
+            object(
+                entity,
+                ReplayKind::Aircraft {
+                    player: player.is_some(),
+                },
+                transform,
+            )
+        })
+        .collect();
+    objects.extend(
+        missile_query
+            .iter()
+            .map(|(entity, transform)| object(entity, ReplayKind::Missile, transform)),
+    );
+    objects.extend(decoy_query.iter().map(|(entity, transform, decoy)| {
+        let kind = match decoy.kind {
+            DecoyKind::Flare => ReplayKind::Flare,
+            DecoyKind::Chaff => ReplayKind::Chaff,
+        };
+        object(entity, kind, transform)
+    }));
+
+    let frame = ReplayFrame {
+        time: recorder.elapsed,
+        objects,
+    };
+    let frames = &mut recorder.replay.frames;
+    frames.push(frame);
+    let oldest = recorder.elapsed - REPLAY_MAX_SECONDS;
+    let expired = frames.partition_point(|frame| frame.time < oldest);
+    frames.drain(..expired);
+}
+
+pub fn save_replay(recorder: Res<ReplayRecorder>) {
+    if !recorder.replay.frames.is_empty() {
+        recorder.replay.write(&replay_path());
+    }
+}
+
+pub fn replay_controls(
+    mut state: ResMut<State<AppState>>,
+    mut viewer: ResMut<ReplayViewer>,
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    mut button_inputs: ResMut<Input<GamepadButton>>,
+    mouse_input: Res<Input<MouseButton>>,
+    windows: Res<Windows>,
+    time: Res<Time>,
+) {
+    if back_pressed(&mut keyboard_input, &gamepads, &mut button_inputs) {
+        queue_state(state.pop());
+        return;
+    }
+
+    let at_end = viewer.time >= viewer.replay.end_time();
+    if pressed(&mut keyboard_input, KeyCode::Space)
+        || gamepad_pressed(&gamepads, &mut button_inputs, GamepadButtonType::South)
+    {
+        if viewer.paused && at_end {
+            let start = viewer.replay.start_time();
+            viewer.seek(start);
+        }
+        viewer.paused = !viewer.paused;
+    }
+    if pressed(&mut keyboard_input, KeyCode::Up)
+        || gamepad_pressed(&gamepads, &mut button_inputs, GamepadButtonType::DPadUp)
+    {
+        viewer.speed_index = (viewer.speed_index + 1).min(REPLAY_SPEEDS.len() - 1);
+    }
+    if pressed(&mut keyboard_input, KeyCode::Down)
+        || gamepad_pressed(&gamepads, &mut button_inputs, GamepadButtonType::DPadDown)
+    {
+        viewer.speed_index = viewer.speed_index.saturating_sub(1);
+    }
+    if pressed(&mut keyboard_input, KeyCode::C)
+        || gamepad_pressed(&gamepads, &mut button_inputs, GamepadButtonType::North)
+    {
+        viewer.camera = match viewer.camera {
+            ReplayCamera::Auto => ReplayCamera::Free,
+            ReplayCamera::Free => ReplayCamera::Auto,
+        };
+    }
+    if pressed(&mut keyboard_input, KeyCode::Tab)
+        || gamepad_pressed(
+            &gamepads,
+            &mut button_inputs,
+            GamepadButtonType::RightTrigger,
+        )
+    {
+        viewer.shot_age = DIRECTOR_SHOT_LENGTH;
+    }
+    if pressed(&mut keyboard_input, KeyCode::Home) {
+        let start = viewer.replay.start_time();
+        viewer.seek(start);
+    }
+
+    let held = |key: KeyCode, button_type: GamepadButtonType| {
+        keyboard_input.pressed(key)
+            || gamepads
+                .iter()
+                .any(|gamepad| button_inputs.pressed(GamepadButton(*gamepad, button_type)))
+    };
+    let rate = if held(KeyCode::Left, GamepadButtonType::DPadLeft) {
+        -REPLAY_SEEK_SPEED
+    } else if held(KeyCode::Right, GamepadButtonType::DPadRight) {
+        REPLAY_SEEK_SPEED
+    } else if viewer.paused {
+        0.
+    } else {
+        viewer.speed()
+    };
+    let time = viewer.time + rate * time.delta_seconds();
+    viewer.seek(time);
+    if !viewer.paused && viewer.time >= viewer.replay.end_time() {
+        viewer.paused = true;
+    }
+
+    // Clicking or dragging along the timeline jumps to that point.
+    if mouse_input.pressed(MouseButton::Left) {
+        let window = windows.get_primary();
+        let cursor = window.and_then(|window| window.cursor_position());
+        if let (Some(window), Some(cursor)) = (window, cursor) {
+            let left = (window.width() - TIMELINE_WIDTH) / 2.;
+            let fraction = (cursor.x - left) / TIMELINE_WIDTH;
+            let on_timeline = (0. ..=1.).contains(&fraction)
+                && (cursor.y - TIMELINE_BOTTOM).abs() < TIMELINE_HEIGHT * 2.;
+            if on_timeline {
+                let start = viewer.replay.start_time();
+                let end = viewer.replay.end_time();
+                viewer.seek(start + (end - start) * fraction);
+            }
+        }
+    }
+}
+
+/// Moves the replay's stand-in objects to where they were at the playhead.
+pub fn replay_playback(
+    mut viewer: ResMut<ReplayViewer>,
+    mut ghost_query: Query<&mut Transform, With<ReplayGhost>>,
+    missile_assets: Res<MissileAssets>,
+    decoy_assets: Res<DecoyAssets>,
+    asset_server: Res<AssetServer>,
+    mut commands: Commands,
+) {
+    let viewer = &mut *viewer;
+    viewer.samples = viewer.replay.sample(viewer.time);
+
+    let samples = &viewer.samples;
+    viewer.ghosts.retain(|id, ghost| {
+        let present = samples.iter().any(|sample| sample.id == *id);
+        if !present {
+            commands.entity(*ghost).despawn_recursive();
+        }
+        present
+    });
+
+    for sample in viewer.samples.iter() {
+        if let Some(ghost) = viewer.ghosts.get(&sample.id) {
+            if let Ok(mut transform) = ghost_query.get_mut(*ghost) {
+                *transform = sample.transform;
+            }
+            continue;
+        }
+
+        let ghost = match sample.kind {
+            ReplayKind::Aircraft { .. } => commands
+                .spawn_bundle((sample.transform, GlobalTransform::identity()))
+                .with_children(|parent| {
+                    parent.spawn_scene(asset_server.load("f35.gltf#Scene0"));
+                })
+                .id(),
+            ReplayKind::Missile | ReplayKind::Flare | ReplayKind::Chaff => {
+                let (mesh, material) = match sample.kind {
+                    ReplayKind::Missile => {
+                        (missile_assets.mesh.clone(), missile_assets.material.clone())
+                    }
+                    ReplayKind::Flare => (
+                        decoy_assets.flare_mesh.clone(),
+                        decoy_assets.flare_material.clone(),
+                    ),
+                    _ => (
+                        decoy_assets.chaff_mesh.clone(),
+                        decoy_assets.chaff_material.clone(),
+                    ),
+                };
+                commands
+                    .spawn_bundle(PbrBundle {
+                        mesh,
+                        material,
+                        transform: sample.transform,
+                        ..Default::default()
+                    })
+                    .id()
+            }
+        };
+        commands.entity(ghost).insert(ReplayGhost);
+        viewer.ghosts.insert(sample.id, ghost);
+    }
+}
+
+pub fn replay_camera(
+    mut viewer: ResMut<ReplayViewer>,
+    settings: Res<CameraSettings>,
+    mut camera_query: Query<
+        (&mut Transform, &mut PerspectiveProjection, &mut Camera),
+        With<MainCamera>,
+    >,
+    keyboard_input: Res<Input<KeyCode>>,
+    mouse_input: Res<Input<MouseButton>>,
+    mut mouse_motion: EventReader<MouseMotion>,
+    windows: Res<Windows>,
+    time: Res<Time>,
+) {
+    let (mut camera_transform, mut perspective_projection, mut camera) =
+        match camera_query.get_single_mut() {
+            Ok(camera) => camera,
+            Err(_) => return,
+        };
+    let delta_seconds = time.delta_seconds();
+    let viewer = &mut *viewer;
+
+    let fov = match viewer.camera {
+        ReplayCamera::Free => {
+            let (look, movement) =
+                free_camera_input(&keyboard_input, &mouse_input, &mut mouse_motion);
+            *camera_transform = fly_free_camera(
+                &camera_transform,
+                &settings.free,
+                look,
+                movement,
+                delta_seconds,
+            );
+            viewer.shot = None;
+            settings.free.fov
+        }
+        ReplayCamera::Auto => {
+            // The director's clock runs with the replay so shots stay put while paused.
+            viewer.shot_age += (delta_seconds * viewer.speed()).max(0.);
+            let shot = choose_shot(&viewer.samples, viewer.shot, viewer.shot_age);
+            let cut = shot != viewer.shot;
+            if cut {
+                viewer.shot = shot;
+                viewer.shot_age = 0.;
+            }
+            let subject = shot.and_then(|shot| {
+                viewer
+                    .samples
+                    .iter()
+                    .find(|sample| sample.id == shot.subject())
+            });
+
+            match (shot, subject) {
+                (Some(Shot::Chase(_)), Some(subject)) => {
+                    let chase = &settings.chase;
+                    let pose = chase_pose(chase, &subject.transform, Vec2::ZERO, 0.5, 1.);
+                    // The rig runs on wall-clock time, so scale the subject's motion to it.
+                    let rate = if viewer.paused { 0. } else { viewer.speed() };
+                    let velocity = subject.velocity * rate;
+                    let angular_velocity = subject.angular_velocity * rate;
+                    if cut {
+                        viewer.rig = CameraRig::moving(pose, velocity);
+                    }
+                    viewer.rig.step(
+                        &pose,
+                        velocity,
+                        angular_velocity,
+                        &chase.position_spring,
+                        &chase.rotation_spring,
+                        delta_seconds,
+                    );
+                    *camera_transform = viewer.rig.transform;
+                    chase.fov
+                }
+                (Some(Shot::FlyBy(_)), Some(subject)) => {
+                    if cut {
+                        viewer.fly_by_anchor =
+                            fly_by_anchor(&settings.fly_by, &subject.transform, subject.velocity);
+                    }
+                    *camera_transform = Transform::from_translation(viewer.fly_by_anchor)
+                        .looking_at(subject.transform.translation, Vec3::Y);
+                    settings.fly_by.fov
+                }
+                (Some(Shot::Missile(_)), Some(subject)) => {
+                    *camera_transform = missile_pose(
+                        &settings.missile,
+                        subject.transform.translation,
+                        subject.velocity,
+                    );
+                    settings.missile.fov
+                }
+                _ => perspective_projection.fov,
+            }
+        }
+    };
+
+    perspective_projection.fov = fov;
+    apply_projection(&mut perspective_projection, &mut camera, &windows);
+}
+
+pub fn replay_ui(
+    viewer: Res<ReplayViewer>,
+    mut timeline_query: Query<&mut Style, With<ReplayTimeline>>,
+    mut fill_query: Query<&mut Style, (With<ReplayTimelineFill>, Without<ReplayTimeline>)>,
+    mut text_query: Query<&mut Text, With<ReplayText>>,
+    windows: Res<Windows>,
+) {
+    let window_width = windows
+        .get_primary()
+        .map_or(TIMELINE_WIDTH, |window| window.width());
+    for mut style in timeline_query.iter_mut() {
+        style.position = Rect {
+            left: Val::Px((window_width - TIMELINE_WIDTH) / 2.),
+            bottom: Val::Px(TIMELINE_BOTTOM),
+            ..Default::default()
+        };
+    }
+
+    let (start, end) = (viewer.replay.start_time(), viewer.replay.end_time());
+    let fraction = if end > start {
+        (viewer.time - start) / (end - start)
+    } else {
+        0.
+    };
+    for mut style in fill_query.iter_mut() {
+        style.size.width = Val::Percent(fraction * 100.);
+    }
+    for mut text in text_query.iter_mut() {
+        text.sections[0].value = viewer.label();
+    }
+}
+
+pub fn exit_replay(
+    mut viewer: ResMut<ReplayViewer>,
+    mut timeline_query: Query<&mut Style, With<ReplayTimeline>>,
+    ghost_query: Query<Entity, With<ReplayGhost>>,
+    mut commands: Commands,
+) {
+    for ghost in ghost_query.iter() {
+        commands.entity(ghost).despawn_recursive();
+    }
+    viewer.ghosts.clear();
+    viewer.shot = None;
+    for mut style in timeline_query.iter_mut() {
+        style.position = Rect {
+            left: Val::Px(-10000.),
+            ..Default::default()
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn object(id: u64, kind: ReplayKind, position: [f32; 3], rotation: Quat) -> ReplayObject {
+        ReplayObject {
+            id,
+            kind,
+            position,
+            rotation: rotation.to_array(),
+        }
+    }
+
+    fn replay() -> Replay {
+        let player = ReplayKind::Aircraft { player: true };
+        Replay {
+            mission: "Test".to_string(),
+            frames: vec![
+                ReplayFrame {
+                    time: 1.,
+                    objects: vec![
+                        object(1, player, [0., 100., 0.], Quat::IDENTITY),
+                        object(2, ReplayKind::Flare, [5., 90., 0.], Quat::IDENTITY),
+                    ],
+                },
+                ReplayFrame {
+                    time: 1.5,
+                    objects: vec![
+                        object(1, player, [100., 110., 0.], Quat::from_rotation_y(0.2)),
+                        object(3, ReplayKind::Missile, [0., 0., 0.], Quat::IDENTITY),
+                    ],
+                },
+            ],
+        }
+    }
+
+    fn sample(samples: &[ReplaySample], id: u64) -> ReplaySample {
+        *samples.iter().find(|sample| sample.id == id).unwrap()
+    }
+
+    fn aircraft(id: u64, player: bool) -> ReplaySample {
+        ReplaySample {
+            id,
+            kind: ReplayKind::Aircraft { player },
+            transform: Transform::identity(),
+            velocity: Vec3::ZERO,
+            angular_velocity: Vec3::ZERO,
+        }
+    }
+
+    fn missile(id: u64) -> ReplaySample {
+        ReplaySample {
+            kind: ReplayKind::Missile,
+            ..aircraft(id, false)
+        }
+    }
+
+    #[test]
+    fn replay_round_trips_through_ron() {
+        let replay = replay();
+        let text = ron::to_string(&replay).unwrap();
+        let loaded: Replay = ron::from_str(&text).unwrap();
+        assert_eq!(loaded, replay);
+    }
+
+    #[test]
+    fn sample_at_frames_matches_recording() {
+        let replay = replay();
+        assert_eq!(replay.start_time(), 1.);
+        assert_eq!(replay.end_time(), 1.5);
+
+        let first = replay.sample(1.);
+        assert_eq!(first.len(), 2);
+        assert_eq!(
+            sample(&first, 1).transform.translation,
+            Vec3::new(0., 100., 0.)
+        );
+
+        let last = replay.sample(1.5);
+        assert_eq!(last.len(), 2);
+        let player = sample(&last, 1);
+        assert_eq!(player.transform.translation, Vec3::new(100., 110., 0.));
+        assert!(
+            player
+                .transform
+                .rotation
+                .dot(Quat::from_rotation_y(0.2))
+                .abs()
+                > 0.99999
+        );
+        assert_eq!(player.velocity, Vec3::ZERO);
+
+        // Before the start and after the end, the nearest frame is shown at rest.
+        let before = replay.sample(0.);
+        assert_eq!(sample(&before, 1).transform, sample(&first, 1).transform);
+        assert_eq!(sample(&before, 1).velocity, Vec3::ZERO);
+        assert_eq!(replay.sample(10.), last);
+        assert!(Replay::default().sample(1.).is_empty());
+    }
+
+    #[test]
+    fn sample_interpolates_between_frames() {
+        let samples = replay().sample(1.25);
+
+        let player = sample(&samples, 1);
+        assert!((player.transform.translation - Vec3::new(50., 105., 0.)).length() < 1e-4);
+        assert!(
+            player
+                .transform
+                .rotation
+                .dot(Quat::from_rotation_y(0.1))
+                .abs()
+                > 0.99999
+        );
+        assert!((player.velocity - Vec3::new(200., 20., 0.)).length() < 1e-3);
+        assert!((player.angular_velocity - Vec3::Y * 0.4).length() < 1e-3);
+
+        // The flare vanishes and the missile appears after this moment: the flare is held where
+        // it was last seen and the missile isn't shown yet.
+        let flare = sample(&samples, 2);
+        assert_eq!(flare.transform.translation, Vec3::new(5., 90., 0.));
+        assert_eq!(flare.velocity, Vec3::ZERO);
+        assert!(samples.iter().all(|sample| sample.id != 3));
+    }
+
+    #[test]
+    fn director_holds_shot_for_its_length() {
+        let samples = [aircraft(1, true), aircraft(2, false)];
+        assert_eq!(choose_shot(&samples, None, 0.), Some(Shot::Chase(1)));
+        assert_eq!(
+            choose_shot(&samples, Some(Shot::Chase(1)), 1.),
+            Some(Shot::Chase(1))
+        );
+        assert_eq!(
+            choose_shot(&samples, Some(Shot::Chase(1)), DIRECTOR_SHOT_LENGTH),
+            Some(Shot::FlyBy(1))
+        );
+        assert_eq!(
+            choose_shot(&samples, Some(Shot::FlyBy(1)), DIRECTOR_SHOT_LENGTH),
+            Some(Shot::Chase(1))
+        );
+
+        // Without the player, another aircraft is shown.
+        assert_eq!(choose_shot(&samples[1..], None, 0.), Some(Shot::Chase(2)));
+        assert_eq!(choose_shot(&[], None, 0.), None);
+    }
+
+    #[test]
+    fn director_follows_missiles_until_they_hit() {
+        let samples = [aircraft(1, true), missile(5), missile(7)];
+        // The newest missile is picked once the current shot ends.
+        assert_eq!(
+            choose_shot(&samples, Some(Shot::Chase(1)), DIRECTOR_SHOT_LENGTH),
+            Some(Shot::Missile(7))
+        );
+        // A missile is held past the shot length while it flies.
+        assert_eq!(
+            choose_shot(&samples, Some(Shot::Missile(7)), 60.),
+            Some(Shot::Missile(7))
+        );
+        // Once it hits, the director moves on to another missile still flying, then back to the
+        // player.
+        let samples = [aircraft(1, true), missile(5)];
+        assert_eq!(
+            choose_shot(&samples, Some(Shot::Missile(7)), 60.),
+            Some(Shot::Missile(5))
+        );
+        let samples = [aircraft(1, true)];
+        assert_eq!(
+            choose_shot(&samples, Some(Shot::Missile(7)), 60.),
+            Some(Shot::Chase(1))
+        );
+    }
+}
@@ -0,0 +1,322 @@
+use std::path::{Path, PathBuf};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::ai::*;
+use super::input::*;
+use super::loadout::*;
+use super::menu::*;
+use super::mission::*;
+use super::stats::*;
+
+pub const SAVE_VERSION: u32 = 2;
+pub const SAVE_DIR_NAME: &str = "ace_bevy";
+pub const SAVE_FILENAME: &str = "save.ron";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PilotProfile {
+    pub callsign: String,
+    pub sorties: u32,
+    pub kills: u32,
+    pub losses: u32,
+    pub flight_time: f32,
+}
+
+impl Default for PilotProfile {
+    fn default() -> Self {
+        PilotProfile {
+            callsign: "MAVERICK".to_string(),
+            sorties: 0,
+            kills: 0,
+            losses: 0,
+            flight_time: 0.,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Progress {
+    /// Names of missions flown to a successful end.
+    pub completed_missions: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub difficulty: DifficultyLevel,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SaveGame {
+    pub version: u32,
+    pub profile: PilotProfile,
+    pub unlocked_aircraft: Vec<String>,
+    pub selected_aircraft: String,
+    pub progress: Progress,
+    pub settings: Settings,
+    pub key_bindings: KeyBindings,
+}
+
+impl Default for SaveGame {
+    fn default() -> Self {
+        SaveGame {
+            version: SAVE_VERSION,
+            profile: PilotProfile::default(),
+            unlocked_aircraft: vec![PLAYER_LOADOUT.to_string()],
+            selected_aircraft: PLAYER_LOADOUT.to_string(),
+            progress: Progress::default(),
+            settings: Settings::default(),
+            key_bindings: KeyBindings::default(),
+        }
+    }
+}
+
+/// Version 1 predates aircraft unlocks and rebindable keys.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+struct SaveGameV1 {
+    profile: PilotProfile,
+    progress: Progress,
+    settings: Settings,
+}
+
+impl From<SaveGameV1> for SaveGame {
+    fn from(save: SaveGameV1) -> Self {
+        SaveGame {
+            profile: save.profile,
+            progress: save.progress,
+            settings: save.settings,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct SaveHeader {
+    version: u32,
+}
+
+/// Parses a save file of any known version, migrating it to `SAVE_VERSION`.
+pub fn parse_save(contents: &str) -> Result<SaveGame, String> {
+    let header: SaveHeader = ron::from_str(contents).map_err(|e| e.to_string())?;
+    let mut save: SaveGame = match header.version {
+        1 => ron::from_str::<SaveGameV1>(contents)
+            .map_err(|e| e.to_string())?
+            .into(),
+        SAVE_VERSION => ron::from_str(contents).map_err(|e| e.to_string())?,
+        version => return Err(format!("unsupported save version {}", version)),
+    };
+    save.version = SAVE_VERSION;
+
+    if !save
+        .unlocked_aircraft
+        .iter()
+        .any(|name| name == PLAYER_LOADOUT)
+    {
+        save.unlocked_aircraft.insert(0, PLAYER_LOADOUT.to_string());
+    }
+    if !save.unlocked_aircraft.contains(&save.selected_aircraft) {
+        save.selected_aircraft = PLAYER_LOADOUT.to_string();
+    }
+    Ok(save)
+}
+
+pub fn save_path() -> PathBuf {
+    dirs::data_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join(SAVE_DIR_NAME)
+        .join(SAVE_FILENAME)
+}
+
+impl SaveGame {
+    /// Loads the save at `path`. A missing save starts a new profile; an unreadable one is
+    /// moved aside so it isn't overwritten, and a new profile is started.
+    pub fn load(path: &Path) -> Self {
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return SaveGame::default(),
+            Err(e) => {
+                println!("Failed to load {}: {}", path.display(), e);
+                return SaveGame::default();
+            }
+        };
+
+        match parse_save(&contents) {
+            Ok(save) => save,
+            Err(e) => {
+                println!("Failed to load {}: {}", path.display(), e);
+                let backup = path.with_extension("ron.corrupt");
+                if let Err(e) = std::fs::rename(path, &backup) {
+                    println!("Failed to back up {}: {}", path.display(), e);
+                }
+                SaveGame::default()
+            }
+        }
+    }
+
+    /// Writes to a temporary file first so a crash mid-write can't corrupt the save.
+    pub fn write(&self, path: &Path) {
+        let temporary = path.with_extension("ron.tmp");
+        let written = path
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .map_err(|e| e.to_string())
+            .and_then(|_| {
+                ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::new())
+                    .map_err(|e| e.to_string())
+            })
+            .and_then(|contents| std::fs::write(&temporary, contents).map_err(|e| e.to_string()))
+            .and_then(|_| std::fs::rename(&temporary, path).map_err(|e| e.to_string()));
+        if let Err(e) = written {
+            println!("Failed to write {}: {}", path.display(), e);
+        }
+    }
+
+    pub fn is_completed(&self, mission: &str) -> bool {
+        self.progress
+            .completed_missions
+            .iter()
+            .any(|name| name == mission)
+    }
+
+    pub fn cycle_aircraft(&mut self, step: isize) {
+        let count = self.unlocked_aircraft.len() as isize;
+        if count == 0 {
+            return;
+        }
+        let current = self
+            .unlocked_aircraft
+            .iter()
+            .position(|name| *name == self.selected_aircraft)
+            .unwrap_or(0) as isize;
+        let next = (current + step).rem_euclid(count) as usize;
+        self.selected_aircraft = self.unlocked_aircraft[next].clone();
+    }
+
+    /// Folds a finished sortie into the profile and mission progress.
+    pub fn record_sortie(
+        &mut self,
+        mission: &MissionDef,
+        status: Option<&MissionStatus>,
+        stats: &SortieStats,
+    ) {
+        self.profile.sorties += 1;
+        self.profile.kills += stats.kills;
+        self.profile.losses += stats.deaths;
+        self.profile.flight_time += stats.time_aloft;
+
+        if status == Some(&MissionStatus::Success) {
+            if !self.is_completed(&mission.name) {
+                self.progress.completed_missions.push(mission.name.clone());
+            }
+            for aircraft in mission.unlocks.iter() {
+                if !self.unlocked_aircraft.contains(aircraft) {
+                    self.unlocked_aircraft.push(aircraft.clone());
+                }
+            }
+        }
+    }
+}
+
+/// Pushes saved settings into the resources the game reads them from.
+pub fn apply_settings(
+    save: Res<SaveGame>,
+    mut difficulty: ResMut<Difficulty>,
+    mut key_bindings: ResMut<KeyBindings>,
+) {
+    if save.is_changed() {
+        *difficulty = save.settings.difficulty.difficulty();
+        *key_bindings = save.key_bindings.clone();
+    }
+}
+
+pub fn save_progress(
+    mut save: ResMut<SaveGame>,
+    mission: Res<MissionDef>,
+    last_sortie: Res<LastSortie>,
+    stats: Res<SortieStats>,
+) {
+    save.record_sortie(&mission, last_sortie.status.as_ref(), &stats);
+    save.write(&save_path());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_v1_save_into_current_version() {
+        let save = parse_save(
+            r#"(
+                version: 1,
+                profile: (callsign: "ICEMAN", sorties: 3, kills: 5, losses: 1, flight_time: 600.0),
+                progress: (completed_missions: ["Training"]),
+                settings: (difficulty: Hard),
+            )"#,
+        )
+        .unwrap();
+
+        assert_eq!(save.version, SAVE_VERSION);
+        assert_eq!(save.profile.callsign, "ICEMAN");
+        assert_eq!(save.profile.kills, 5);
+        assert!(save.is_completed("Training"));
+        assert_eq!(save.settings.difficulty, DifficultyLevel::Hard);
+        // Fields added in version 2 take their defaults.
+        assert_eq!(save.unlocked_aircraft, vec![PLAYER_LOADOUT.to_string()]);
+        assert_eq!(save.selected_aircraft, PLAYER_LOADOUT);
+        assert_eq!(save.key_bindings, KeyBindings::default());
+    }
+
+    #[test]
+    fn parses_v2_save_as_written() {
+        let mut written = SaveGame::default();
+        written.profile.sorties = 7;
+        written.unlocked_aircraft.push("f35_heavy".to_string());
+        written.selected_aircraft = "f35_heavy".to_string();
+        written.key_bindings.flare = KeyCode::Q;
+        let contents = ron::ser::to_string_pretty(&written, ron::ser::PrettyConfig::new()).unwrap();
+
+        let save = parse_save(&contents).unwrap();
+        assert_eq!(save.version, SAVE_VERSION);
+        assert_eq!(save.profile.sorties, 7);
+        assert_eq!(save.unlocked_aircraft, written.unlocked_aircraft);
+        assert_eq!(save.selected_aircraft, "f35_heavy");
+        assert_eq!(save.key_bindings, written.key_bindings);
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        assert!(parse_save("").is_err());
+        assert!(parse_save("not a save").is_err());
+        assert!(parse_save("(version: \"two\")").is_err());
+        assert!(parse_save("(version: 2, profile: (kills: -1))").is_err());
+    }
+
+    #[test]
+    fn rejects_unsupported_version() {
+        let error = parse_save("(version: 99)").unwrap_err();
+        assert!(error.contains("99"), "{}", error);
+        assert!(parse_save("(version: 0)").is_err());
+    }
+
+    #[test]
+    fn repairs_aircraft_that_is_not_unlocked() {
+        let save = parse_save(
+            r#"(
+                version: 2,
+                unlocked_aircraft: ["f35_heavy"],
+                selected_aircraft: "drone_light",
+            )"#,
+        )
+        .unwrap();
+        assert_eq!(
+            save.unlocked_aircraft,
+            vec![PLAYER_LOADOUT.to_string(), "f35_heavy".to_string()]
+        );
+        assert_eq!(save.selected_aircraft, PLAYER_LOADOUT);
+    }
+}
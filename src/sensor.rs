@@ -0,0 +1,233 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use super::ai::contact_velocity;
+use super::crash::*;
+use super::damage::*;
+use super::faction::*;
+use super::player::*;
+use super::terrain::*;
+
+/// Seconds a lost track is kept, coasting on its last velocity.
+pub const TRACK_MEMORY: f32 = 4.;
+/// Detection range multiplier when the target is pointing straight at the sensor.
+pub const NOSE_ASPECT: f32 = 0.6;
+/// Detection range multiplier when the target is pointing straight away from the sensor.
+pub const TAIL_ASPECT: f32 = 0.8;
+
+#[derive(Debug, Clone, Copy, Component)]
+pub struct Sensor {
+    /// Detection range against a target seen side on.
+    pub range: f32,
+    /// Half-angle of the scan cone around the nose, in radians.
+    pub scan_half_angle: f32,
+    /// Seconds a target must stay visible before it becomes a track.
+    pub latency: f32,
+}
+
+impl Sensor {
+    pub fn fighter() -> Self {
+        Sensor {
+            range: 3000.,
+            scan_half_angle: 60_f32.to_radians(),
+            latency: 0.5,
+        }
+    }
+
+    pub fn drone() -> Self {
+        Sensor {
+            range: 2000.,
+            scan_half_angle: 60_f32.to_radians(),
+            latency: 1.,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SensorTrack {
+    pub position: Vec3,
+    pub velocity: Vec3,
+    /// Seconds the target has been continuously visible.
+    pub exposure: f32,
+    /// Seconds since the target was last visible.
+    pub since_seen: f32,
+}
+
+/// What an aircraft's sensor currently knows about other aircraft.
+#[derive(Debug, Default, Component)]
+pub struct SensorContacts {
+    pub tracks: HashMap<Entity, SensorTrack>,
+    latency: f32,
+}
+
+impl SensorContacts {
+    /// Tracks that have outlasted the sensor's detection latency.
+    pub fn detected(&self) -> impl Iterator<Item = (Entity, &SensorTrack)> {
+        let latency = self.latency;
+        self.tracks
+            .iter()
+            .filter(move |(_, track)| track.exposure >= latency)
+            .map(|(entity, track)| (*entity, track))
+    }
+
+    pub fn track(&self, entity: Entity) -> Option<&SensorTrack> {
+        self.tracks
+            .get(&entity)
+            .filter(|track| track.exposure >= self.latency)
+    }
+
+    pub fn is_detected(&self, entity: Entity) -> bool {
+        self.track(entity).is_some()
+    }
+}
+
+/// Detection range multiplier for a target whose nose points along `target_forward`, seen along
+/// `line_of_sight` from the sensor.
+pub fn aspect_factor(target_forward: Vec3, line_of_sight: Vec3) -> f32 {
+    let towards_sensor = target_forward
+        .normalize_or_zero()
+        .dot(-line_of_sight.normalize_or_zero());
+    if towards_sensor >= 0. {
+        1. - (1. - NOSE_ASPECT) * towards_sensor
+    } else {
+        1. + (1. - TAIL_ASPECT) * towards_sensor
+    }
+}
+
+/// Whether the sensor can see the target, ignoring terrain.
+pub fn in_sensor_coverage(
+    sensor: &Sensor,
+    position: Vec3,
+    forward: Vec3,
+    target_position: Vec3,
+    target_forward: Vec3,
+) -> bool {
+    let line_of_sight = target_position - position;
+    let distance = line_of_sight.length();
+    distance > 0.
+        && distance <= sensor.range * aspect_factor(target_forward, line_of_sight)
+        && forward.angle_between(line_of_sight) <= sensor.scan_half_angle
+}
+
+/// Advances a track by `delta_seconds` with the target's position and velocity if it was seen.
+/// Returns false once the track should be dropped.
+pub fn update_track(
+    track: &mut SensorTrack,
+    observation: Option<(Vec3, Vec3)>,
+    latency: f32,
+    delta_seconds: f32,
+) -> bool {
+    if let Some((position, velocity)) = observation {
+        track.position = position;
+        track.velocity = velocity;
+        track.exposure += delta_seconds;
+        track.since_seen = 0.;
+        return true;
+    }
+
+    if track.exposure < latency {
+        return false;
+    }
+    track.position += track.velocity * delta_seconds;
+    track.since_seen += delta_seconds;
+    track.since_seen <= TRACK_MEMORY
+}
+
+pub fn sensor_update(
+    mut sensor_query: Query<(
+        Entity,
+        &Transform,
+        &Sensor,
+        Option<&Faction>,
+        &mut SensorContacts,
+    )>,
+    aircraft_query: Query<
+        (
+            Entity,
+            &Transform,
+            Option<&Faction>,
+            Option<&RigidBodyVelocityComponent>,
+            Option<&TargetVelocity>,
+        ),
+        (With<AircraftDamage>, Without<Crashed>),
+    >,
+    terrain_query: Query<Entity, With<Terrain>>,
+    query_pipeline: Res<QueryPipeline>,
+    collider_query: QueryPipelineColliderComponentsQuery,
+    time: Res<Time>,
+) {
+    let collider_set = QueryPipelineColliderComponentsSet(&collider_query);
+    let terrain = terrain_query.get_single().ok();
+    let delta_seconds = time.delta_seconds();
+
+    for (entity, transform, sensor, faction, mut contacts) in sensor_query.iter_mut() {
+        let faction = faction.copied().unwrap_or_default();
+        let position = transform.translation;
+        let forward = transform.rotation * Vec3::X;
+        contacts.latency = sensor.latency;
+
+        let mut visible = HashMap::new();
+        for (target, target_transform, target_faction, rb_vel, target_velocity) in
+            aircraft_query.iter()
+        {
+            if target == entity {
+                continue;
+            }
+            let target_position = target_transform.translation;
+
+            // Friendly aircraft share their position over the datalink.
+            let datalink =
+                iff(faction, target_faction.copied().unwrap_or_default()) == Iff::Friendly;
+            let seen = datalink
+                || (in_sensor_coverage(
+                    sensor,
+                    position,
+                    forward,
+                    target_position,
+                    target_transform.rotation * Vec3::X,
+                ) && !terrain.map_or(false, |terrain| {
+                    let line_of_sight = target_position - position;
+                    query_pipeline
+                        .cast_ray(
+                            &collider_set,
+                            &Ray::new(position.into(), line_of_sight.into()),
+                            1.,
+                            true,
+                            InteractionGroups::all(),
+                            Some(&|handle: ColliderHandle| handle.entity() == terrain),
+                        )
+                        .is_some()
+                }));
+
+            if seen {
+                visible.insert(
+                    target,
+                    (
+                        target_position,
+                        contact_velocity(rb_vel, target_velocity),
+                        datalink,
+                    ),
+                );
+            }
+        }
+
+        let latency = sensor.latency;
+        contacts.tracks.retain(|target, track| {
+            let observation = visible
+                .get(target)
+                .map(|(position, velocity, _)| (*position, *velocity));
+            aircraft_query.get(*target).is_ok()
+                && update_track(track, observation, latency, delta_seconds)
+        });
+        for (target, (position, velocity, datalink)) in visible {
+            contacts.tracks.entry(target).or_insert(SensorTrack {
+                position,
+                velocity,
+                exposure: if datalink { latency } else { 0. },
+                since_seen: 0.,
+            });
+        }
+    }
+}
@@ -0,0 +1,194 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use serde::Serialize;
+
+use super::crash::*;
+use super::damage::*;
+use super::gun::GRAVITY;
+use super::loadout::*;
+use super::menu::*;
+use super::mission::*;
+use super::player::*;
+use super::weapons::*;
+
+pub const SORTIES_DIR: &str = "sorties";
+/// Samples above this are teleports or crash stops rather than manoeuvres.
+pub const MAX_PLAUSIBLE_G: f32 = 20.;
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct WeaponStats {
+    pub shots: u32,
+    pub hits: u32,
+}
+
+impl WeaponStats {
+    pub fn accuracy(&self) -> Option<f32> {
+        accuracy(self.hits, self.shots)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SortieStats {
+    pub mission: String,
+    pub result: String,
+    pub missiles: WeaponStats,
+    pub gun: WeaponStats,
+    pub kills: u32,
+    pub deaths: u32,
+    pub damage_taken: f32,
+    pub time_aloft: f32,
+    pub max_g: f32,
+    pub max_speed: f32,
+    #[serde(skip)]
+    last_velocity: Option<Vec3>,
+}
+
+impl SortieStats {
+    pub fn new(mission: &str) -> Self {
+        SortieStats {
+            mission: mission.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn weapon_mut(&mut self, weapon: WeaponType) -> &mut WeaponStats {
+        match weapon {
+            WeaponType::Missile => &mut self.missiles,
+            WeaponType::Gun => &mut self.gun,
+        }
+    }
+
+    pub fn summary(&self) -> String {
+        let percent = |accuracy: Option<f32>| {
+            accuracy.map_or("-".to_string(), |accuracy| {
+                format!("{:.0}%", accuracy * 100.)
+            })
+        };
+        format!(
+            "MSL {}/{} ({})\nGUN {}/{} ({})\nKILLS {}  LOSSES {}\nDAMAGE TAKEN {:.0}\nTIME ALOFT {:.0}s\nMAX G {:.1}  MAX SPEED {:.0} km/h",
+            self.missiles.hits,
+            self.missiles.shots,
+            percent(self.missiles.accuracy()),
+            self.gun.hits,
+            self.gun.shots,
+            percent(self.gun.accuracy()),
+            self.kills,
+            self.deaths,
+            self.damage_taken,
+            self.time_aloft,
+            self.max_g,
+            self.max_speed * 3.6
+        )
+    }
+}
+
+pub fn accuracy(hits: u32, shots: u32) -> Option<f32> {
+    if shots == 0 {
+        None
+    } else {
+        Some(hits as f32 / shots as f32)
+    }
+}
+
+/// Load factor felt by the pilot while the velocity changes from `previous` to `current`.
+pub fn load_factor(previous: Vec3, current: Vec3, delta_seconds: f32) -> f32 {
+    if delta_seconds <= 0. {
+        return 1.;
+    }
+    let acceleration = (current - previous) / delta_seconds;
+    (acceleration + Vec3::Y * GRAVITY).length() / GRAVITY
+}
+
+pub fn reset_stats(mut commands: Commands, mission: Res<MissionDef>) {
+    commands.insert_resource(SortieStats::new(&mission.name));
+}
+
+pub fn combat_stats(
+    mut stats: ResMut<SortieStats>,
+    mut fired_events: EventReader<WeaponFired>,
+    mut damage_events: EventReader<DamageEvent>,
+    mut destroyed_events: EventReader<AircraftDestroyed>,
+    mut crash_events: EventReader<PlayerCrashed>,
+    player_query: Query<Entity, With<Player>>,
+    aircraft_query: Query<(), With<AircraftDamage>>,
+) {
+    let player = match player_query.iter().next() {
+        Some(player) => player,
+        None => return,
+    };
+
+    for event in fired_events.iter() {
+        if event.shooter == player {
+            stats.weapon_mut(event.weapon).shots += 1;
+        }
+    }
+    for event in damage_events.iter() {
+        if event.target == player {
+            stats.damage_taken += event.amount;
+        } else if event.source == Some(player) && aircraft_query.get(event.target).is_ok() {
+            if let Some(weapon) = event.weapon {
+                stats.weapon_mut(weapon).hits += 1;
+            }
+        }
+    }
+    for event in destroyed_events.iter() {
+        if event.entity != player && event.source == Some(player) {
+            stats.kills += 1;
+        }
+    }
+    for event in crash_events.iter() {
+        if event.entity == player {
+            stats.deaths += 1;
+        }
+    }
+}
+
+pub fn flight_stats(
+    mut stats: ResMut<SortieStats>,
+    player_query: Query<(&RigidBodyVelocityComponent, Option<&Crashed>), With<Player>>,
+    time: Res<Time>,
+) {
+    let (rb_vel, crashed) = match player_query.iter().next() {
+        Some(player) => player,
+        None => return,
+    };
+    let velocity: Vec3 = rb_vel.linvel.into();
+
+    if crashed.is_some() {
+        stats.last_velocity = None;
+        return;
+    }
+
+    stats.time_aloft += time.delta_seconds();
+    stats.max_speed = stats.max_speed.max(velocity.length());
+    if let Some(last_velocity) = stats.last_velocity {
+        let g = load_factor(last_velocity, velocity, time.delta_seconds());
+        if g < MAX_PLAUSIBLE_G {
+            stats.max_g = stats.max_g.max(g);
+        }
+    }
+    stats.last_velocity = Some(velocity);
+}
+
+/// Fills in the result and writes the sortie to `SORTIES_DIR` as JSON.
+pub fn write_sortie_report(mut stats: ResMut<SortieStats>, last_sortie: Res<LastSortie>) {
+    stats.result = match &last_sortie.status {
+        Some(MissionStatus::Success) => "success".to_string(),
+        Some(MissionStatus::Failure(reason)) => format!("failure: {}", reason),
+        Some(MissionStatus::InProgress) | None => "incomplete".to_string(),
+    };
+
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+    let filename = format!("{}/sortie_{}.json", SORTIES_DIR, timestamp);
+
+    let written = std::fs::create_dir_all(SORTIES_DIR)
+        .map_err(|e| e.to_string())
+        .and_then(|_| serde_json::to_string_pretty(&*stats).map_err(|e| e.to_string()))
+        .and_then(|json| std::fs::write(&filename, json).map_err(|e| e.to_string()));
+    if let Err(e) = written {
+        println!("Failed to write {}: {}", filename, e);
+    }
+}
@@ -82,13 +82,29 @@ fn mesh_from_heightmap(
 
 const WIDTH: u32 = 1000;
 const LENGTH: u32 = 1000;
+const SCALE_FACTOR: f32 = 2.;
+
+#[derive(Component)]
+pub struct Terrain;
+
+/// Heights of the terrain vertices, row by row from the north-west corner.
+pub struct TerrainHeights {
+    pub width: usize,
+    pub length: usize,
+    /// Distance between neighbouring vertices.
+    pub spacing: f32,
+    pub heights: Vec<f32>,
+}
+
+pub const WATER_LEVEL: f32 = 10.;
+pub const TERRAIN_HALF_SIZE: f32 = WIDTH as f32 * SCALE_FACTOR / 2.;
 
 pub fn setup_terrain(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
 ) {
-    let scale_factor = 2.;
+    let scale_factor = SCALE_FACTOR;
 
     let vertices_vec = mesh_from_heightmap(
         "assets/heightmap.png",
@@ -132,6 +148,13 @@ pub fn setup_terrain(
         uvs.push(*uv);
     }
 
+    commands.insert_resource(TerrainHeights {
+        width: WIDTH as usize,
+        length: LENGTH as usize,
+        spacing: scale_factor,
+        heights: positions.iter().map(|position| position[1]).collect(),
+    });
+
     let collider_shape = ColliderShape::trimesh(
         positions.iter().map(|p| Point::from_slice(p)).collect(),
         indices_vec_2,
@@ -173,11 +196,14 @@ pub fn setup_terrain(
             .into(),
             ..Default::default()
         })
-        .insert(ColliderPositionSync::Discrete);
+        .insert(ColliderPositionSync::Discrete)
+        .insert(Terrain);
 
     commands.spawn_bundle(PbrBundle {
-        mesh: meshes.add(Mesh::from(bevy::render::mesh::shape::Plane { size: 2000. })),
-        transform: Transform::from_translation(Vec3::new(0., 10., 0.)),
+        mesh: meshes.add(Mesh::from(bevy::render::mesh::shape::Plane {
+            size: TERRAIN_HALF_SIZE * 2.,
+        })),
+        transform: Transform::from_translation(Vec3::new(0., WATER_LEVEL, 0.)),
         material: materials.add(StandardMaterial {
             base_color: Color::MIDNIGHT_BLUE,
             perceptual_roughness: 0.7,
@@ -1,9 +1,30 @@
+use std::collections::HashMap;
+
 use bevy::{prelude::*, render::camera::*};
 use bevy_rapier3d::prelude::*;
 
+use super::countermeasures::*;
+use super::faction::*;
+use super::guidance::*;
+use super::gun::*;
+use super::hud::FlightHud;
+use super::loadout::*;
+use super::mission::*;
+use super::navigation::*;
 use super::player::*;
+use super::radar::*;
+use super::sensor::*;
+use super::warning::*;
+use super::wingman::Wingman;
 
-const RADAR_RANGE: f32 = 1000.;
+const RADAR_SIZE: f32 = 200.;
+const THREAT_MARKER_RADIUS: f32 = 150.;
+const MISSION_MESSAGE_DURATION: f32 = 5.;
+const WAYPOINT_ARROW_MARGIN: f32 = 40.;
+const TARGET_ARROW_MARGIN: f32 = 60.;
+const TARGET_UI_SIZE: f32 = 30.;
+/// Distance inside which the selected target counts as locked when it is in the seeker's view.
+pub const LOCK_RANGE: f32 = 1500.;
 
 #[derive(Component)]
 pub struct Radar;
@@ -12,15 +33,66 @@ pub struct Radar;
 pub struct RadarDot;
 
 #[derive(Component)]
-pub struct SpeedText;
+pub struct RadarContact;
+
+#[derive(Component)]
+pub struct RadarSweep;
+
+#[derive(Component)]
+pub struct RadarLabel;
+
+#[derive(Component)]
+pub struct RadarNorth;
+
+#[derive(Component)]
+pub struct LoadoutText;
+
+#[derive(Component)]
+pub struct GunPipper;
+
+#[derive(Component)]
+pub struct MissileWarningText;
+
+#[derive(Component)]
+pub struct ThreatMarker;
 
 #[derive(Component)]
 pub struct UiTarget;
 
+#[derive(Component)]
+pub struct TargetBox;
+
+#[derive(Component)]
+pub struct TargetLabel;
+
+#[derive(Component)]
+pub struct TargetArrow;
+
+#[derive(Component)]
+pub struct TargetReticle;
+
+#[derive(Component)]
+pub struct MissionText;
+
+#[derive(Component)]
+pub struct NavText;
+
+#[derive(Component)]
+pub struct WaypointMarker;
+
+#[derive(Component)]
+pub struct WaypointArrow;
+
+#[derive(Component)]
+pub struct RadarWaypoint;
+
 #[derive(Default)]
 pub struct UiTargets {
-    targets: Vec<Entity>,
+    /// UI box for each `Target` entity.
+    targets: HashMap<Entity, Entity>,
     radar_dots: Vec<Entity>,
+    threat_dots: Vec<Entity>,
+    threat_markers: Vec<Entity>,
 }
 
 pub fn setup_ui(
@@ -36,8 +108,63 @@ pub fn setup_ui(
                 align_self: AlignSelf::FlexStart,
                 position_type: PositionType::Absolute,
                 position: Rect {
-                    bottom: Val::Percent(50.0),
-                    left: Val::Percent(25.0),
+                    bottom: Val::Px(10.0),
+                    left: Val::Px(10.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            text: Text::with_section(
+                "",
+                TextStyle {
+                    font: asset_server.load("fonts/FiraMono-Medium.ttf"),
+                    font_size: 20.0,
+                    color: Color::GREEN,
+                },
+                Default::default(),
+            ),
+            ..Default::default()
+        })
+        .insert(FlightHud::default())
+        .insert(LoadoutText);
+
+    spawn_gun_pipper(&mut commands);
+    spawn_player_target(&mut commands, &mut color_materials);
+
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                align_self: AlignSelf::FlexStart,
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Percent(20.0),
+                    left: Val::Percent(45.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            text: Text::with_section(
+                "",
+                TextStyle {
+                    font: asset_server.load("fonts/FiraMono-Medium.ttf"),
+                    font_size: 30.0,
+                    color: Color::RED,
+                },
+                Default::default(),
+            ),
+            ..Default::default()
+        })
+        .insert(FlightHud::default())
+        .insert(MissileWarningText);
+
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                align_self: AlignSelf::FlexStart,
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Px(10.0),
+                    left: Val::Px(10.0),
                     ..Default::default()
                 },
                 ..Default::default()
@@ -46,23 +173,82 @@ pub fn setup_ui(
                 "",
                 TextStyle {
                     font: asset_server.load("fonts/FiraMono-Medium.ttf"),
-                    font_size: 25.0,
+                    font_size: 18.0,
                     color: Color::GREEN,
                 },
-                TextAlignment {
-                    horizontal: HorizontalAlign::Right,
-                    vertical: VerticalAlign::Center,
+                Default::default(),
+            ),
+            ..Default::default()
+        })
+        .insert(FlightHud::default())
+        .insert(MissionText);
+
+    let nav_text_style = TextStyle {
+        font: asset_server.load("fonts/FiraMono-Medium.ttf"),
+        font_size: 18.0,
+        color: Color::GREEN,
+    };
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                align_self: AlignSelf::FlexStart,
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Px(10.0),
+                    right: Val::Px(10.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            text: Text::with_section("", nav_text_style.clone(), Default::default()),
+            ..Default::default()
+        })
+        .insert(FlightHud::default())
+        .insert(NavText);
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Px(-100.),
+                    bottom: Val::Px(-100.),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            text: Text::with_section("", nav_text_style.clone(), Default::default()),
+            ..Default::default()
+        })
+        .insert(FlightHud::default())
+        .insert(WaypointMarker);
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Px(-100.),
+                    bottom: Val::Px(-100.),
                     ..Default::default()
                 },
+                ..Default::default()
+            },
+            text: Text::with_section(
+                "",
+                TextStyle {
+                    font_size: 30.0,
+                    ..nav_text_style
+                },
+                Default::default(),
             ),
             ..Default::default()
         })
-        .insert(SpeedText);
+        .insert(FlightHud::default())
+        .insert(WaypointArrow);
 
     commands
         .spawn_bundle(NodeBundle {
             style: Style {
-                size: Size::new(Val::Px(200.), Val::Px(200.)),
+                size: Size::new(Val::Px(RADAR_SIZE), Val::Px(RADAR_SIZE)),
                 position_type: PositionType::Absolute,
                 position: Rect {
                     right: Val::Px(10.),
@@ -74,6 +260,7 @@ pub fn setup_ui(
             color: Color::rgb(0.0, 0.15, 0.).into(),
             ..Default::default()
         })
+        .insert(FlightHud::default())
         .insert(Radar)
         .with_children(|parent| {
             parent.spawn_bundle(NodeBundle {
@@ -90,74 +277,204 @@ pub fn setup_ui(
                 color: Color::rgb(0.0, 0.5, 1.).into(),
                 ..Default::default()
             });
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        size: Size::new(Val::Px(7.), Val::Px(7.)),
+                        position_type: PositionType::Absolute,
+                        position: Rect {
+                            left: Val::Px(-10000.),
+                            bottom: Val::Px(-10000.),
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    },
+                    color: Color::YELLOW.into(),
+                    ..Default::default()
+                })
+                .insert(RadarWaypoint);
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        position: Rect {
+                            left: Val::Percent(50.),
+                            bottom: Val::Percent(50.),
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    },
+                    color: Color::NONE.into(),
+                    ..Default::default()
+                })
+                .insert(RadarSweep)
+                .with_children(|parent| {
+                    parent.spawn_bundle(NodeBundle {
+                        style: Style {
+                            size: Size::new(Val::Px(1.), Val::Px(RADAR_SIZE / 2.)),
+                            position_type: PositionType::Absolute,
+                            position: Rect {
+                                left: Val::Px(0.),
+                                bottom: Val::Px(0.),
+                                ..Default::default()
+                            },
+                            ..Default::default()
+                        },
+                        color: Color::rgba(0., 1., 0., 0.4).into(),
+                        ..Default::default()
+                    });
+                });
+            let radar_text_style = TextStyle {
+                font: asset_server.load("fonts/FiraMono-Medium.ttf"),
+                font_size: 12.0,
+                color: Color::GREEN,
+            };
+            parent
+                .spawn_bundle(TextBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        position: Rect {
+                            left: Val::Px(4.),
+                            top: Val::Px(4.),
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    },
+                    text: Text::with_section("", radar_text_style.clone(), Default::default()),
+                    ..Default::default()
+                })
+                .insert(RadarLabel);
+            parent
+                .spawn_bundle(TextBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        ..Default::default()
+                    },
+                    text: Text::with_section("N", radar_text_style, Default::default()),
+                    ..Default::default()
+                })
+                .insert(RadarNorth);
         });
 }
 
-fn spawn_target(
-    commands: &mut Commands,
-    color_materials: &mut ResMut<Assets<ColorMaterial>>,
-) -> Entity {
-    let target_ui_size = 30.;
+fn spawn_target(commands: &mut Commands, font: Handle<Font>) -> Entity {
+    let text_style = TextStyle {
+        font,
+        font_size: 14.0,
+        color: Color::GREEN,
+    };
 
     commands
-        .spawn_bundle(NodeBundle::default())
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                ..Default::default()
+            },
+            color: Color::NONE.into(),
+            ..Default::default()
+        })
+        .insert(FlightHud::default())
         .with_children(|parent| {
-            parent.spawn_bundle(NodeBundle {
-                style: Style {
-                    size: Size::new(Val::Px(1.), Val::Px(target_ui_size + 1.)),
-                    position_type: PositionType::Absolute,
-                    position: Rect {
-                        left: Val::Px(-target_ui_size / 2.),
-                        bottom: Val::Px(-target_ui_size / 2.),
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
                         ..Default::default()
                     },
+                    color: Color::NONE.into(),
                     ..Default::default()
-                },
-                color: Color::rgb(0.0, 1., 0.).into(),
-                ..Default::default()
-            });
-            parent.spawn_bundle(NodeBundle {
-                style: Style {
-                    size: Size::new(Val::Px(1.), Val::Px(target_ui_size + 1.)),
-                    position_type: PositionType::Absolute,
-                    position: Rect {
-                        left: Val::Px(target_ui_size / 2.),
-                        bottom: Val::Px(-target_ui_size / 2.),
+                })
+                .insert(TargetBox)
+                .with_children(|parent| {
+                    parent.spawn_bundle(NodeBundle {
+                        style: Style {
+                            size: Size::new(Val::Px(1.), Val::Px(TARGET_UI_SIZE + 1.)),
+                            position_type: PositionType::Absolute,
+                            position: Rect {
+                                left: Val::Px(-TARGET_UI_SIZE / 2.),
+                                bottom: Val::Px(-TARGET_UI_SIZE / 2.),
+                                ..Default::default()
+                            },
+                            ..Default::default()
+                        },
+                        color: Color::rgb(0.0, 1., 0.).into(),
                         ..Default::default()
-                    },
-                    ..Default::default()
-                },
-                color: Color::rgb(0.0, 1., 0.).into(),
-                ..Default::default()
-            });
-            parent.spawn_bundle(NodeBundle {
-                style: Style {
-                    size: Size::new(Val::Px(target_ui_size + 1.), Val::Px(1.)),
-                    position_type: PositionType::Absolute,
-                    position: Rect {
-                        left: Val::Px(-target_ui_size / 2.),
-                        bottom: Val::Px(target_ui_size / 2.),
+                    });
+                    parent.spawn_bundle(NodeBundle {
+                        style: Style {
+                            size: Size::new(Val::Px(1.), Val::Px(TARGET_UI_SIZE + 1.)),
+                            position_type: PositionType::Absolute,
+                            position: Rect {
+                                left: Val::Px(TARGET_UI_SIZE / 2.),
+                                bottom: Val::Px(-TARGET_UI_SIZE / 2.),
+                                ..Default::default()
+                            },
+                            ..Default::default()
+                        },
+                        color: Color::rgb(0.0, 1., 0.).into(),
+                        ..Default::default()
+                    });
+                    parent.spawn_bundle(NodeBundle {
+                        style: Style {
+                            size: Size::new(Val::Px(TARGET_UI_SIZE + 1.), Val::Px(1.)),
+                            position_type: PositionType::Absolute,
+                            position: Rect {
+                                left: Val::Px(-TARGET_UI_SIZE / 2.),
+                                bottom: Val::Px(TARGET_UI_SIZE / 2.),
+                                ..Default::default()
+                            },
+                            ..Default::default()
+                        },
+                        color: Color::rgb(0.0, 1., 0.).into(),
+                        ..Default::default()
+                    });
+                    parent.spawn_bundle(NodeBundle {
+                        style: Style {
+                            size: Size::new(Val::Px(TARGET_UI_SIZE + 1.), Val::Px(1.)),
+                            position_type: PositionType::Absolute,
+                            position: Rect {
+                                left: Val::Px(-TARGET_UI_SIZE / 2.),
+                                bottom: Val::Px(-TARGET_UI_SIZE / 2.),
+                                ..Default::default()
+                            },
+                            ..Default::default()
+                        },
+                        color: Color::rgb(0.0, 1., 0.).into(),
+                        ..Default::default()
+                    });
+                });
+            parent
+                .spawn_bundle(TextBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        position: Rect {
+                            left: Val::Px(TARGET_UI_SIZE / 2. + 4.),
+                            bottom: Val::Px(-TARGET_UI_SIZE / 2.),
+                            ..Default::default()
+                        },
                         ..Default::default()
                     },
+                    text: Text::with_section("", text_style.clone(), Default::default()),
                     ..Default::default()
-                },
-                color: Color::rgb(0.0, 1., 0.).into(),
-                ..Default::default()
-            });
-            parent.spawn_bundle(NodeBundle {
-                style: Style {
-                    size: Size::new(Val::Px(target_ui_size + 1.), Val::Px(1.)),
-                    position_type: PositionType::Absolute,
-                    position: Rect {
-                        left: Val::Px(-target_ui_size / 2.),
-                        bottom: Val::Px(-target_ui_size / 2.),
+                })
+                .insert(TargetLabel);
+            parent
+                .spawn_bundle(TextBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
                         ..Default::default()
                     },
+                    text: Text::with_section(
+                        "",
+                        TextStyle {
+                            font_size: 30.0,
+                            ..text_style
+                        },
+                        Default::default(),
+                    ),
                     ..Default::default()
-                },
-                color: Color::rgb(0.0, 1., 0.).into(),
-                ..Default::default()
-            });
+                })
+                .insert(TargetArrow);
         })
         .insert(UiTarget)
         .id()
@@ -171,8 +488,19 @@ fn spawn_player_target(
 
     commands
         .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Px(-100.),
+                    bottom: Val::Px(-100.),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            color: Color::NONE.into(),
             ..Default::default()
         })
+        .insert(FlightHud::default())
         .with_children(|parent| {
             parent.spawn_bundle(NodeBundle {
                 transform: Transform::from_rotation(Quat::from_axis_angle(
@@ -247,13 +575,78 @@ fn spawn_player_target(
                 ..Default::default()
             });
         })
-        .insert(UiTarget)
+        .insert(TargetReticle)
+        .id()
+}
+
+fn spawn_gun_pipper(commands: &mut Commands) -> Entity {
+    let pipper_size = 24.;
+    let tick_length = 6.;
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Px(-pipper_size),
+                    bottom: Val::Px(-pipper_size),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            color: Color::NONE.into(),
+            ..Default::default()
+        })
+        .insert(FlightHud::default())
+        .with_children(|parent| {
+            let ticks = [
+                (-1.5, -1.5, 3., 3.),
+                (-0.5, pipper_size / 2. - tick_length, 1., tick_length),
+                (-0.5, -pipper_size / 2., 1., tick_length),
+                (pipper_size / 2. - tick_length, -0.5, tick_length, 1.),
+                (-pipper_size / 2., -0.5, tick_length, 1.),
+            ];
+            for (left, bottom, width, height) in ticks {
+                parent.spawn_bundle(NodeBundle {
+                    style: Style {
+                        size: Size::new(Val::Px(width), Val::Px(height)),
+                        position_type: PositionType::Absolute,
+                        position: Rect {
+                            left: Val::Px(left),
+                            bottom: Val::Px(bottom),
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    },
+                    color: Color::rgb(0.0, 1., 0.).into(),
+                    ..Default::default()
+                });
+            }
+        })
+        .insert(GunPipper)
+        .id()
+}
+
+fn spawn_threat_marker(commands: &mut Commands) -> Entity {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Px(12.), Val::Px(12.)),
+                position_type: PositionType::Absolute,
+                ..Default::default()
+            },
+            color: Color::RED.into(),
+            ..Default::default()
+        })
+        .insert(FlightHud::default())
+        .insert(ThreatMarker)
         .id()
 }
 
 fn spawn_radar_dot(
     commands: &mut Commands,
     radar: Entity,
+    color: Color,
     color_materials: &mut ResMut<Assets<ColorMaterial>>,
 ) -> Entity {
     let mut child_id: Entity = Entity::from_raw(0);
@@ -262,7 +655,7 @@ fn spawn_radar_dot(
             .spawn_bundle(NodeBundle {
                 style: Style {
                     size: Size::new(Val::Px(5.), Val::Px(5.)),
-                    position_type: PositionType::Relative,
+                    position_type: PositionType::Absolute,
                     position: Rect {
                         left: Val::Percent(50.),
                         bottom: Val::Percent(50.),
@@ -270,7 +663,7 @@ fn spawn_radar_dot(
                     },
                     ..Default::default()
                 },
-                color: Color::rgb(0.0, 1., 0.).into(),
+                color: color.into(),
                 ..Default::default()
             })
             .insert(RadarDot)
@@ -279,134 +672,692 @@ fn spawn_radar_dot(
     child_id
 }
 
-pub fn text_update_system(
-    mut query: Query<&mut Text, With<SpeedText>>,
-    player_query: Query<(&Transform, &RigidBodyVelocityComponent), With<Player>>,
+fn spawn_radar_contact(commands: &mut Commands, radar: Entity, font: Handle<Font>) -> Entity {
+    let mut child_id: Entity = Entity::from_raw(0);
+    commands.entity(radar).with_children(|parent| {
+        child_id = parent
+            .spawn_bundle(TextBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    ..Default::default()
+                },
+                text: Text::with_section(
+                    "",
+                    TextStyle {
+                        font,
+                        font_size: 12.0,
+                        color: Color::GREEN,
+                    },
+                    Default::default(),
+                ),
+                ..Default::default()
+            })
+            .insert(RadarContact)
+            .id();
+    });
+    child_id
+}
+
+/// Style position for a point in `[-1, 1]` scope coordinates, nudged so a glyph is centred on it.
+fn scope_style_position(position: Vec2, glyph_offset: f32) -> Rect<Val> {
+    Rect {
+        left: Val::Px(RADAR_SIZE / 2. * (1. + position.x) - glyph_offset),
+        bottom: Val::Px(RADAR_SIZE / 2. * (1. + position.y) - glyph_offset),
+        ..Default::default()
+    }
+}
+
+pub fn loadout_text_system(
+    mut query: Query<&mut Text, With<LoadoutText>>,
+    player_query: Query<(&Loadout, &Fuel, &Countermeasures), With<Player>>,
 ) {
-    if let Some((player_transform, rb_vel)) = player_query.iter().next() {
+    if let Some((loadout, fuel, countermeasures)) = player_query.iter().next() {
         for mut text in query.iter_mut() {
             text.sections[0].value = format!(
-                "{:.2} Km/H\n{} m",
-                (rb_vel.linvel.magnitude() * 3.6).round() as i32,
-                (player_transform.translation.y * 5.) as i32
+                "GUN {}\nMSL {}/{}\nFLR {} CHF {}\nFUEL {:.0}%{}",
+                loadout.count(WeaponType::Gun),
+                loadout.count(WeaponType::Missile),
+                loadout.capacity(WeaponType::Missile),
+                countermeasures.flares,
+                countermeasures.chaff,
+                fuel.fraction() * 100.,
+                if fuel.is_empty() { "\nFLAMEOUT" } else { "" }
             );
         }
     }
 }
 
-pub fn target_ui(
-    target_query: Query<&Transform, With<Target>>,
+pub fn mission_text_system(
+    mission: Res<MissionDef>,
+    state: Res<MissionState>,
+    mut message_events: EventReader<MissionMessage>,
+    mut last_message: Local<Option<(String, f32)>>,
+    mut query: Query<&mut Text, With<MissionText>>,
+    time: Res<Time>,
+) {
+    if let Some(message) = message_events.iter().last() {
+        *last_message = Some((message.text.clone(), MISSION_MESSAGE_DURATION));
+    }
+    if let Some((_, remaining)) = last_message.as_mut() {
+        *remaining -= time.delta_seconds();
+    }
+    if last_message
+        .as_ref()
+        .map_or(false, |(_, remaining)| *remaining <= 0.)
+    {
+        *last_message = None;
+    }
+
+    let mut value = mission.name.to_uppercase();
+    for (objective, completed) in mission.objectives.iter().zip(state.completed.iter()) {
+        value += &format!(
+            "\n[{}] {}",
+            if *completed { "X" } else { " " },
+            objective.description
+        );
+    }
+    if let Some(time_limit) = mission.time_limit {
+        value += &format!("\nTIME {:.0}", (time_limit - state.elapsed).max(0.));
+    }
+    match &state.status {
+        MissionStatus::InProgress => {}
+        MissionStatus::Success => value += "\nMISSION COMPLETE",
+        MissionStatus::Failure(reason) => value += &format!("\nMISSION FAILED: {}", reason),
+    }
+    if let Some((message, _)) = last_message.as_ref() {
+        value += &format!("\n\n{}", message);
+    }
+
+    for mut text in query.iter_mut() {
+        text.sections[0].value = value.clone();
+    }
+}
+
+pub fn gun_pipper(
+    player_query: Query<(&Transform, &RigidBodyVelocityComponent, &Player)>,
+    target_query: Query<(&Transform, &TargetVelocity)>,
     camera_query: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
-    player_query: Query<&Player>,
-    mut ui_targets: Query<&mut Style, With<UiTarget>>,
-    mut ui_targets_res: ResMut<UiTargets>,
+    mut pipper_query: Query<&mut Style, With<GunPipper>>,
     windows: Res<Windows>,
-    mut commands: Commands,
-    mut color_materials: ResMut<Assets<ColorMaterial>>,
 ) {
-    let player_target = player_query.single().target;
+    let (player_transform, rb_vel, player) = match player_query.get_single() {
+        Ok(player) => player,
+        Err(_) => return,
+    };
     let (camera, camera_global_transform) = camera_query.single();
 
-    let targets_to_draw: Vec<Vec2> = target_query
-        .iter()
-        .flat_map(|target_transform| {
-            camera.world_to_screen(
-                &windows,
-                camera_global_transform,
+    let screen_coords = player
+        .target
+        .and_then(|target| target_query.get(target).ok())
+        .filter(|(target_transform, _)| {
+            (target_transform.translation - player_transform.translation).length() < GUN_RANGE
+        })
+        .and_then(|(target_transform, target_velocity)| {
+            gunsight_aim_point(
+                player_transform.translation,
+                rb_vel.linvel.into(),
                 target_transform.translation,
+                target_velocity.linvel,
+                MUZZLE_SPEED,
             )
         })
-        .filter(|target_screen_coords| {
-            target_screen_coords.x < windows.get_primary().unwrap().width()
-                && target_screen_coords.x > 0.
-                && target_screen_coords.y < windows.get_primary().unwrap().height()
-                && target_screen_coords.y > 0.
+        .and_then(|aim_point| {
+            let window = windows.get_primary()?;
+            match project_to_screen(
+                camera.projection_matrix,
+                camera_global_transform,
+                aim_point,
+                Vec2::new(window.width(), window.height()),
+                0.,
+            ) {
+                ScreenPosition::OnScreen(screen_coords) => Some(screen_coords),
+                ScreenPosition::OffScreen { .. } => None,
+            }
         })
-        .collect();
+        .unwrap_or(Vec2::new(-100., -100.));
+
+    for mut pipper in pipper_query.iter_mut() {
+        pipper.position = Rect {
+            bottom: Val::Px(screen_coords.y),
+            left: Val::Px(screen_coords.x),
+            ..Default::default()
+        };
+    }
+}
+
+/// Label shown next to a target box.
+fn target_name(tag: Option<&MissionTag>, is_wingman: bool, iff: Iff) -> String {
+    match (tag, is_wingman, iff) {
+        (Some(tag), _, _) => tag.0.to_uppercase(),
+        (None, true, _) => "WINGMAN".to_string(),
+        (None, false, Iff::Hostile) => "BANDIT".to_string(),
+        (None, false, Iff::Friendly) => "FRIENDLY".to_string(),
+        (None, false, Iff::Neutral) => "UNKNOWN".to_string(),
+    }
+}
 
-    while ui_targets_res.targets.len() > targets_to_draw.len() {
-        if let Some(target) = ui_targets_res.targets.pop() {
-            commands.entity(target).despawn_recursive();
+/// Rate at which the distance to the target is shrinking.
+pub fn closure_rate(
+    position: Vec3,
+    velocity: Vec3,
+    target_position: Vec3,
+    target_velocity: Vec3,
+) -> f32 {
+    let line_of_sight = (target_position - position).normalize_or_zero();
+    -(target_velocity - velocity).dot(line_of_sight)
+}
+
+pub fn target_ui(
+    target_query: Query<
+        (
+            Entity,
+            Option<&Faction>,
+            Option<&MissionTag>,
+            Option<&Wingman>,
+        ),
+        With<Target>,
+    >,
+    camera_query: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    player_query: Query<
+        (
+            &Transform,
+            &RigidBodyVelocityComponent,
+            &Player,
+            Option<&Faction>,
+            &SensorContacts,
+        ),
+        Without<Target>,
+    >,
+    mut ui_targets: Query<(&mut Style, &Children), With<UiTarget>>,
+    mut box_query: Query<(&mut Style, &Children), (With<TargetBox>, Without<UiTarget>)>,
+    mut label_query: Query<&mut Text, (With<TargetLabel>, Without<TargetArrow>)>,
+    mut arrow_query: Query<
+        (&mut Style, &mut Text),
+        (With<TargetArrow>, Without<UiTarget>, Without<TargetBox>),
+    >,
+    mut reticle_query: Query<
+        (&mut Style, &Children),
+        (
+            With<TargetReticle>,
+            Without<UiTarget>,
+            Without<TargetBox>,
+            Without<TargetArrow>,
+        ),
+    >,
+    mut colors_query: Query<&mut UiColor, Without<UiTarget>>,
+    mut ui_targets_res: ResMut<UiTargets>,
+    windows: Res<Windows>,
+    asset_server: Res<AssetServer>,
+    mut commands: Commands,
+) {
+    let hidden = Rect {
+        left: Val::Px(-100.),
+        bottom: Val::Px(-100.),
+        ..Default::default()
+    };
+    let (player_transform, player_rb_vel, player, player_faction, contacts) =
+        match player_query.get_single() {
+            Ok(player) => player,
+            Err(_) => {
+                for (_, ui_target) in ui_targets_res.targets.drain() {
+                    commands.entity(ui_target).despawn_recursive();
+                }
+                for (mut style, _) in reticle_query.iter_mut() {
+                    style.position = hidden;
+                }
+                return;
+            }
+        };
+    let player_faction = player_faction.copied().unwrap_or_default();
+    let player_velocity: Vec3 = player_rb_vel.linvel.into();
+    let (camera, camera_global_transform) = camera_query.single();
+    let window_size = match windows.get_primary() {
+        Some(window) => Vec2::new(window.width(), window.height()),
+        None => return,
+    };
+
+    let stale: Vec<Entity> = ui_targets_res
+        .targets
+        .keys()
+        .filter(|target| target_query.get(**target).is_err())
+        .copied()
+        .collect();
+    for target in stale {
+        if let Some(ui_target) = ui_targets_res.targets.remove(&target) {
+            commands.entity(ui_target).despawn_recursive();
         }
     }
-    while ui_targets_res.targets.len() < targets_to_draw.len() {
-        ui_targets_res
-            .targets
-            .push(spawn_target(&mut commands, &mut color_materials));
+
+    let mut reticle = None;
+    for (entity, faction, tag, wingman) in target_query.iter() {
+        let ui_target = match ui_targets_res.targets.get(&entity) {
+            Some(ui_target) => *ui_target,
+            None => {
+                let ui_target = spawn_target(
+                    &mut commands,
+                    asset_server.load("fonts/FiraMono-Medium.ttf"),
+                );
+                ui_targets_res.targets.insert(entity, ui_target);
+                continue;
+            }
+        };
+        let (mut ui_target_style, children) = match ui_targets.get_mut(ui_target) {
+            Ok(ui_target) => ui_target,
+            Err(_) => continue,
+        };
+        let track = match contacts.track(entity) {
+            Some(track) => track,
+            None => {
+                ui_target_style.position = hidden;
+                continue;
+            }
+        };
+
+        let target_iff = iff(player_faction, faction.copied().unwrap_or_default());
+        let color = target_iff.color();
+        let offset = track.position - player_transform.translation;
+        let distance = offset.length();
+        let closure = closure_rate(
+            player_transform.translation,
+            player_velocity,
+            track.position,
+            track.velocity,
+        );
+        let selected = player.target == Some(entity);
+        let locked = selected
+            && distance < LOCK_RANGE
+            && seeker_can_acquire(
+                &MissileParams::default().seeker,
+                player_transform.rotation * Vec3::X,
+                offset,
+            );
+
+        let (position, arrow) = match project_to_screen(
+            camera.projection_matrix,
+            camera_global_transform,
+            track.position,
+            window_size,
+            TARGET_ARROW_MARGIN,
+        ) {
+            ScreenPosition::OnScreen(position) => (position, None),
+            ScreenPosition::OffScreen {
+                position,
+                direction,
+            } => (position, Some(arrow_glyph(direction))),
+        };
+        ui_target_style.position = Rect {
+            left: Val::Px(position.x),
+            bottom: Val::Px(position.y),
+            ..Default::default()
+        };
+        if selected && arrow.is_none() {
+            reticle = Some((position, if locked { Color::RED } else { color }));
+        }
+
+        for child in children.iter() {
+            if let Ok((mut style, box_children)) = box_query.get_mut(*child) {
+                style.position = if arrow.is_none() {
+                    Rect::default()
+                } else {
+                    Rect {
+                        left: Val::Px(-10000.),
+                        ..Default::default()
+                    }
+                };
+                for line in box_children.iter() {
+                    if let Ok(mut ui_color) = colors_query.get_mut(*line) {
+                        ui_color.0 = color;
+                    }
+                }
+            }
+            if let Ok(mut text) = label_query.get_mut(*child) {
+                text.sections[0].value = format!(
+                    "{}{}\n{:.1}km {:+.0}",
+                    target_name(tag, wingman.is_some(), target_iff),
+                    if locked { " LOCK" } else { "" },
+                    distance / 1000.,
+                    closure * 3.6
+                );
+                text.sections[0].style.color = color;
+            }
+            if let Ok((mut style, mut text)) = arrow_query.get_mut(*child) {
+                style.position = if arrow.is_some() {
+                    Rect::default()
+                } else {
+                    Rect {
+                        left: Val::Px(-10000.),
+                        ..Default::default()
+                    }
+                };
+                text.sections[0].value = arrow.unwrap_or("").to_string();
+                text.sections[0].style.color = color;
+            }
+        }
     }
 
-    ui_targets_res.targets.iter().zip(targets_to_draw).for_each(
-        |(ui_target_entity, screen_coords)| {
-            if let Ok(mut ui_target) = ui_targets.get_mut(*ui_target_entity) {
-                ui_target.position = Rect {
-                    bottom: Val::Px(screen_coords.y),
-                    left: Val::Px(screen_coords.x),
+    for (mut style, children) in reticle_query.iter_mut() {
+        match reticle {
+            Some((position, color)) => {
+                style.position = Rect {
+                    left: Val::Px(position.x),
+                    bottom: Val::Px(position.y),
                     ..Default::default()
                 };
+                for child in children.iter() {
+                    if let Ok(mut ui_color) = colors_query.get_mut(*child) {
+                        ui_color.0 = color;
+                    }
+                }
             }
-        },
-    );
+            None => style.position = hidden,
+        }
+    }
 }
 
 pub fn radar(
-    target_query: Query<&Transform, With<Target>>,
-    player_query: Query<&Transform, With<Player>>,
+    settings: Res<RadarSettings>,
+    target_query: Query<Option<&Faction>, With<Target>>,
+    player_query: Query<(&Transform, Option<&Faction>, &Player, &SensorContacts)>,
     radar_query: Query<Entity, With<Radar>>,
-    mut dots_query: Query<&mut Style, With<RadarDot>>,
+    mut contacts_query: Query<(&mut Style, &mut Text), With<RadarContact>>,
+    mut sweep_query: Query<&mut Transform, (With<RadarSweep>, Without<Target>, Without<Player>)>,
+    mut label_query: Query<&mut Text, (With<RadarLabel>, Without<RadarContact>)>,
+    mut north_query: Query<
+        &mut Style,
+        (With<RadarNorth>, Without<RadarContact>, Without<RadarLabel>),
+    >,
     mut ui_targets_res: ResMut<UiTargets>,
+    asset_server: Res<AssetServer>,
+    time: Res<Time>,
     mut commands: Commands,
-    mut color_materials: ResMut<Assets<ColorMaterial>>,
 ) {
     let radar = radar_query.single();
-    let player_transform = player_query.single();
-    let player_pos = Vec3::new(
-        player_transform.translation.x,
-        0.,
-        player_transform.translation.z,
-    );
-
-    let mut current_vec = player_transform.rotation * Vec3::X;
-    current_vec.y = 0.;
-    current_vec = current_vec.normalize_or_zero();
-    let rotation = Quat::from_rotation_arc(current_vec, Vec3::Z);
-
-    let targets_to_draw: Vec<Vec3> = target_query
-        .iter()
-        .map(|target_transform| {
-            let target_pos = Vec3::new(
-                target_transform.translation.x,
-                0.,
-                target_transform.translation.z,
-            );
-            rotation * (target_pos - player_pos)
+    let sweep = sweep_angle(time.seconds_since_startup());
+    for mut transform in sweep_query.iter_mut() {
+        transform.rotation = Quat::from_rotation_z(-sweep);
+    }
+    for mut text in label_query.iter_mut() {
+        text.sections[0].value = settings.label();
+    }
+
+    let (player_transform, player_faction, player, contacts) = match player_query.get_single() {
+        Ok(player) => player,
+        Err(_) => return,
+    };
+    let player_faction = player_faction.copied().unwrap_or_default();
+    let heading = compass_heading(player_transform.rotation * Vec3::X);
+
+    let (north, _) = scope_position(NORTH, heading, settings.mode, 1.);
+    for mut style in north_query.iter_mut() {
+        style.position = scope_style_position(north * 0.9, 4.);
+    }
+
+    let contacts_to_draw: Vec<(Vec2, String, Color)> = contacts
+        .detected()
+        .filter_map(|(entity, track)| {
+            target_query
+                .get(entity)
+                .ok()
+                .map(|target_faction| (entity, track, target_faction))
+        })
+        .map(|(entity, track, target_faction)| {
+            let offset = track.position - player_transform.translation;
+            let (position, clamped) =
+                scope_position(offset, heading, settings.mode, settings.range());
+            let symbol = contact_symbol(offset.y);
+            let symbol = if player.target == Some(entity) {
+                format!("[{}]", symbol)
+            } else {
+                symbol.to_string()
+            };
+            let brightness = if clamped {
+                1. - RADAR_SWEEP_FADE
+            } else {
+                sweep_fade(sweep, position)
+            };
+            let mut color =
+                iff(player_faction, target_faction.copied().unwrap_or_default()).color();
+            color.set_a(brightness);
+            (position, symbol, color)
         })
-        .filter(|target_vec| target_vec.x.abs() < RADAR_RANGE && target_vec.z.abs() < RADAR_RANGE)
         .collect();
 
-    while ui_targets_res.radar_dots.len() > targets_to_draw.len() {
-        if let Some(target) = ui_targets_res.radar_dots.pop() {
-            commands.entity(target).despawn_recursive();
+    while ui_targets_res.radar_dots.len() > contacts_to_draw.len() {
+        if let Some(contact) = ui_targets_res.radar_dots.pop() {
+            commands.entity(contact).despawn_recursive();
         }
     }
-    while ui_targets_res.radar_dots.len() < targets_to_draw.len() {
-        ui_targets_res.radar_dots.push(spawn_radar_dot(
+    while ui_targets_res.radar_dots.len() < contacts_to_draw.len() {
+        ui_targets_res.radar_dots.push(spawn_radar_contact(
             &mut commands,
-            radar.clone(),
-            &mut color_materials,
+            radar,
+            asset_server.load("fonts/FiraMono-Medium.ttf"),
         ));
     }
 
     ui_targets_res
         .radar_dots
         .iter()
-        .zip(targets_to_draw)
-        .for_each(|(dot_entity, target_vec)| {
-            if let Ok(mut dot) = dots_query.get_mut(*dot_entity) {
-                dot.position = Rect {
-                    left: Val::Percent(50. + (target_vec.x) / RADAR_RANGE * 50.),
-                    bottom: Val::Percent(50. + (target_vec.z) / RADAR_RANGE * 50.),
+        .zip(contacts_to_draw)
+        .for_each(|(contact_entity, (position, symbol, color))| {
+            if let Ok((mut style, mut text)) = contacts_query.get_mut(*contact_entity) {
+                let glyph_offset = if symbol.len() > 3 { 10. } else { 4. };
+                style.position = scope_style_position(position, glyph_offset);
+                text.sections[0].value = symbol;
+                text.sections[0].style.color = color;
+            }
+        });
+}
+
+pub fn missile_warning_ui(
+    warning: Res<MissileWarning>,
+    player_query: Query<&Transform, With<Player>>,
+    missile_query: Query<&Transform>,
+    radar_query: Query<Entity, With<Radar>>,
+    mut text_query: Query<&mut Text, With<MissileWarningText>>,
+    mut styles_query: Query<&mut Style, Or<(With<RadarDot>, With<ThreatMarker>)>>,
+    mut ui_targets_res: ResMut<UiTargets>,
+    radar_settings: Res<RadarSettings>,
+    windows: Res<Windows>,
+    time: Res<Time>,
+    mut commands: Commands,
+    mut color_materials: ResMut<Assets<ColorMaterial>>,
+) {
+    let flash_on = time.seconds_since_startup().fract() < 0.5;
+    for mut text in text_query.iter_mut() {
+        text.sections[0].value = match warning.level {
+            WarningLevel::None => String::new(),
+            WarningLevel::Approach => "MISSILE".to_string(),
+            WarningLevel::Imminent if flash_on => "MISSILE - BREAK".to_string(),
+            WarningLevel::Imminent => String::new(),
+        };
+    }
+
+    let threat_count = warning.threats.len();
+    let radar = radar_query.single();
+    while ui_targets_res.threat_dots.len() > threat_count {
+        if let Some(dot) = ui_targets_res.threat_dots.pop() {
+            commands.entity(dot).despawn_recursive();
+        }
+    }
+    while ui_targets_res.threat_dots.len() < threat_count {
+        ui_targets_res.threat_dots.push(spawn_radar_dot(
+            &mut commands,
+            radar,
+            Color::RED,
+            &mut color_materials,
+        ));
+    }
+    while ui_targets_res.threat_markers.len() > threat_count {
+        if let Some(marker) = ui_targets_res.threat_markers.pop() {
+            commands.entity(marker).despawn_recursive();
+        }
+    }
+    while ui_targets_res.threat_markers.len() < threat_count {
+        ui_targets_res
+            .threat_markers
+            .push(spawn_threat_marker(&mut commands));
+    }
+
+    let player_transform = match player_query.iter().next() {
+        Some(player_transform) => player_transform,
+        None => return,
+    };
+    let heading = compass_heading(player_transform.rotation * Vec3::X);
+
+    for (dot_entity, threat) in ui_targets_res
+        .threat_dots
+        .iter()
+        .zip(warning.threats.iter())
+    {
+        if let (Ok(missile_transform), Ok(mut dot)) = (
+            missile_query.get(threat.missile),
+            styles_query.get_mut(*dot_entity),
+        ) {
+            let (position, _) = scope_position(
+                missile_transform.translation - player_transform.translation,
+                heading,
+                radar_settings.mode,
+                radar_settings.range(),
+            );
+            dot.position = scope_style_position(position, 2.5);
+        }
+    }
+
+    if let Some(window) = windows.get_primary() {
+        let center = Vec2::new(window.width() / 2., window.height() / 2.);
+        for (marker_entity, threat) in ui_targets_res
+            .threat_markers
+            .iter()
+            .zip(warning.threats.iter())
+        {
+            if let Ok(mut marker) = styles_query.get_mut(*marker_entity) {
+                let screen_coords = center
+                    + Vec2::new(threat.bearing.sin(), threat.bearing.cos()) * THREAT_MARKER_RADIUS;
+                marker.position = Rect {
+                    left: Val::Px(screen_coords.x - 6.),
+                    bottom: Val::Px(screen_coords.y - 6.),
                     ..Default::default()
+                };
+            }
+        }
+    }
+}
+
+pub fn navigation_ui(
+    navigation: Res<Navigation>,
+    radar_settings: Res<RadarSettings>,
+    player_query: Query<(&Transform, &RigidBodyVelocityComponent), With<Player>>,
+    camera_query: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    mut nav_text_query: Query<&mut Text, (With<NavText>, Without<WaypointArrow>)>,
+    mut marker_query: Query<
+        (&mut Style, &mut Text),
+        (
+            With<WaypointMarker>,
+            Without<NavText>,
+            Without<WaypointArrow>,
+        ),
+    >,
+    mut arrow_query: Query<(&mut Style, &mut Text), (With<WaypointArrow>, Without<NavText>)>,
+    mut radar_query: Query<
+        &mut Style,
+        (
+            With<RadarWaypoint>,
+            Without<WaypointMarker>,
+            Without<WaypointArrow>,
+        ),
+    >,
+    windows: Res<Windows>,
+) {
+    let hidden = Rect {
+        left: Val::Px(-100.),
+        bottom: Val::Px(-100.),
+        ..Default::default()
+    };
+    let mut nav_text = String::new();
+    let mut marker_position = hidden;
+    let mut marker_label = String::new();
+    let mut arrow_position = hidden;
+    let mut arrow_glyph_text = "";
+    let mut radar_position = Rect {
+        left: Val::Px(-10000.),
+        bottom: Val::Px(-10000.),
+        ..Default::default()
+    };
+
+    let player = player_query.iter().next();
+    if let (Some(waypoint), Some((player_transform, rb_vel))) =
+        (navigation.active_waypoint(), player)
+    {
+        let solution = navigate(
+            player_transform.translation,
+            player_transform.rotation,
+            rb_vel.linvel.into(),
+            waypoint.position,
+        );
+        nav_text = format!(
+            "WP{} {}\n{:.1} km {:03.0}\nETA {}",
+            navigation.active + 1,
+            waypoint.name,
+            solution.distance / 1000.,
+            solution.heading,
+            format_eta(solution.eta)
+        );
+
+        let (scope, _) = scope_position(
+            waypoint.position - player_transform.translation,
+            compass_heading(player_transform.rotation * Vec3::X),
+            radar_settings.mode,
+            radar_settings.range(),
+        );
+        radar_position = scope_style_position(scope, 3.5);
+
+        if let (Some((camera, camera_global_transform)), Some(window)) =
+            (camera_query.iter().next(), windows.get_primary())
+        {
+            let window_size = Vec2::new(window.width(), window.height());
+            match project_to_screen(
+                camera.projection_matrix,
+                camera_global_transform,
+                waypoint.position,
+                window_size,
+                WAYPOINT_ARROW_MARGIN,
+            ) {
+                ScreenPosition::OnScreen(screen_coords) => {
+                    marker_position = Rect {
+                        left: Val::Px(screen_coords.x),
+                        bottom: Val::Px(screen_coords.y),
+                        ..Default::default()
+                    };
+                    marker_label = format!("◇ {}", waypoint.name);
+                }
+                ScreenPosition::OffScreen {
+                    position,
+                    direction,
+                } => {
+                    arrow_position = Rect {
+                        left: Val::Px(position.x),
+                        bottom: Val::Px(position.y),
+                        ..Default::default()
+                    };
+                    arrow_glyph_text = arrow_glyph(direction);
                 }
             }
-        });
+        }
+    }
+
+    for mut text in nav_text_query.iter_mut() {
+        text.sections[0].value = nav_text.clone();
+    }
+    for (mut style, mut text) in marker_query.iter_mut() {
+        style.position = marker_position;
+        text.sections[0].value = marker_label.clone();
+    }
+    for (mut style, mut text) in arrow_query.iter_mut() {
+        style.position = arrow_position;
+        text.sections[0].value = arrow_glyph_text.to_string();
+    }
+    for mut style in radar_query.iter_mut() {
+        style.position = radar_position;
+    }
 }
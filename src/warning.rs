@@ -0,0 +1,322 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use super::guidance::*;
+use super::player::*;
+
+pub const IMMINENT_TIME_TO_IMPACT: f32 = 3.;
+pub const TONE_SAMPLE_RATE: u32 = 22050;
+pub const APPROACH_TONE_FREQUENCY: f32 = 900.;
+pub const IMMINENT_TONE_FREQUENCY: f32 = 1400.;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum WarningLevel {
+    None,
+    Approach,
+    Imminent,
+}
+
+impl Default for WarningLevel {
+    fn default() -> Self {
+        WarningLevel::None
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Threat {
+    pub missile: Entity,
+    /// Angle from the nose in the horizontal plane of the aircraft, positive to the right.
+    pub bearing: f32,
+    pub elevation: f32,
+    pub distance: f32,
+    pub closing_speed: f32,
+    pub time_to_impact: Option<f32>,
+}
+
+impl Threat {
+    /// Warning for this missile. One that isn't closing can't hit and raises no warning.
+    pub fn level(&self) -> WarningLevel {
+        match self.time_to_impact {
+            Some(time) if time < IMMINENT_TIME_TO_IMPACT => WarningLevel::Imminent,
+            Some(_) => WarningLevel::Approach,
+            None => WarningLevel::None,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct MissileWarning {
+    pub threats: Vec<Threat>,
+    pub level: WarningLevel,
+}
+
+pub struct WarningTone {
+    pub level: WarningLevel,
+}
+
+pub struct WarningSounds {
+    approach: Handle<AudioSource>,
+    imminent: Handle<AudioSource>,
+}
+
+/// Mono 16-bit WAV file of `beeps` sine beeps at `frequency` Hz, each `beep_seconds` long and
+/// followed by a gap of the same length.
+pub fn tone_wav(frequency: f32, beeps: u32, beep_seconds: f32) -> Vec<u8> {
+    let beep_samples = (beep_seconds * TONE_SAMPLE_RATE as f32) as u32;
+    let samples: Vec<i16> = (0..beeps * beep_samples * 2)
+        .map(|i| {
+            if (i / beep_samples) % 2 == 1 {
+                return 0;
+            }
+            let t = i as f32 / TONE_SAMPLE_RATE as f32;
+            ((t * frequency * std::f32::consts::TAU).sin() * 0.4 * i16::MAX as f32) as i16
+        })
+        .collect();
+
+    let data_size = samples.len() as u32 * 2;
+    let mut wav = Vec::with_capacity(44 + data_size as usize);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_size).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16_u32.to_le_bytes());
+    wav.extend_from_slice(&1_u16.to_le_bytes()); // PCM
+    wav.extend_from_slice(&1_u16.to_le_bytes()); // mono
+    wav.extend_from_slice(&TONE_SAMPLE_RATE.to_le_bytes());
+    wav.extend_from_slice(&(TONE_SAMPLE_RATE * 2).to_le_bytes());
+    wav.extend_from_slice(&2_u16.to_le_bytes());
+    wav.extend_from_slice(&16_u16.to_le_bytes());
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_size.to_le_bytes());
+    for sample in samples {
+        wav.extend_from_slice(&sample.to_le_bytes());
+    }
+    wav
+}
+
+pub fn setup_warning_tones(mut commands: Commands, mut audio_sources: ResMut<Assets<AudioSource>>) {
+    let mut tone = |frequency, beeps, beep_seconds| {
+        audio_sources.add(AudioSource {
+            bytes: tone_wav(frequency, beeps, beep_seconds).into(),
+        })
+    };
+    commands.insert_resource(WarningSounds {
+        approach: tone(APPROACH_TONE_FREQUENCY, 2, 0.25),
+        imminent: tone(IMMINENT_TONE_FREQUENCY, 6, 0.08),
+    });
+}
+
+pub fn play_warning_tone(
+    mut tone_events: EventReader<WarningTone>,
+    sounds: Option<Res<WarningSounds>>,
+    audio: Res<Audio>,
+) {
+    let sounds = match sounds {
+        Some(sounds) => sounds,
+        None => return,
+    };
+
+    for event in tone_events.iter() {
+        match event.level {
+            WarningLevel::None => {}
+            WarningLevel::Approach => {
+                audio.play(sounds.approach.clone());
+            }
+            WarningLevel::Imminent => {
+                audio.play(sounds.imminent.clone());
+            }
+        }
+    }
+}
+
+/// Geometry of an incoming missile as seen from the aircraft.
+pub fn assess_threat(
+    missile: Entity,
+    aircraft_position: Vec3,
+    aircraft_rotation: Quat,
+    aircraft_velocity: Vec3,
+    missile_position: Vec3,
+    missile_velocity: Vec3,
+) -> Threat {
+    let offset = missile_position - aircraft_position;
+    let distance = offset.length();
+    let local = aircraft_rotation.inverse() * offset;
+
+    let bearing = local.z.atan2(local.x);
+    let elevation = if distance > 0. {
+        (local.y / distance).clamp(-1., 1.).asin()
+    } else {
+        0.
+    };
+
+    let closing_speed = if distance > 0. {
+        (missile_velocity - aircraft_velocity).dot(-offset / distance)
+    } else {
+        0.
+    };
+    let time_to_impact = if closing_speed > 0. {
+        Some(distance / closing_speed)
+    } else {
+        None
+    };
+
+    Threat {
+        missile,
+        bearing,
+        elevation,
+        distance,
+        closing_speed,
+        time_to_impact,
+    }
+}
+
+pub fn missile_warning_system(
+    player_query: Query<(Entity, &Transform, &RigidBodyVelocityComponent), With<Player>>,
+    missile_query: Query<(Entity, &Missile, &Transform)>,
+    mut warning: ResMut<MissileWarning>,
+    mut tone_events: EventWriter<WarningTone>,
+) {
+    let previous_level = warning.level;
+    warning.threats.clear();
+
+    if let Some((player_entity, player_transform, rb_vel)) = player_query.iter().next() {
+        warning.threats = missile_query
+            .iter()
+            .filter(|(_, missile, _)| missile.target == Some(player_entity))
+            .map(|(missile_entity, missile, missile_transform)| {
+                assess_threat(
+                    missile_entity,
+                    player_transform.translation,
+                    player_transform.rotation,
+                    rb_vel.linvel.into(),
+                    missile_transform.translation,
+                    missile.velocity,
+                )
+            })
+            .filter(|threat| threat.level() != WarningLevel::None)
+            .collect();
+    }
+
+    warning.level = warning
+        .threats
+        .iter()
+        .map(|threat| threat.level())
+        .max()
+        .unwrap_or(WarningLevel::None);
+
+    if warning.level != previous_level {
+        tone_events.send(WarningTone {
+            level: warning.level,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::{FRAC_PI_2, FRAC_PI_4, PI};
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-4,
+            "expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    fn threat(missile_position: Vec3, missile_velocity: Vec3) -> Threat {
+        assess_threat(
+            Entity::from_raw(0),
+            Vec3::new(0., 1000., 0.),
+            Quat::IDENTITY,
+            Vec3::X * 200.,
+            Vec3::new(0., 1000., 0.) + missile_position,
+            missile_velocity,
+        )
+    }
+
+    #[test]
+    fn bearing_is_measured_from_nose_positive_right() {
+        assert_close(threat(Vec3::new(1000., 0., 0.), Vec3::ZERO).bearing, 0.);
+        assert_close(
+            threat(Vec3::new(1000., 0., 1000.), Vec3::ZERO).bearing,
+            FRAC_PI_4,
+        );
+        assert_close(
+            threat(Vec3::new(0., 0., -1000.), Vec3::ZERO).bearing,
+            -FRAC_PI_2,
+        );
+        assert_close(
+            threat(Vec3::new(-1000., 0., 0.), Vec3::ZERO).bearing.abs(),
+            PI,
+        );
+
+        // Bearing follows the aircraft's heading: after a left turn to face -Z, a missile
+        // there is dead ahead.
+        let turned = assess_threat(
+            Entity::from_raw(0),
+            Vec3::ZERO,
+            Quat::from_rotation_y(FRAC_PI_2),
+            Vec3::ZERO,
+            Vec3::new(0., 0., -1000.),
+            Vec3::ZERO,
+        );
+        assert_close(turned.bearing, 0.);
+    }
+
+    #[test]
+    fn elevation_is_angle_above_aircraft() {
+        assert_close(
+            threat(Vec3::new(1000., 1000., 0.), Vec3::ZERO).elevation,
+            FRAC_PI_4,
+        );
+        assert_close(
+            threat(Vec3::new(0., -500., 0.), Vec3::ZERO).elevation,
+            -FRAC_PI_2,
+        );
+        assert_close(threat(Vec3::new(0., 0., 800.), Vec3::ZERO).elevation, 0.);
+    }
+
+    #[test]
+    fn time_to_impact_uses_closing_speed() {
+        // Head on: the missile at 600 m/s and the aircraft at 200 m/s close at 800 m/s.
+        let head_on = threat(Vec3::new(4000., 0., 0.), Vec3::X * -600.);
+        assert_close(head_on.distance, 4000.);
+        assert_close(head_on.closing_speed, 800.);
+        assert_close(head_on.time_to_impact.unwrap(), 5.);
+
+        // From behind, only the difference in speed closes the gap.
+        let tail = threat(Vec3::new(-2000., 0., 0.), Vec3::X * 600.);
+        assert_close(tail.closing_speed, 400.);
+        assert_close(tail.time_to_impact.unwrap(), 5.);
+    }
+
+    #[test]
+    fn warning_turns_imminent_close_to_impact() {
+        let at_range = |range: f32| threat(Vec3::new(range, 0., 0.), Vec3::X * -600.).level();
+        assert_eq!(at_range(4000.), WarningLevel::Approach);
+        assert_eq!(
+            at_range(800. * IMMINENT_TIME_TO_IMPACT),
+            WarningLevel::Approach
+        );
+        assert_eq!(
+            at_range(800. * IMMINENT_TIME_TO_IMPACT - 1.),
+            WarningLevel::Imminent
+        );
+        assert_eq!(at_range(500.), WarningLevel::Imminent);
+    }
+
+    #[test]
+    fn opening_missile_raises_no_warning() {
+        // Chasing from behind but slower than the aircraft.
+        let slow = threat(Vec3::new(-1000., 0., 0.), Vec3::X * 150.);
+        assert!(slow.closing_speed < 0.);
+        assert_eq!(slow.time_to_impact, None);
+        assert_eq!(slow.level(), WarningLevel::None);
+
+        // Flying away after overshooting.
+        let overshot = threat(Vec3::new(500., 0., 0.), Vec3::X * 700.);
+        assert_eq!(overshot.time_to_impact, None);
+        assert_eq!(overshot.level(), WarningLevel::None);
+    }
+}
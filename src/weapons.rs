@@ -0,0 +1,203 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use super::faction::*;
+use super::guidance::*;
+use super::gun::*;
+use super::loadout::*;
+use super::player::*;
+
+pub const MISSILE_EJECT_SPEED: f32 = 20.;
+
+/// Request for `shooter` to fire one round of `weapon`, shared by the player and the AI.
+pub struct FireWeapon {
+    pub shooter: Entity,
+    pub weapon: WeaponType,
+    pub target: Option<Entity>,
+}
+
+pub struct WeaponFired {
+    pub shooter: Entity,
+    pub weapon: WeaponType,
+    pub target: Option<Entity>,
+    pub projectile: Entity,
+}
+
+#[derive(Default)]
+pub struct MissileAssets {
+    pub mesh: Handle<Mesh>,
+    pub material: Handle<StandardMaterial>,
+}
+
+pub fn setup_weapons(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    commands.insert_resource(MissileAssets {
+        mesh: meshes.add(Mesh::from(shape::Capsule {
+            radius: 0.03,
+            depth: 0.5,
+            ..Default::default()
+        })),
+        material: materials.add(StandardMaterial {
+            base_color: Color::GRAY,
+            ..Default::default()
+        }),
+    });
+}
+
+pub fn spawn_missile(
+    commands: &mut Commands,
+    missile_assets: &MissileAssets,
+    launch_transform: &Transform,
+    missile: Missile,
+) -> Entity {
+    commands
+        .spawn_bundle(PbrBundle {
+            mesh: missile_assets.mesh.clone(),
+            material: missile_assets.material.clone(),
+            transform: Transform {
+                translation: launch_transform.translation,
+                rotation: launch_transform.rotation
+                    * Quat::from_rotation_z(-std::f32::consts::FRAC_PI_2),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(missile)
+        .id()
+}
+
+pub fn spawn_bullet(
+    commands: &mut Commands,
+    gun_assets: &GunAssets,
+    source: Entity,
+    position: Vec3,
+    velocity: Vec3,
+) -> Entity {
+    commands
+        .spawn_bundle(PbrBundle {
+            mesh: gun_assets.mesh.clone(),
+            material: gun_assets.material.clone(),
+            transform: Transform {
+                translation: position,
+                rotation: Quat::from_rotation_arc(Vec3::Y, velocity.normalize()),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(Bullet {
+            source: Some(source),
+            velocity,
+            lifetime: BULLET_LIFETIME,
+        })
+        .id()
+}
+
+pub fn weapon_fire_system(
+    mut commands: Commands,
+    mut fire_events: EventReader<FireWeapon>,
+    mut fired_events: EventWriter<WeaponFired>,
+    missile_assets: Res<MissileAssets>,
+    gun_assets: Res<GunAssets>,
+    mut shooter_query: Query<(
+        &Transform,
+        &mut Loadout,
+        Option<&RigidBodyVelocityComponent>,
+        Option<&TargetVelocity>,
+        Option<&Faction>,
+    )>,
+    target_query: Query<(&Transform, Option<&Faction>)>,
+) {
+    for event in fire_events.iter() {
+        let (transform, mut loadout, rb_vel, target_velocity, faction) =
+            match shooter_query.get_mut(event.shooter) {
+                Ok(shooter) => shooter,
+                Err(_) => continue,
+            };
+        let faction = faction.copied().unwrap_or_default();
+
+        let (target, target_position) = match event
+            .target
+            .and_then(|target| target_query.get(target).ok().map(|result| (target, result)))
+        {
+            Some((target, (target_transform, target_faction)))
+                if can_lock(faction, target_faction.copied().unwrap_or_default()) =>
+            {
+                (Some(target), Some(target_transform.translation))
+            }
+            _ => (None, None),
+        };
+
+        let shooter_velocity = rb_vel
+            .map(|rb_vel| Vec3::from(rb_vel.linvel))
+            .or_else(|| target_velocity.map(|target_velocity| target_velocity.linvel))
+            .unwrap_or(Vec3::ZERO);
+        let forward = transform.rotation * Vec3::X;
+
+        let hardpoint = match loadout.take(event.weapon) {
+            Some(hardpoint) => hardpoint,
+            None => continue,
+        };
+        let launch_position = transform.translation + transform.rotation * hardpoint.offset;
+
+        let projectile = match event.weapon {
+            WeaponType::Missile => {
+                let params = MissileParams {
+                    guidance: hardpoint.guidance,
+                    seeker_kind: hardpoint.seeker,
+                    ..Default::default()
+                };
+                let target = target.filter(|_| {
+                    target_position.map_or(false, |target_position| {
+                        seeker_can_acquire(
+                            &params.seeker,
+                            forward,
+                            target_position - transform.translation,
+                        )
+                    })
+                });
+
+                spawn_missile(
+                    &mut commands,
+                    &missile_assets,
+                    &Transform {
+                        translation: launch_position,
+                        rotation: transform.rotation,
+                        ..Default::default()
+                    },
+                    Missile::new(
+                        Some(event.shooter),
+                        target,
+                        params,
+                        shooter_velocity + forward * MISSILE_EJECT_SPEED,
+                    ),
+                )
+            }
+            WeaponType::Gun => {
+                let dispersion = Vec3::new(
+                    0.,
+                    (rand::random::<f32>() - 0.5) * BULLET_DISPERSION,
+                    (rand::random::<f32>() - 0.5) * BULLET_DISPERSION,
+                );
+                let direction = (transform.rotation * (Vec3::X + dispersion)).normalize();
+
+                spawn_bullet(
+                    &mut commands,
+                    &gun_assets,
+                    event.shooter,
+                    launch_position,
+                    shooter_velocity + direction * MUZZLE_SPEED,
+                )
+            }
+        };
+
+        fired_events.send(WeaponFired {
+            shooter: event.shooter,
+            weapon: event.weapon,
+            target,
+            projectile,
+        });
+    }
+}
@@ -0,0 +1,413 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use super::ai::*;
+use super::crash::*;
+use super::faction::*;
+use super::input::*;
+use super::loadout::*;
+use super::player::*;
+use super::sensor::*;
+use super::weapons::*;
+
+pub const FORMATION_SPACING: f32 = 40.;
+pub const COVER_RANGE: f32 = 800.;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Formation {
+    Echelon,
+    LineAbreast,
+    Trail,
+}
+
+impl Formation {
+    pub fn next(&self) -> Formation {
+        match self {
+            Formation::Echelon => Formation::LineAbreast,
+            Formation::LineAbreast => Formation::Trail,
+            Formation::Trail => Formation::Echelon,
+        }
+    }
+}
+
+impl Default for Formation {
+    fn default() -> Self {
+        Formation::Echelon
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WingmanCommand {
+    Rejoin,
+    AttackTarget(Entity),
+    CoverMe,
+}
+
+#[derive(Component)]
+pub struct Wingman {
+    pub leader: Entity,
+    pub slot: usize,
+    pub command: WingmanCommand,
+}
+
+impl Wingman {
+    pub fn new(leader: Entity, slot: usize) -> Self {
+        Wingman {
+            leader,
+            slot,
+            command: WingmanCommand::Rejoin,
+        }
+    }
+}
+
+/// Command given by a leader to every wingman in their flight.
+pub struct WingmanOrder {
+    pub leader: Entity,
+    pub command: WingmanCommand,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct FormationGains {
+    /// Closure speed per metre of slot error.
+    pub position_gain: f32,
+    pub max_closure_speed: f32,
+    pub min_speed: f32,
+    pub max_speed: f32,
+}
+
+impl Default for FormationGains {
+    fn default() -> Self {
+        FormationGains {
+            position_gain: 0.5,
+            max_closure_speed: 80.,
+            min_speed: 60.,
+            max_speed: 320.,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct LeaderState {
+    pub position: Vec3,
+    pub rotation: Quat,
+    pub velocity: Vec3,
+}
+
+/// Slot position in the leader's frame (+X forward, +Y up, +Z right) for wingman `slot`.
+pub fn slot_offset(formation: Formation, slot: usize) -> Vec3 {
+    let rank = (slot + 1) as f32;
+    match formation {
+        Formation::Echelon => Vec3::new(-rank, 0., rank) * FORMATION_SPACING,
+        Formation::LineAbreast => {
+            let side = if slot % 2 == 0 { 1. } else { -1. };
+            let rank = (slot / 2 + 1) as f32;
+            Vec3::new(0., 0., side * rank) * FORMATION_SPACING
+        }
+        Formation::Trail => Vec3::new(-rank * 1.5, -rank * 0.1, 0.) * FORMATION_SPACING,
+    }
+}
+
+/// Direction and speed that bring a wingman at `position` onto its slot while matching the
+/// leader's velocity.
+pub fn formation_steering(
+    leader: &LeaderState,
+    offset: Vec3,
+    position: Vec3,
+    gains: &FormationGains,
+) -> (Vec3, f32) {
+    let slot_position = leader.position + leader.rotation * offset;
+    let closure = ((slot_position - position) * gains.position_gain)
+        .clamp_length_max(gains.max_closure_speed);
+    let desired_velocity = leader.velocity + closure;
+
+    let direction = if desired_velocity.length_squared() > 0. {
+        desired_velocity.normalize()
+    } else {
+        leader.rotation * Vec3::X
+    };
+    let speed = desired_velocity
+        .length()
+        .clamp(gains.min_speed, gains.max_speed);
+    (direction, speed)
+}
+
+pub fn wingman_commands(
+    keyboard_input: Res<Input<KeyCode>>,
+    key_bindings: Res<KeyBindings>,
+    button_inputs: Res<Input<GamepadButton>>,
+    mut formation: ResMut<Formation>,
+    mut order_events: EventWriter<WingmanOrder>,
+    player_query: Query<(Entity, &Player, &InputDevice)>,
+) {
+    if keyboard_input.just_pressed(key_bindings.cycle_formation) {
+        *formation = formation.next();
+    }
+
+    for (leader, player, device) in player_query.iter() {
+        let pressed =
+            |key, button| device.just_pressed(&keyboard_input, key, &button_inputs, button);
+        let command = if pressed(key_bindings.wingman_attack, GamepadButtonType::DPadLeft) {
+            player.target.map(WingmanCommand::AttackTarget)
+        } else if pressed(key_bindings.wingman_cover, GamepadButtonType::DPadRight) {
+            Some(WingmanCommand::CoverMe)
+        } else if pressed(key_bindings.wingman_rejoin, GamepadButtonType::DPadUp) {
+            Some(WingmanCommand::Rejoin)
+        } else {
+            None
+        };
+
+        if let Some(command) = command {
+            order_events.send(WingmanOrder { leader, command });
+        }
+    }
+}
+
+pub fn apply_wingman_orders(
+    mut order_events: EventReader<WingmanOrder>,
+    mut wingman_query: Query<&mut Wingman>,
+) {
+    for order in order_events.iter() {
+        for mut wingman in wingman_query.iter_mut() {
+            if wingman.leader == order.leader {
+                wingman.command = order.command;
+            }
+        }
+    }
+}
+
+pub fn wingman_ai(
+    difficulty: Res<Difficulty>,
+    formation: Res<Formation>,
+    mut fire_events: EventWriter<FireWeapon>,
+    mut wingman_query: Query<(
+        Entity,
+        &Transform,
+        &Faction,
+        &Loadout,
+        &SensorContacts,
+        &mut Wingman,
+        &mut DroneAi,
+        &mut DroneControl,
+    )>,
+    leader_query: Query<(&Transform, &RigidBodyVelocityComponent), Without<Crashed>>,
+    contact_query: Query<&Faction, Without<Wingman>>,
+    time: Res<Time>,
+) {
+    let gains = FormationGains::default();
+
+    for (entity, transform, faction, loadout, contacts, mut wingman, mut ai, mut control) in
+        wingman_query.iter_mut()
+    {
+        let leader = leader_query
+            .get(wingman.leader)
+            .ok()
+            .map(|(leader_transform, rb_vel)| LeaderState {
+                position: leader_transform.translation,
+                rotation: leader_transform.rotation,
+                velocity: rb_vel.linvel.into(),
+            });
+
+        let hostile = |target: Entity| {
+            contact_query.get(target).map_or(false, |contact_faction| {
+                is_hostile(*faction, *contact_faction)
+            })
+        };
+        let contact = |target: Entity| {
+            contacts
+                .track(target)
+                .filter(|_| hostile(target))
+                .map(|track| Contact {
+                    entity: target,
+                    position: track.position,
+                    velocity: track.velocity,
+                })
+        };
+
+        let target = match wingman.command {
+            WingmanCommand::Rejoin => None,
+            WingmanCommand::AttackTarget(target) => {
+                let target = contact(target);
+                if target.is_none() {
+                    wingman.command = WingmanCommand::Rejoin;
+                }
+                target
+            }
+            WingmanCommand::CoverMe => leader.and_then(|leader| {
+                contacts
+                    .detected()
+                    .filter(|(target, _)| hostile(*target))
+                    .map(|(target, track)| (target, (track.position - leader.position).length()))
+                    .filter(|(_, distance)| *distance < COVER_RANGE)
+                    .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
+                    .and_then(|(target, _)| contact(target))
+            }),
+        };
+
+        match (target, leader) {
+            (Some(target), _) => {
+                ai.target = Some(target.entity);
+                let (direction, weapons) = ai.engage(
+                    transform,
+                    loadout,
+                    &target,
+                    &difficulty,
+                    time.delta_seconds(),
+                );
+                control.desired_direction = Some(direction);
+                control.desired_speed = None;
+
+                for weapon in weapons {
+                    fire_events.send(FireWeapon {
+                        shooter: entity,
+                        weapon,
+                        target: Some(target.entity),
+                    });
+                }
+            }
+            (None, Some(leader)) => {
+                ai.target = None;
+                ai.lock_progress = 0.;
+                let (direction, speed) = formation_steering(
+                    &leader,
+                    slot_offset(*formation, wingman.slot),
+                    transform.translation,
+                    &gains,
+                );
+                control.desired_direction = Some(direction);
+                control.desired_speed = Some(speed);
+            }
+            (None, None) => {
+                ai.target = None;
+                control.desired_direction = None;
+                control.desired_speed = None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FORMATIONS: [Formation; 3] =
+        [Formation::Echelon, Formation::LineAbreast, Formation::Trail];
+    const STEP: f32 = 1. / 60.;
+
+    fn slot_position(leader: &LeaderState, offset: Vec3) -> Vec3 {
+        leader.position + leader.rotation * offset
+    }
+
+    /// Flies a wingman that turns and changes speed instantly along the steering command.
+    fn fly(
+        position: Vec3,
+        offset: Vec3,
+        leader_at: impl Fn(f32) -> LeaderState,
+        seconds: f32,
+    ) -> Vec<f32> {
+        let gains = FormationGains::default();
+        let mut position = position;
+        let mut errors = Vec::new();
+        let mut time = 0.;
+        while time < seconds {
+            let leader = leader_at(time);
+            let (direction, speed) = formation_steering(&leader, offset, position, &gains);
+            position += direction * speed * STEP;
+            time += STEP;
+            errors.push((slot_position(&leader_at(time), offset) - position).length());
+        }
+        errors
+    }
+
+    fn straight(time: f32) -> LeaderState {
+        LeaderState {
+            position: Vec3::new(200. * time, 1000., 0.),
+            rotation: Quat::IDENTITY,
+            velocity: Vec3::X * 200.,
+        }
+    }
+
+    #[test]
+    fn slots_are_distinct_and_clear_of_leader() {
+        for formation in FORMATIONS {
+            let slots: Vec<Vec3> = (0..4).map(|slot| slot_offset(formation, slot)).collect();
+            for (i, a) in slots.iter().enumerate() {
+                assert!(
+                    a.length() >= FORMATION_SPACING - 1e-3,
+                    "{:?} {}",
+                    formation,
+                    i
+                );
+                for b in &slots[i + 1..] {
+                    assert!(
+                        (*a - *b).length() >= FORMATION_SPACING - 1e-3,
+                        "{:?}",
+                        formation
+                    );
+                }
+            }
+        }
+        // Line abreast alternates sides of the leader.
+        assert!(slot_offset(Formation::LineAbreast, 0).z > 0.);
+        assert!(slot_offset(Formation::LineAbreast, 1).z < 0.);
+        // Trail stacks behind the leader.
+        assert!(slot_offset(Formation::Trail, 1).x < slot_offset(Formation::Trail, 0).x);
+    }
+
+    #[test]
+    fn wingman_converges_on_slot() {
+        for formation in FORMATIONS {
+            for slot in 0..4 {
+                let offset = slot_offset(formation, slot);
+                let start = slot_position(&straight(0.), offset) + Vec3::new(120., -30., 80.);
+                let errors = fly(start, offset, straight, 30.);
+                let error = *errors.last().unwrap();
+                assert!(
+                    error < 0.5,
+                    "{:?} slot {} is {} m off",
+                    formation,
+                    slot,
+                    error
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn slot_follows_leader_through_turn() {
+        // A level left turn at 200 m/s and 0.1 rad/s, starting at the origin heading +X.
+        let rate = 0.1;
+        let radius = 200. / rate;
+        let turning = |time: f32| {
+            let heading = rate * time;
+            LeaderState {
+                position: Vec3::new(heading.sin(), 0., heading.cos() - 1.) * radius,
+                rotation: Quat::from_rotation_y(heading),
+                velocity: Vec3::new(heading.cos(), 0., -heading.sin()) * 200.,
+            }
+        };
+
+        // After a quarter turn the leader heads north, so the echelon slot behind and to the
+        // right lies to the south east.
+        let quarter = std::f32::consts::FRAC_PI_2 / rate;
+        let leader = turning(quarter);
+        let slot = slot_position(&leader, slot_offset(Formation::Echelon, 0));
+        assert!((slot - leader.position - Vec3::new(40., 0., 40.)).length() < 1e-2);
+
+        for formation in FORMATIONS {
+            let offset = slot_offset(formation, 0);
+            let errors = fly(
+                slot_position(&turning(0.), offset),
+                offset,
+                turning,
+                quarter,
+            );
+            let worst = errors.iter().cloned().fold(0., f32::max);
+            assert!(
+                worst < FORMATION_SPACING / 2.,
+                "{:?} fell {} m out of its slot",
+                formation,
+                worst
+            );
+        }
+    }
+}